@@ -20,9 +20,11 @@ If not, see <https://www.gnu.org/licenses/>.
 #![allow(unused_variables)]
 
 mod model;
+mod parser;
+mod scheduler;
 mod state_machine;
 
-use std::{fmt, rc::Rc, str::FromStr, sync::Arc};
+use std::{fmt, path::PathBuf, rc::Rc, str::FromStr, sync::Arc};
 
 use clap::Parser;
 use teloxide::{
@@ -32,11 +34,14 @@ use teloxide::{
     utils::command::BotCommands,
 };
 
+use model::backend::Backend;
+use model::currency;
+use model::inmemory::backend::InMemoryBackend;
 use model::sqlite::backend::SqliteBackend;
 use model::sqlite::migrations;
-use state_machine::state::State;
+use state_machine::{currency::Admins, state::State, undo::UndoWindow};
 
-use crate::model::datamodel::{self, Datamodel};
+use crate::model::datamodel::{self, Datamodel, RemainderRule};
 
 #[derive(Debug, Clone, Copy)]
 enum DbBackend {
@@ -57,6 +62,24 @@ impl clap::ValueEnum for DbBackend {
     }
 }
 
+impl clap::ValueEnum for RemainderRule {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            RemainderRule::InOrder,
+            RemainderRule::Reverse,
+            RemainderRule::PayerFirst,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            RemainderRule::InOrder => Option::Some(clap::builder::PossibleValue::new("inorder")),
+            RemainderRule::Reverse => Option::Some(clap::builder::PossibleValue::new("reverse")),
+            RemainderRule::PayerFirst => Option::Some(clap::builder::PossibleValue::new("payer")),
+        }
+    }
+}
+
 // Telegram bot for sharing expenses and settling among friends
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -69,9 +92,30 @@ struct Cli {
     #[arg(short, long, value_enum)]
     backend: DbBackend,
 
-    // Connection string to use
+    // Connection string to use (Only for SQLite)
     #[arg(short, long)]
-    connection_string: String,
+    connection_string: Option<String>,
+
+    /// Who gets the leftover units when an amount can't be split exactly
+    #[arg(short, long, value_enum, default_value = "inorder")]
+    remainder_rule: RemainderRule,
+
+    /// Import exchange rates from a file before starting. Each line has the form
+    /// date,currency,base_currency,rate
+    #[arg(short, long)]
+    import_rates: Option<PathBuf>,
+
+    /// Minutes after a change during which /undo can still revert it
+    #[arg(long, default_value_t = 10)]
+    undo_window: u32,
+
+    /// Days deleted expenses are kept before they are removed for good
+    #[arg(long, default_value_t = 30)]
+    purge_after: u32,
+
+    /// Chat id allowed to run admin commands like /importrates, can be repeated
+    #[arg(long = "admin")]
+    admins: Vec<i64>,
 }
 
 #[tokio::main]
@@ -85,19 +129,77 @@ async fn main() {
 
     let bot = Bot::from_env();
 
-    log::info!("Migrating Database...");
-    let mut backend = SqliteBackend::new(args.connection_string.into(), args.parallel_readers);
-    log::info!("Migration complete.");
+    let mut backend = match args.backend {
+        DbBackend::Sqlite => {
+            let connection_string = args
+                .connection_string
+                .expect("Connection string is required for the sqlite backend");
+            Backend::Sqlite(SqliteBackend::new(
+                connection_string.into(),
+                args.parallel_readers,
+                args.remainder_rule,
+            ))
+        }
+        DbBackend::InMemory => {
+            log::warn!("Using in-memory backend. Data will be lost when the bot stops.");
+            Backend::InMemory(InMemoryBackend::new(args.remainder_rule))
+        }
+    };
 
+    log::info!("Migrating Database...");
     backend
         .migrate_database()
         .await
         .expect("Failed to migrate database");
+    log::info!("Migration complete.");
+
+    log::info!("Checking queries against the database...");
+    let failures = backend
+        .verify_database()
+        .await
+        .expect("Failed to check database queries");
+    if !failures.is_empty() {
+        for failure in &failures {
+            log::error!("{}", failure);
+        }
+        log::error!(
+            "Queries not matching the database schema: {}. Refusing to start.",
+            failures.len()
+        );
+        std::process::exit(1);
+    }
+    log::info!("Queries checked.");
+
+    if let Some(rates_file) = args.import_rates {
+        log::info!("Importing exchange rates...");
+        let contents =
+            std::fs::read_to_string(rates_file).expect("Failed to read exchange rate file");
+        let rates = currency::parse_rates(&contents).expect("Invalid exchange rate file");
+        let rate_count = rates.len();
+        for rate in rates {
+            backend
+                .add_currency_rate(rate)
+                .await
+                .expect("Failed to import exchange rate");
+        }
+        log::info!("Imported {} exchange rates.", rate_count);
+    }
+
+    let backend = Arc::new(backend);
+    tokio::spawn(scheduler::run(
+        bot.clone(),
+        backend.clone(),
+        chrono::Duration::days(args.purge_after as i64),
+    ));
+    let undo_window = UndoWindow(chrono::Duration::minutes(args.undo_window as i64));
+    let admins = Admins(args.admins.into_iter().map(ChatId).collect());
 
     Dispatcher::builder(bot, state_machine::schema())
         .dependencies(dptree::deps![
             InMemStorage::<State>::new(),
-            Arc::new(backend)
+            backend,
+            undo_window,
+            admins
         ])
         .enable_ctrlc_handler()
         .build()
@@ -0,0 +1,402 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use chrono::{NaiveDate, Utc};
+
+use super::datamodel::{
+    AuditAction, AuditEntity, AuditEntry, Budget, Category, CategoryKeyword, Expense, ExpensePayer,
+    Group, GroupId, GroupMembership, Money, RecurringExpense, Settlement, SplitValue, User, UserId,
+};
+
+/// A record whose changes are kept in the audit log.
+pub trait Audited {
+    const ENTITY: AuditEntity;
+
+    fn entity_id(&self) -> String;
+    fn group(&self) -> Option<GroupId>;
+    /// The fields of the record as `(name, value)` pairs.
+    fn fields(&self) -> Vec<(&'static str, String)>;
+
+    /// The record as text, one `name: value` line per field. Line breaks in
+    /// values are escaped so every field stays on its own line.
+    fn snapshot(&self) -> String {
+        self.fields()
+            .into_iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}: {}",
+                    name,
+                    value.replace('\\', "\\\\").replace('\n', "\\n")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// An entry for `actor` adding `record`.
+pub fn added<T: Audited>(actor: UserId, record: &T) -> AuditEntry {
+    entry(
+        actor,
+        record,
+        AuditAction::Add,
+        None,
+        Some(record.snapshot()),
+    )
+}
+
+/// An entry for `actor` changing `before` into `after`.
+pub fn updated<T: Audited>(actor: UserId, before: &T, after: &T) -> AuditEntry {
+    entry(
+        actor,
+        after,
+        AuditAction::Update,
+        Some(before.snapshot()),
+        Some(after.snapshot()),
+    )
+}
+
+/// An entry for `actor` removing `record`.
+pub fn deleted<T: Audited>(actor: UserId, record: &T) -> AuditEntry {
+    entry(
+        actor,
+        record,
+        AuditAction::Delete,
+        Some(record.snapshot()),
+        None,
+    )
+}
+
+fn entry<T: Audited>(
+    actor: UserId,
+    record: &T,
+    action: AuditAction,
+    before: Option<String>,
+    after: Option<String>,
+) -> AuditEntry {
+    AuditEntry {
+        id: None,
+        group: record.group(),
+        actor,
+        entity: T::ENTITY,
+        entity_id: record.entity_id(),
+        action,
+        before,
+        after,
+        created_at: Utc::now(),
+        reverts: None,
+    }
+}
+
+/// The value of the field `name` in a snapshot.
+pub fn snapshot_field(snapshot: &str, name: &str) -> Option<String> {
+    snapshot.lines().find_map(|line| {
+        let (field, value) = line.split_once(": ")?;
+        (field == name).then(|| unescape(value))
+    })
+}
+
+/// Reads back an expense from its snapshot. The creation time isn't part of
+/// the snapshot and is set to now.
+pub fn expense_from_snapshot(expense_id: u32, snapshot: &str) -> Option<Expense> {
+    let field = |name: &str| snapshot_field(snapshot, name);
+    let number = |name: &str| field(name).and_then(|value| value.parse::<u32>().ok());
+    let pairs = |name: &str| -> Option<Vec<(UserId, u32)>> {
+        field(name)?
+            .split(',')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (user_id, value) = pair.split_once('=')?;
+                Some((user_id.to_string(), value.parse().ok()?))
+            })
+            .collect()
+    };
+
+    let mut expense = Expense::new(
+        field("added_by")?,
+        number("group")?,
+        Money::new(field("amount")?.parse().ok()?, &field("currency")?),
+        field("title")?,
+        field("description")?,
+    );
+    expense.id = Some(expense_id);
+    expense.incurred_on = field("incurred_on")?.parse::<NaiveDate>().ok()?;
+    expense.category = number("category");
+    expense.split_type = number("split_type")?;
+    expense.participants = field("participants")?
+        .split(',')
+        .filter(|user_id| !user_id.is_empty())
+        .map(str::to_string)
+        .collect();
+    expense.split_values = pairs("split_values")?
+        .into_iter()
+        .map(|(user_id, value)| SplitValue::new(user_id, value))
+        .collect();
+    expense.paid_by = pairs("paid_by")?
+        .into_iter()
+        .map(|(user_id, amount)| ExpensePayer::new(user_id, amount))
+        .collect();
+    expense.recurring = number("recurring");
+    Some(expense)
+}
+
+/// Names of the fields that differ between two snapshots of a record.
+pub fn changed_fields(before: &str, after: &str) -> Vec<String> {
+    let mut changed: Vec<String> = Vec::new();
+    for line in after.lines() {
+        let name = line.split_once(": ").map_or(line, |(name, _)| name);
+        if !before.lines().any(|before_line| before_line == line) {
+            changed.push(name.to_string());
+        }
+    }
+    changed
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+impl Audited for User {
+    const ENTITY: AuditEntity = AuditEntity::User;
+
+    fn entity_id(&self) -> String {
+        self.user_id.clone()
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        None
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", self.name.clone()),
+            ("username", self.username.clone()),
+            ("utc_offset", self.utc_offset.to_string()),
+        ]
+    }
+}
+
+impl Audited for Group {
+    const ENTITY: AuditEntity = AuditEntity::Group;
+
+    fn entity_id(&self) -> String {
+        self.group_id.to_string()
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        Some(self.group_id)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("name", self.name.clone()),
+            ("description", self.description.clone()),
+            ("created_by", self.created_by.clone()),
+            ("base_currency", self.base_currency.clone()),
+            ("simplify_debts", self.simplify_debts.to_string()),
+        ]
+    }
+}
+
+impl Audited for GroupMembership {
+    const ENTITY: AuditEntity = AuditEntity::Membership;
+
+    fn entity_id(&self) -> String {
+        self.user_id.clone()
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        Some(self.group_id)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("user_id", self.user_id.clone()),
+            ("group", self.group_id.to_string()),
+        ]
+    }
+}
+
+impl Audited for Expense {
+    const ENTITY: AuditEntity = AuditEntity::Expense;
+
+    fn entity_id(&self) -> String {
+        optional(self.id)
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        Some(self.group)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let pairs = |pairs: Vec<(&UserId, u32)>| {
+            pairs
+                .into_iter()
+                .map(|(user_id, value)| format!("{}={}", user_id, value))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        vec![
+            ("group", self.group.to_string()),
+            ("added_by", self.added_by.clone()),
+            ("title", self.title.clone()),
+            ("description", self.description.clone()),
+            ("amount", self.amount.minor_units.to_string()),
+            ("currency", self.amount.currency.clone()),
+            ("incurred_on", self.incurred_on.to_string()),
+            ("category", optional(self.category)),
+            ("split_type", self.split_type.to_string()),
+            ("participants", self.participants.join(",")),
+            (
+                "split_values",
+                pairs(
+                    self.split_values
+                        .iter()
+                        .map(|split_value| (&split_value.user_id, split_value.value))
+                        .collect(),
+                ),
+            ),
+            (
+                "paid_by",
+                pairs(
+                    self.paid_by
+                        .iter()
+                        .map(|payer| (&payer.user_id, payer.amount))
+                        .collect(),
+                ),
+            ),
+            ("recurring", optional(self.recurring)),
+        ]
+    }
+}
+
+impl Audited for Settlement {
+    const ENTITY: AuditEntity = AuditEntity::Settlement;
+
+    fn entity_id(&self) -> String {
+        optional(self.id)
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        Some(self.group)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("payer", self.payer.clone()),
+            ("payee", self.payee.clone()),
+            ("amount", self.amount.to_string()),
+            ("settled_at", self.settled_at.to_rfc3339()),
+        ]
+    }
+}
+
+impl Audited for Category {
+    const ENTITY: AuditEntity = AuditEntity::Category;
+
+    fn entity_id(&self) -> String {
+        optional(self.id)
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        self.group
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![("name", self.name.clone())]
+    }
+}
+
+impl Audited for CategoryKeyword {
+    const ENTITY: AuditEntity = AuditEntity::CategoryKeyword;
+
+    fn entity_id(&self) -> String {
+        self.keyword.clone()
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        self.group
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("keyword", self.keyword.clone()),
+            ("category", self.category.to_string()),
+        ]
+    }
+}
+
+impl Audited for RecurringExpense {
+    const ENTITY: AuditEntity = AuditEntity::RecurringExpense;
+
+    fn entity_id(&self) -> String {
+        optional(self.id)
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        Some(self.template.group)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("schedule", self.schedule.to_string()),
+            ("start_date", self.start_date.to_string()),
+            ("active", self.active.to_string()),
+        ];
+        fields.extend(
+            self.template
+                .fields()
+                .into_iter()
+                .filter(|(name, _)| !matches!(*name, "incurred_on" | "recurring")),
+        );
+        fields
+    }
+}
+
+impl Audited for Budget {
+    const ENTITY: AuditEntity = AuditEntity::Budget;
+
+    fn entity_id(&self) -> String {
+        optional(self.id)
+    }
+
+    fn group(&self) -> Option<GroupId> {
+        Some(self.group)
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("category", optional(self.category)),
+            ("amount", self.amount.to_string()),
+        ]
+    }
+}
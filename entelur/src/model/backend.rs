@@ -0,0 +1,403 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::{DateTime, Utc};
+
+use crate::model::{
+    datamodel::{
+        AuditEntry, Budget, Category, CategoryId, CategoryKeyword, CurrencyRate, Datamodel,
+        Expense, Group, GroupId, GroupMembership, RecurringExpense, RecurringExpenseId,
+        RemainderRule, Settlement, User, UserExpenses, UserId,
+    },
+    inmemory::backend::InMemoryBackend,
+    sqlite::{backend::SqliteBackend, verify::QueryFailure},
+    DataError,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The backend selected on the command line. The bot talks to this type so the
+/// handlers don't need to know which storage is in use.
+pub enum Backend {
+    Sqlite(SqliteBackend),
+    InMemory(InMemoryBackend),
+}
+
+impl Backend {
+    pub async fn migrate_database(&mut self) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.migrate_database().await,
+            Backend::InMemory(_) => Ok(()),
+        }
+    }
+
+    pub async fn verify_database(&self) -> Result<Vec<QueryFailure>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.verify_queries().await,
+            Backend::InMemory(_) => Ok(Vec::new()),
+        }
+    }
+
+    pub fn remainder_rule(&self) -> RemainderRule {
+        match self {
+            Backend::Sqlite(backend) => backend.remainder_rule(),
+            Backend::InMemory(backend) => backend.remainder_rule(),
+        }
+    }
+}
+
+impl Datamodel for Backend {
+    async fn add_user(&self, user: User) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_user(user).await,
+            Backend::InMemory(backend) => backend.add_user(user).await,
+        }
+    }
+
+    async fn add_group(&self, group: Group) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_group(group).await,
+            Backend::InMemory(backend) => backend.add_group(group).await,
+        }
+    }
+
+    async fn add_user_to_group(
+        &self,
+        group_id: GroupId,
+        user_id: UserId,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_user_to_group(group_id, user_id, actor).await,
+            Backend::InMemory(backend) => backend.add_user_to_group(group_id, user_id, actor).await,
+        }
+    }
+
+    async fn add_expense(&self, expense: Expense) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_expense(expense).await,
+            Backend::InMemory(backend) => backend.add_expense(expense).await,
+        }
+    }
+
+    async fn update_expense(&self, expense: Expense, actor: UserId) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.update_expense(expense, actor).await,
+            Backend::InMemory(backend) => backend.update_expense(expense, actor).await,
+        }
+    }
+
+    async fn get_user(&self, user_id: UserId) -> Result<User, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_user(user_id).await,
+            Backend::InMemory(backend) => backend.get_user(user_id).await,
+        }
+    }
+
+    async fn get_group(&self, group_id: GroupId) -> Result<Group, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_group(group_id).await,
+            Backend::InMemory(backend) => backend.get_group(group_id).await,
+        }
+    }
+
+    async fn get_group_members(&self, group_id: GroupId) -> Result<Vec<User>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_group_members(group_id).await,
+            Backend::InMemory(backend) => backend.get_group_members(group_id).await,
+        }
+    }
+
+    async fn get_expenses(&self, group_id: GroupId) -> Result<Vec<Expense>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_expenses(group_id).await,
+            Backend::InMemory(backend) => backend.get_expenses(group_id).await,
+        }
+    }
+
+    async fn remove_user_from_group(
+        &self,
+        group_id: GroupId,
+        user_id: UserId,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => {
+                backend
+                    .remove_user_from_group(group_id, user_id, actor)
+                    .await
+            }
+            Backend::InMemory(backend) => {
+                backend
+                    .remove_user_from_group(group_id, user_id, actor)
+                    .await
+            }
+        }
+    }
+
+    async fn delete_group(&self, group_id: GroupId, actor: UserId) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.delete_group(group_id, actor).await,
+            Backend::InMemory(backend) => backend.delete_group(group_id, actor).await,
+        }
+    }
+
+    async fn delete_user(&self, user_id: UserId) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.delete_user(user_id).await,
+            Backend::InMemory(backend) => backend.delete_user(user_id).await,
+        }
+    }
+
+    async fn delete_expense(&self, expense_id: u32, actor: UserId) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.delete_expense(expense_id, actor).await,
+            Backend::InMemory(backend) => backend.delete_expense(expense_id, actor).await,
+        }
+    }
+
+    async fn purge_deleted_expenses(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.purge_deleted_expenses(deleted_before).await,
+            Backend::InMemory(backend) => backend.purge_deleted_expenses(deleted_before).await,
+        }
+    }
+
+    async fn get_membership(&self, user_id: UserId) -> Result<Vec<GroupMembership>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_membership(user_id).await,
+            Backend::InMemory(backend) => backend.get_membership(user_id).await,
+        }
+    }
+
+    async fn get_user_expenses(&self, user_id: UserId) -> Result<Vec<Expense>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_user_expenses(user_id).await,
+            Backend::InMemory(backend) => backend.get_user_expenses(user_id).await,
+        }
+    }
+
+    async fn get_expense_splits(&self, group_id: GroupId) -> Result<Vec<UserExpenses>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_expense_splits(group_id).await,
+            Backend::InMemory(backend) => backend.get_expense_splits(group_id).await,
+        }
+    }
+
+    async fn add_settlement(&self, settlement: Settlement, actor: UserId) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_settlement(settlement, actor).await,
+            Backend::InMemory(backend) => backend.add_settlement(settlement, actor).await,
+        }
+    }
+
+    async fn get_settlements(&self, group_id: GroupId) -> Result<Vec<Settlement>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_settlements(group_id).await,
+            Backend::InMemory(backend) => backend.get_settlements(group_id).await,
+        }
+    }
+
+    async fn set_group_simplify_debts(
+        &self,
+        group_id: GroupId,
+        simplify: bool,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => {
+                backend
+                    .set_group_simplify_debts(group_id, simplify, actor)
+                    .await
+            }
+            Backend::InMemory(backend) => {
+                backend
+                    .set_group_simplify_debts(group_id, simplify, actor)
+                    .await
+            }
+        }
+    }
+
+    async fn set_user_utc_offset(&self, user_id: UserId, utc_offset: i32) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.set_user_utc_offset(user_id, utc_offset).await,
+            Backend::InMemory(backend) => backend.set_user_utc_offset(user_id, utc_offset).await,
+        }
+    }
+
+    async fn add_currency_rate(&self, rate: CurrencyRate) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_currency_rate(rate).await,
+            Backend::InMemory(backend) => backend.add_currency_rate(rate).await,
+        }
+    }
+
+    async fn get_currency_rates(
+        &self,
+        base_currency: String,
+    ) -> Result<Vec<CurrencyRate>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_currency_rates(base_currency).await,
+            Backend::InMemory(backend) => backend.get_currency_rates(base_currency).await,
+        }
+    }
+
+    async fn add_category(
+        &self,
+        category: Category,
+        actor: UserId,
+    ) -> Result<CategoryId, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_category(category, actor).await,
+            Backend::InMemory(backend) => backend.add_category(category, actor).await,
+        }
+    }
+
+    async fn get_categories(&self, group_id: GroupId) -> Result<Vec<Category>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_categories(group_id).await,
+            Backend::InMemory(backend) => backend.get_categories(group_id).await,
+        }
+    }
+
+    async fn add_category_keyword(
+        &self,
+        keyword: CategoryKeyword,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_category_keyword(keyword, actor).await,
+            Backend::InMemory(backend) => backend.add_category_keyword(keyword, actor).await,
+        }
+    }
+
+    async fn get_category_keywords(
+        &self,
+        group_id: GroupId,
+    ) -> Result<Vec<CategoryKeyword>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_category_keywords(group_id).await,
+            Backend::InMemory(backend) => backend.get_category_keywords(group_id).await,
+        }
+    }
+
+    async fn add_recurring_expense(
+        &self,
+        recurring: RecurringExpense,
+    ) -> Result<RecurringExpenseId, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.add_recurring_expense(recurring).await,
+            Backend::InMemory(backend) => backend.add_recurring_expense(recurring).await,
+        }
+    }
+
+    async fn get_recurring_expenses(&self) -> Result<Vec<RecurringExpense>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_recurring_expenses().await,
+            Backend::InMemory(backend) => backend.get_recurring_expenses().await,
+        }
+    }
+
+    async fn set_recurring_occurrences(
+        &self,
+        recurring_id: RecurringExpenseId,
+        occurrences: u32,
+    ) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => {
+                backend
+                    .set_recurring_occurrences(recurring_id, occurrences)
+                    .await
+            }
+            Backend::InMemory(backend) => {
+                backend
+                    .set_recurring_occurrences(recurring_id, occurrences)
+                    .await
+            }
+        }
+    }
+
+    async fn cancel_recurring_expense(
+        &self,
+        recurring_id: RecurringExpenseId,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.cancel_recurring_expense(recurring_id, actor).await,
+            Backend::InMemory(backend) => {
+                backend.cancel_recurring_expense(recurring_id, actor).await
+            }
+        }
+    }
+
+    async fn set_budget(&self, budget: Budget, actor: UserId) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.set_budget(budget, actor).await,
+            Backend::InMemory(backend) => backend.set_budget(budget, actor).await,
+        }
+    }
+
+    async fn get_budgets(&self, group_id: GroupId) -> Result<Vec<Budget>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_budgets(group_id).await,
+            Backend::InMemory(backend) => backend.get_budgets(group_id).await,
+        }
+    }
+
+    async fn remove_budget(
+        &self,
+        group_id: GroupId,
+        category: Option<CategoryId>,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.remove_budget(group_id, category, actor).await,
+            Backend::InMemory(backend) => backend.remove_budget(group_id, category, actor).await,
+        }
+    }
+
+    async fn get_audit_log(
+        &self,
+        group_id: GroupId,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_audit_log(group_id, limit).await,
+            Backend::InMemory(backend) => backend.get_audit_log(group_id, limit).await,
+        }
+    }
+
+    async fn get_last_undoable_change(
+        &self,
+        actor: UserId,
+    ) -> Result<Option<AuditEntry>, DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.get_last_undoable_change(actor).await,
+            Backend::InMemory(backend) => backend.get_last_undoable_change(actor).await,
+        }
+    }
+
+    async fn undo_change(&self, entry: AuditEntry, actor: UserId) -> Result<(), DataError> {
+        match self {
+            Backend::Sqlite(backend) => backend.undo_change(entry, actor).await,
+            Backend::InMemory(backend) => backend.undo_change(entry, actor).await,
+        }
+    }
+}
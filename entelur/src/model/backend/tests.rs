@@ -0,0 +1,567 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Behaviour every `Datamodel` implementation must share, run against each
+//! backend.
+
+use chrono::Utc;
+
+use super::Backend;
+use crate::model::{
+    balance::compute_balances,
+    datamodel::{
+        AuditAction, AuditEntity, AuditEntry, Datamodel, Expense, ExpensePayer, Group, Money,
+        RecurringExpense, RemainderRule, Schedule, Settlement, User, UserId,
+    },
+    inmemory::backend::InMemoryBackend,
+    sqlite::{backend::SqliteBackend, testing::TempDatabase},
+    DataError,
+};
+
+/// A backend with users "1", "2" and "3" in group 1, created by "1". The
+/// database file of the sqlite backend lives as long as the fixture.
+struct Fixture {
+    backend: Backend,
+    _database: Option<TempDatabase>,
+}
+
+impl Fixture {
+    async fn in_memory() -> Self {
+        let backend = Backend::InMemory(InMemoryBackend::new(RemainderRule::default()));
+        Fixture::with_group(backend, None).await
+    }
+
+    async fn sqlite(name: &str) -> Self {
+        let database = TempDatabase::new(name);
+        let mut backend = Backend::Sqlite(SqliteBackend::new(
+            database.path(),
+            1,
+            RemainderRule::default(),
+        ));
+        backend.migrate_database().await.unwrap();
+        Fixture::with_group(backend, Some(database)).await
+    }
+
+    async fn with_group(backend: Backend, database: Option<TempDatabase>) -> Self {
+        for user_id in ["1", "2", "3"] {
+            backend
+                .add_user(User::new(user_id.into(), user_id.into(), user_id.into()))
+                .await
+                .unwrap();
+        }
+        backend
+            .add_group(Group::new(0, "flat".into(), "".into(), "1".into()))
+            .await
+            .unwrap();
+        for user_id in ["2", "3"] {
+            backend
+                .add_user_to_group(1, user_id.into(), "1".into())
+                .await
+                .unwrap();
+        }
+        Fixture {
+            backend,
+            _database: database,
+        }
+    }
+}
+
+/// Runs every listed test against the in-memory and the sqlite backend.
+macro_rules! backend_tests {
+    ($($name:ident),* $(,)?) => {
+        mod inmemory {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    let fixture = super::Fixture::in_memory().await;
+                    super::$name(&fixture.backend).await;
+                }
+            )*
+        }
+
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    let fixture = super::Fixture::sqlite(stringify!($name)).await;
+                    super::$name(&fixture.backend).await;
+                }
+            )*
+        }
+    };
+}
+
+backend_tests!(
+    users_and_groups_round_trip,
+    unregistered_users_are_rejected,
+    adding_a_member_twice_keeps_one_membership,
+    expenses_and_splits_round_trip,
+    users_with_history_are_kept,
+    deleted_groups_keep_their_expenses_until_purged,
+    settlements_count_towards_balances,
+    invalid_settlements_are_rejected,
+    undo_restores_a_deleted_expense,
+    undo_reverts_an_edit_unless_changed_since,
+    purged_expenses_cant_be_restored,
+    deleted_occurrences_are_not_added_again,
+    edits_recalculate_splits_and_are_audited,
+    edits_only_split_among_current_members,
+    every_change_writes_one_audit_entry,
+    undone_changes_are_linked_and_skipped,
+);
+
+fn pizza(amount: i64) -> Expense {
+    Expense::new(
+        "1".into(),
+        1,
+        Money::new(amount, "INR"),
+        "Pizza".into(),
+        "".into(),
+    )
+}
+
+async fn users_and_groups_round_trip(backend: &Backend) {
+    assert_eq!(backend.get_user("2".into()).await.unwrap().name, "2");
+    assert_eq!(backend.get_group(1).await.unwrap().name, "flat");
+    let members: Vec<UserId> = backend
+        .get_group_members(1)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|user| user.user_id)
+        .collect();
+    assert_eq!(members, vec!["1", "2", "3"]);
+    assert!(backend.get_group(2).await.is_err());
+}
+
+async fn unregistered_users_are_rejected(backend: &Backend) {
+    assert!(matches!(
+        backend
+            .add_group(Group::new(0, "trip".into(), "".into(), "4".into()))
+            .await,
+        Err(DataError::UnknownUser)
+    ));
+    assert!(matches!(
+        backend.add_user_to_group(1, "4".into(), "1".into()).await,
+        Err(DataError::UnknownUser)
+    ));
+    assert!(backend.get_group(2).await.is_err());
+}
+
+async fn adding_a_member_twice_keeps_one_membership(backend: &Backend) {
+    backend
+        .add_user_to_group(1, "2".into(), "1".into())
+        .await
+        .unwrap();
+    assert_eq!(backend.get_group_members(1).await.unwrap().len(), 3);
+    assert_eq!(backend.get_membership("2".into()).await.unwrap().len(), 1);
+
+    backend.add_expense(pizza(300)).await.unwrap();
+    let shares: Vec<u32> = backend
+        .get_expense_splits(1)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|split| split.split)
+        .collect();
+    assert_eq!(shares, vec![100, 100, 100]);
+}
+
+async fn expenses_and_splits_round_trip(backend: &Backend) {
+    let mut rent = pizza(1000);
+    rent.title = "Rent".into();
+    backend.add_expense(rent).await.unwrap();
+
+    let expenses = backend.get_expenses(1).await.unwrap();
+    assert_eq!(expenses.len(), 1);
+    assert_eq!(expenses[0].id, Some(1));
+    assert_eq!(expenses[0].amount, Money::new(1000, "INR"));
+    assert_eq!(expenses[0].title, "Rent");
+
+    let splits = backend.get_expense_splits(1).await.unwrap();
+    let total: u32 = splits.iter().map(|split| split.split).sum();
+    assert_eq!(total, 1000);
+    assert_eq!(splits.len(), 3);
+    assert!(splits.iter().all(|split| split.expenses_id == 1));
+    assert_eq!(
+        backend.get_user_expenses("3".into()).await.unwrap().len(),
+        1
+    );
+}
+
+async fn users_with_history_are_kept(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    assert!(matches!(
+        backend.delete_user("2".into()).await,
+        Err(DataError::UserHasHistory)
+    ));
+    assert!(backend.get_user("2".into()).await.is_ok());
+
+    backend
+        .add_user(User::new("4".into(), "4".into(), "4".into()))
+        .await
+        .unwrap();
+    backend
+        .add_user_to_group(1, "4".into(), "1".into())
+        .await
+        .unwrap();
+    backend.delete_user("4".into()).await.unwrap();
+    assert!(backend.get_user("4".into()).await.is_err());
+    assert_eq!(backend.get_group_members(1).await.unwrap().len(), 3);
+}
+
+async fn deleted_groups_keep_their_expenses_until_purged(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    backend.delete_group(1, "1".into()).await.unwrap();
+    assert!(backend.get_group(1).await.is_err());
+    assert!(backend.get_expenses(1).await.unwrap().is_empty());
+    assert!(backend.get_membership("2".into()).await.unwrap().is_empty());
+
+    let day = chrono::Duration::days(1);
+    assert_eq!(
+        backend
+            .purge_deleted_expenses(Utc::now() - day)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        backend
+            .purge_deleted_expenses(Utc::now() + day)
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+async fn settlements_count_towards_balances(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    backend
+        .add_settlement(Settlement::new(1, "2".into(), "1".into(), 100), "2".into())
+        .await
+        .unwrap();
+
+    let group = backend.get_group(1).await.unwrap();
+    let settlements = backend.get_settlements(1).await.unwrap();
+    assert_eq!(settlements.len(), 1);
+    assert_eq!(settlements[0].id, Some(1));
+    let balances = compute_balances(
+        &group,
+        &backend.get_expenses(1).await.unwrap(),
+        &backend.get_expense_splits(1).await.unwrap(),
+        &settlements,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(balances["1"], 100);
+    assert_eq!(balances["2"], 0);
+    assert_eq!(balances["3"], -100);
+}
+
+async fn invalid_settlements_are_rejected(backend: &Backend) {
+    backend
+        .add_user(User::new("4".into(), "4".into(), "4".into()))
+        .await
+        .unwrap();
+    let settle = |payer: &str, payee: &str, amount| {
+        backend.add_settlement(
+            Settlement::new(1, payer.into(), payee.into(), amount),
+            payer.into(),
+        )
+    };
+    assert!(matches!(
+        settle("2", "1", 0).await,
+        Err(DataError::InvalidAmount)
+    ));
+    assert!(matches!(
+        settle("2", "2", 100).await,
+        Err(DataError::LogicalError)
+    ));
+    assert!(matches!(
+        settle("4", "1", 100).await,
+        Err(DataError::LogicalError)
+    ));
+    assert!(matches!(
+        settle("2", "4", 100).await,
+        Err(DataError::LogicalError)
+    ));
+    backend.delete_group(1, "1".into()).await.unwrap();
+    assert!(settle("2", "1", 100).await.is_err());
+    assert!(backend.get_settlements(1).await.unwrap().is_empty());
+}
+
+async fn undo_restores_a_deleted_expense(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    backend.delete_expense(1, "1".into()).await.unwrap();
+    assert!(backend.get_expenses(1).await.unwrap().is_empty());
+
+    let entry = backend
+        .get_last_undoable_change("1".into())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.action, AuditAction::Delete);
+    backend.undo_change(entry, "1".into()).await.unwrap();
+
+    let expenses = backend.get_expenses(1).await.unwrap();
+    assert_eq!(expenses.len(), 1);
+    assert_eq!(expenses[0].amount, Money::new(300, "INR"));
+    assert_eq!(backend.get_expense_splits(1).await.unwrap().len(), 3);
+    // The delete was undone, what is left to undo is adding the expense
+    let entry = backend
+        .get_last_undoable_change("1".into())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.action, AuditAction::Add);
+}
+
+async fn undo_reverts_an_edit_unless_changed_since(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    let mut expense = backend.get_expenses(1).await.unwrap().remove(0);
+    expense.title = "Pasta".into();
+    backend
+        .update_expense(expense.clone(), "1".into())
+        .await
+        .unwrap();
+    let edit = backend
+        .get_last_undoable_change("1".into())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edit.action, AuditAction::Update);
+
+    expense.title = "Salad".into();
+    backend.update_expense(expense, "2".into()).await.unwrap();
+    assert!(matches!(
+        backend.undo_change(edit.clone(), "1".into()).await,
+        Err(DataError::ConflictingChange)
+    ));
+
+    let latest = backend
+        .get_last_undoable_change("2".into())
+        .await
+        .unwrap()
+        .unwrap();
+    backend.undo_change(latest, "2".into()).await.unwrap();
+    backend.undo_change(edit, "1".into()).await.unwrap();
+    assert_eq!(backend.get_expenses(1).await.unwrap()[0].title, "Pizza");
+}
+
+async fn purged_expenses_cant_be_restored(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    backend.delete_expense(1, "1".into()).await.unwrap();
+    let entry = backend
+        .get_last_undoable_change("1".into())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let day = chrono::Duration::days(1);
+    assert_eq!(
+        backend
+            .purge_deleted_expenses(Utc::now() - day)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(
+        backend
+            .purge_deleted_expenses(Utc::now() + day)
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(backend.undo_change(entry, "1".into()).await.is_err());
+    assert!(backend.get_expenses(1).await.unwrap().is_empty());
+    assert!(backend.get_expense_splits(1).await.unwrap().is_empty());
+}
+
+async fn deleted_occurrences_are_not_added_again(backend: &Backend) {
+    let recurring_id = backend
+        .add_recurring_expense(RecurringExpense::new(
+            pizza(300),
+            Schedule::Daily,
+            Utc::now().date_naive(),
+        ))
+        .await
+        .unwrap();
+    let recurring = backend.get_recurring_expenses().await.unwrap().remove(0);
+    assert_eq!(recurring.id, Some(recurring_id));
+    let occurrence = recurring.next_expense().unwrap();
+
+    backend.add_expense(occurrence.clone()).await.unwrap();
+    assert!(matches!(
+        backend.add_expense(occurrence.clone()).await,
+        Err(DataError::DuplicateExpense)
+    ));
+    backend.delete_expense(1, "1".into()).await.unwrap();
+    assert!(matches!(
+        backend.add_expense(occurrence).await,
+        Err(DataError::DuplicateExpense)
+    ));
+}
+
+async fn edits_recalculate_splits_and_are_audited(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    let mut expense = backend.get_expenses(1).await.unwrap().remove(0);
+    expense.amount = Money::new(600, "INR");
+    expense.paid_by = vec![ExpensePayer::new("1".into(), 600)];
+    expense.participants = vec!["1".into(), "2".into()];
+    backend.update_expense(expense, "2".into()).await.unwrap();
+
+    assert_eq!(
+        backend.get_expenses(1).await.unwrap()[0].amount,
+        Money::new(600, "INR")
+    );
+    let shares: Vec<(UserId, u32)> = backend
+        .get_expense_splits(1)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|split| (split.user_id, split.split))
+        .collect();
+    assert_eq!(shares, vec![("1".into(), 300), ("2".into(), 300)]);
+
+    let entry = backend.get_audit_log(1, 1).await.unwrap().remove(0);
+    assert_eq!(entry.action, AuditAction::Update);
+    assert_eq!(entry.actor, "2");
+    assert_eq!(entry.entity_id, "1");
+    assert!(entry.before.unwrap().contains("300"));
+    assert!(entry.after.unwrap().contains("600"));
+}
+
+async fn edits_only_split_among_current_members(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    let mut expense = backend.get_expenses(1).await.unwrap().remove(0);
+    backend
+        .remove_user_from_group(1, "3".into(), "1".into())
+        .await
+        .unwrap();
+
+    expense.participants = vec!["1".into(), "3".into()];
+    assert!(matches!(
+        backend.update_expense(expense.clone(), "1".into()).await,
+        Err(DataError::LogicalError)
+    ));
+    expense.participants = Vec::new();
+    backend.update_expense(expense, "1".into()).await.unwrap();
+    assert_eq!(backend.get_expense_splits(1).await.unwrap().len(), 2);
+}
+
+/// The audit log of group 1, oldest first.
+async fn audit_log(backend: &Backend) -> Vec<AuditEntry> {
+    let mut log = backend.get_audit_log(1, 100).await.unwrap();
+    log.reverse();
+    log
+}
+
+/// Checks that exactly one entry was added to the log since it had `count`
+/// entries, and returns it.
+async fn single_new_entry(backend: &Backend, count: usize) -> AuditEntry {
+    let mut log = audit_log(backend).await;
+    assert_eq!(log.len(), count + 1);
+    log.pop().unwrap()
+}
+
+async fn every_change_writes_one_audit_entry(backend: &Backend) {
+    backend
+        .add_user(User::new("4".into(), "4".into(), "4".into()))
+        .await
+        .unwrap();
+    let count = audit_log(backend).await.len();
+
+    backend
+        .add_user_to_group(1, "4".into(), "1".into())
+        .await
+        .unwrap();
+    let entry = single_new_entry(backend, count).await;
+    assert_eq!(
+        (entry.entity, entry.action, entry.actor.as_str()),
+        (AuditEntity::Membership, AuditAction::Add, "1")
+    );
+    assert!(entry.before.is_none() && entry.after.is_some());
+
+    backend
+        .remove_user_from_group(1, "4".into(), "1".into())
+        .await
+        .unwrap();
+    let entry = single_new_entry(backend, count + 1).await;
+    assert_eq!(
+        (entry.entity, entry.action),
+        (AuditEntity::Membership, AuditAction::Delete)
+    );
+    assert!(entry.before.is_some() && entry.after.is_none());
+
+    backend.add_expense(pizza(300)).await.unwrap();
+    let entry = single_new_entry(backend, count + 2).await;
+    assert_eq!(
+        (entry.entity, entry.action, entry.entity_id.as_str()),
+        (AuditEntity::Expense, AuditAction::Add, "1")
+    );
+    assert!(entry.before.is_none());
+    assert!(entry.after.unwrap().contains("title: Pizza"));
+
+    backend.delete_expense(1, "2".into()).await.unwrap();
+    let entry = single_new_entry(backend, count + 3).await;
+    assert_eq!(
+        (entry.entity, entry.action, entry.actor.as_str()),
+        (AuditEntity::Expense, AuditAction::Delete, "2")
+    );
+    assert!(entry.before.unwrap().contains("title: Pizza"));
+    assert!(entry.after.is_none());
+
+    backend.delete_group(1, "1".into()).await.unwrap();
+    let entry = single_new_entry(backend, count + 4).await;
+    assert_eq!(
+        (entry.entity, entry.action),
+        (AuditEntity::Group, AuditAction::Delete)
+    );
+    assert!(entry.before.unwrap().contains("name: flat"));
+    assert!(entry.after.is_none());
+}
+
+async fn undone_changes_are_linked_and_skipped(backend: &Backend) {
+    backend.add_expense(pizza(300)).await.unwrap();
+    let added = audit_log(backend).await.pop().unwrap();
+    backend.delete_expense(1, "1".into()).await.unwrap();
+    let deleted = audit_log(backend).await.pop().unwrap();
+
+    backend
+        .undo_change(deleted.clone(), "1".into())
+        .await
+        .unwrap();
+    let undo = audit_log(backend).await.pop().unwrap();
+    assert_eq!(undo.reverts, deleted.id);
+    assert_eq!(undo.action, AuditAction::Add);
+    assert!(added.reverts.is_none());
+
+    // Neither the undone delete nor the undo itself can be undone
+    let last = backend
+        .get_last_undoable_change("1".into())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(last.id, added.id);
+    assert!(matches!(
+        backend.undo_change(deleted, "1".into()).await,
+        Err(DataError::ConflictingChange)
+    ));
+    assert!(matches!(
+        backend.undo_change(undo, "1".into()).await,
+        Err(DataError::ConflictingChange)
+    ));
+}
@@ -0,0 +1,357 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+
+use super::{
+    currency::{convert, find_rate},
+    datamodel::{
+        CurrencyRate, Expense, Group, RemainderRule, Settlement, SplitValue, Transfer, User,
+        UserExpenses, UserId,
+    },
+    split::distribute_by_weight,
+    DataError,
+};
+
+/// Computes the net position of every user in the group, in minor units of the
+/// group's base currency. A positive balance means the user is owed money and a
+/// negative balance means the user owes money.
+pub fn compute_balances(
+    group: &Group,
+    expenses: &[Expense],
+    splits: &[UserExpenses],
+    settlements: &[Settlement],
+    rates: &[CurrencyRate],
+) -> Result<BTreeMap<UserId, i64>, DataError> {
+    let mut balances: BTreeMap<UserId, i64> = BTreeMap::new();
+    for expense in expenses {
+        let converted = convert_expense(group, expense, splits, rates)?;
+        for (user_id, paid) in converted.paid {
+            *balances.entry(user_id).or_insert(0) += paid;
+        }
+        for (user_id, share) in converted.owed {
+            *balances.entry(user_id).or_insert(0) -= share;
+        }
+    }
+    for settlement in settlements {
+        *balances.entry(settlement.payer.clone()).or_insert(0) += settlement.amount as i64;
+        *balances.entry(settlement.payee.clone()).or_insert(0) -= settlement.amount as i64;
+    }
+    Ok(balances)
+}
+
+/// A settlement is a payment of a positive amount between two different members
+/// of the group.
+pub fn validate_settlement(settlement: &Settlement, users: &[User]) -> Result<(), DataError> {
+    if settlement.amount == 0 {
+        return Err(DataError::InvalidAmount);
+    }
+    let is_member = |user_id: &UserId| users.iter().any(|user| &user.user_id == user_id);
+    if settlement.payer == settlement.payee
+        || !is_member(&settlement.payer)
+        || !is_member(&settlement.payee)
+    {
+        return Err(DataError::LogicalError);
+    }
+    Ok(())
+}
+
+/// Computes who owes whom without simplifying, every participant owes the payers
+/// of each expense their share. Debts between the same two users are netted.
+pub fn compute_pairwise_debts(
+    group: &Group,
+    expenses: &[Expense],
+    splits: &[UserExpenses],
+    settlements: &[Settlement],
+    rates: &[CurrencyRate],
+) -> Result<Vec<Transfer>, DataError> {
+    let mut debts: BTreeMap<(UserId, UserId), i64> = BTreeMap::new();
+    for expense in expenses {
+        let converted = convert_expense(group, expense, splits, rates)?;
+        let payers = payer_weights(expense);
+        // With several payers each participant owes every payer in proportion
+        // to what the payer contributed.
+        for (user_id, share) in converted.owed {
+            for (payer, part) in spread(share, &payers) {
+                add_debt(&mut debts, user_id.clone(), payer, part);
+            }
+        }
+    }
+    // A settlement works like an expense paid by the payer and owed in full by the payee
+    for settlement in settlements {
+        add_debt(
+            &mut debts,
+            settlement.payee.clone(),
+            settlement.payer.clone(),
+            settlement.amount as i64,
+        );
+    }
+
+    Ok(debts
+        .into_iter()
+        .filter(|(_, amount)| *amount != 0)
+        .map(|((first, second), amount)| {
+            if amount > 0 {
+                Transfer::new(first, second, amount)
+            } else {
+                Transfer::new(second, first, -amount)
+            }
+        })
+        .collect())
+}
+
+/// Records that `debtor` owes `creditor`. Only one entry is kept per pair, it is
+/// positive when the first user of the key owes the second.
+fn add_debt(
+    debts: &mut BTreeMap<(UserId, UserId), i64>,
+    debtor: UserId,
+    creditor: UserId,
+    amount: i64,
+) {
+    if debtor == creditor {
+        return;
+    }
+    if debtor < creditor {
+        *debts.entry((debtor, creditor)).or_insert(0) += amount;
+    } else {
+        *debts.entry((creditor, debtor)).or_insert(0) -= amount;
+    }
+}
+
+/// An expense converted into the group's base currency.
+pub(super) struct ConvertedExpense {
+    /// What each payer contributed.
+    pub(super) paid: Vec<(UserId, i64)>,
+    /// What each participant owes.
+    pub(super) owed: Vec<(UserId, i64)>,
+}
+
+/// Converts the payers and shares of an expense into the group's base currency.
+/// Both the converted contributions and the converted shares always add up to
+/// the converted total.
+pub(super) fn convert_expense(
+    group: &Group,
+    expense: &Expense,
+    splits: &[UserExpenses],
+    rates: &[CurrencyRate],
+) -> Result<ConvertedExpense, DataError> {
+    let Some(expense_id) = expense.id else {
+        return Ok(ConvertedExpense {
+            paid: Vec::new(),
+            owed: Vec::new(),
+        });
+    };
+    let payers = payer_weights(expense);
+    let expense_splits: Vec<SplitValue> = splits
+        .iter()
+        .filter(|split| split.expenses_id == expense_id)
+        .map(|split| SplitValue::new(split.user_id.clone(), split.split))
+        .collect();
+
+    let total = base_amount(group, expense, rates)?;
+    Ok(ConvertedExpense {
+        paid: spread(total, &payers),
+        owed: spread(total, &expense_splits),
+    })
+}
+
+/// The amount of the expense in the group's base currency, converted with the
+/// rate of the day it was incurred on.
+pub(super) fn base_amount(
+    group: &Group,
+    expense: &Expense,
+    rates: &[CurrencyRate],
+) -> Result<i64, DataError> {
+    if expense.amount.currency == group.base_currency {
+        return Ok(expense.amount.minor_units);
+    }
+    let rate = find_rate(
+        rates,
+        &expense.amount.currency,
+        &group.base_currency,
+        expense.incurred_on,
+    )
+    .ok_or(DataError::MissingCurrencyRate)?;
+    Ok(convert(expense.amount.minor_units, rate))
+}
+
+fn payer_weights(expense: &Expense) -> Vec<SplitValue> {
+    expense
+        .payers()
+        .into_iter()
+        .map(|payer| SplitValue::new(payer.user_id, payer.amount))
+        .collect()
+}
+
+/// Spreads `amount` over the users proportionally to their weights.
+fn spread(amount: i64, weights: &[SplitValue]) -> Vec<(UserId, i64)> {
+    let Some(first) = weights.first() else {
+        return Vec::new();
+    };
+    distribute_by_weight(
+        amount as u64,
+        weights,
+        RemainderRule::InOrder,
+        &first.user_id,
+    )
+    .into_iter()
+    .map(|(user_id, share)| (user_id, share as i64))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::model::datamodel::{ExpensePayer, Money};
+
+    fn group() -> Group {
+        Group::new(1, "flat".into(), "".into(), "1".into())
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, 14).unwrap()
+    }
+
+    /// Expense `id` of `amount`, paid by the given users.
+    fn expense(id: u32, amount: Money, payers: &[(&str, u32)]) -> Expense {
+        let mut expense = Expense::new("1".into(), 1, amount, "".into(), "".into());
+        expense.id = Some(id);
+        expense.incurred_on = date();
+        expense.paid_by = payers
+            .iter()
+            .map(|(user_id, amount)| ExpensePayer::new(user_id.to_string(), *amount))
+            .collect();
+        expense
+    }
+
+    /// The shares of expense `id`.
+    fn shares(id: u32, shares: &[(&str, u32)]) -> Vec<UserExpenses> {
+        shares
+            .iter()
+            .map(|(user_id, split)| UserExpenses {
+                user_id: user_id.to_string(),
+                expenses_id: id,
+                split: *split,
+            })
+            .collect()
+    }
+
+    fn transfers(transfers: &[Transfer]) -> Vec<(&str, &str, i64)> {
+        transfers
+            .iter()
+            .map(|transfer| {
+                (
+                    transfer.from.as_str(),
+                    transfer.to.as_str(),
+                    transfer.amount,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn balances_add_up_to_zero() {
+        let expenses = [
+            expense(1, Money::new(300, "INR"), &[("1", 300)]),
+            expense(2, Money::new(1000, "INR"), &[("2", 1000)]),
+        ];
+        let mut splits = shares(1, &[("1", 100), ("2", 100), ("3", 100)]);
+        splits.extend(shares(2, &[("1", 500), ("2", 300), ("3", 200)]));
+
+        let balances = compute_balances(&group(), &expenses, &splits, &[], &[]).unwrap();
+        assert_eq!(balances["1"], -300);
+        assert_eq!(balances["2"], 600);
+        assert_eq!(balances["3"], -300);
+        assert_eq!(balances.values().sum::<i64>(), 0);
+    }
+
+    #[test]
+    fn participants_owe_every_payer_in_proportion() {
+        let expenses = [expense(
+            1,
+            Money::new(900, "INR"),
+            &[("1", 600), ("2", 300)],
+        )];
+        let splits = shares(1, &[("1", 300), ("2", 300), ("3", 300)]);
+
+        let balances = compute_balances(&group(), &expenses, &splits, &[], &[]).unwrap();
+        assert_eq!(balances["1"], 300);
+        assert_eq!(balances["2"], 0);
+        assert_eq!(balances["3"], -300);
+
+        let debts = compute_pairwise_debts(&group(), &expenses, &splits, &[], &[]).unwrap();
+        assert_eq!(
+            transfers(&debts),
+            vec![("2", "1", 100), ("3", "1", 200), ("3", "2", 100)]
+        );
+    }
+
+    #[test]
+    fn settlements_pay_off_debts() {
+        let expenses = [expense(1, Money::new(300, "INR"), &[("1", 300)])];
+        let splits = shares(1, &[("1", 100), ("2", 100), ("3", 100)]);
+        let settlements = [Settlement::new(1, "2".into(), "1".into(), 100)];
+
+        let balances = compute_balances(&group(), &expenses, &splits, &settlements, &[]).unwrap();
+        assert_eq!(balances["1"], 100);
+        assert_eq!(balances["2"], 0);
+        assert_eq!(balances["3"], -100);
+
+        let debts =
+            compute_pairwise_debts(&group(), &expenses, &splits, &settlements, &[]).unwrap();
+        assert_eq!(transfers(&debts), vec![("3", "1", 100)]);
+    }
+
+    #[test]
+    fn converted_expenses_keep_every_minor_unit() {
+        // 10.01 EUR at 90.25 is 903.40 INR
+        let rates = [CurrencyRate {
+            currency: "EUR".into(),
+            base_currency: "INR".into(),
+            rate_date: date(),
+            rate: 90_250_000,
+        }];
+        let expense = expense(1, Money::new(1001, "EUR"), &[("1", 500), ("2", 501)]);
+        let splits = shares(1, &[("1", 334), ("2", 334), ("3", 333)]);
+
+        let converted = convert_expense(&group(), &expense, &splits, &rates).unwrap();
+        let paid: Vec<i64> = converted.paid.iter().map(|(_, amount)| *amount).collect();
+        let owed: Vec<i64> = converted.owed.iter().map(|(_, amount)| *amount).collect();
+        assert_eq!(paid.iter().sum::<i64>(), 90340);
+        assert_eq!(owed.iter().sum::<i64>(), 90340);
+        assert_eq!(paid, vec![45125, 45215]);
+        assert_eq!(owed, vec![30144, 30143, 30053]);
+
+        let balances = compute_balances(
+            &group(),
+            std::slice::from_ref(&expense),
+            &splits,
+            &[],
+            &rates,
+        )
+        .unwrap();
+        assert_eq!(balances.values().sum::<i64>(), 0);
+
+        let mut earlier = expense;
+        earlier.incurred_on = date().pred_opt().unwrap();
+        assert!(matches!(
+            compute_balances(&group(), &[earlier], &splits, &[], &rates),
+            Err(DataError::MissingCurrencyRate)
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use chrono::{Datelike, NaiveDate};
+
+use super::{
+    balance::base_amount,
+    datamodel::{Budget, CategoryId, CurrencyRate, Expense, Group},
+    DataError,
+};
+
+/// Shares of a budget, in percent, at which the group is warned.
+pub const BUDGET_THRESHOLDS: [u32; 2] = [80, 100];
+
+/// A budget threshold crossed by an expense.
+#[derive(Debug, Clone)]
+pub struct BudgetAlert {
+    pub budget: Budget,
+    /// The threshold crossed, in percent of the budget.
+    pub threshold: u32,
+    /// Spent in the month so far, in minor units of the group's base currency.
+    pub spent: i64,
+}
+
+/// What the group spent in the month `month` falls in, in minor units of its
+/// base currency. When `category` is set only the expenses filed under it
+/// count.
+pub fn month_spend(
+    group: &Group,
+    expenses: &[Expense],
+    rates: &[CurrencyRate],
+    month: NaiveDate,
+    category: Option<CategoryId>,
+) -> Result<i64, DataError> {
+    let mut spent = 0;
+    for expense in expenses {
+        if same_month(expense.incurred_on, month) && counts_for(expense, category) {
+            spent += base_amount(group, expense, rates)?;
+        }
+    }
+    Ok(spent)
+}
+
+/// The thresholds of `budgets` crossed by adding `expense`, at most one per
+/// budget. `expenses` are the group's stored expenses and must already include
+/// `expense`. When `expense` is an edit of `previous`, only the difference
+/// between the two counts as new spending.
+pub fn budget_alerts(
+    group: &Group,
+    budgets: &[Budget],
+    expenses: &[Expense],
+    rates: &[CurrencyRate],
+    expense: &Expense,
+    previous: Option<&Expense>,
+) -> Result<Vec<BudgetAlert>, DataError> {
+    let amount = base_amount(group, expense, rates)?;
+    let mut alerts: Vec<BudgetAlert> = Vec::new();
+    for budget in budgets {
+        if budget.amount == 0 || !counts_for(expense, budget.category) {
+            continue;
+        }
+        let spent = month_spend(group, expenses, rates, expense.incurred_on, budget.category)?;
+        let replaced = match previous {
+            Some(previous)
+                if same_month(previous.incurred_on, expense.incurred_on)
+                    && counts_for(previous, budget.category) =>
+            {
+                base_amount(group, previous, rates)?
+            }
+            _ => 0,
+        };
+        let amount = amount - replaced;
+        let limit = budget.amount as i64;
+        let crossed = BUDGET_THRESHOLDS.iter().rev().find(|threshold| {
+            let threshold = **threshold as i64;
+            (spent - amount) * 100 < limit * threshold && spent * 100 >= limit * threshold
+        });
+        if let Some(threshold) = crossed {
+            alerts.push(BudgetAlert {
+                budget: budget.clone(),
+                threshold: *threshold,
+                spent,
+            });
+        }
+    }
+    Ok(alerts)
+}
+
+fn same_month(date: NaiveDate, month: NaiveDate) -> bool {
+    date.year() == month.year() && date.month() == month.month()
+}
+
+fn counts_for(expense: &Expense, category: Option<CategoryId>) -> bool {
+    category.is_none() || expense.category == category
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::datamodel::Money;
+
+    fn expense(amount: i64) -> Expense {
+        let amount = Money::new(amount, "INR");
+        Expense::new("1".into(), 1, amount, "test".into(), "".into())
+    }
+
+    /// Thresholds crossed by `expense` when it is the group's only expense.
+    fn alerts(expense: &Expense, previous: Option<&Expense>) -> Vec<u32> {
+        let group = Group::new(1, "flat".into(), "".into(), "1".into());
+        let budgets = [Budget::new(1, None, 1000)];
+        let stored = std::slice::from_ref(expense);
+        budget_alerts(&group, &budgets, stored, &[], expense, previous)
+            .unwrap()
+            .into_iter()
+            .map(|alert| alert.threshold)
+            .collect()
+    }
+
+    #[test]
+    fn adding_an_expense_crosses_thresholds() {
+        let added = expense(850);
+        assert_eq!(alerts(&added, None), vec![80]);
+        let added = expense(1200);
+        assert_eq!(alerts(&added, None), vec![100]);
+    }
+
+    #[test]
+    fn editing_an_expense_only_counts_the_difference() {
+        let (before, after) = (expense(850), expense(900));
+        assert!(alerts(&after, Some(&before)).is_empty());
+        let (before, after) = (expense(500), expense(1000));
+        assert_eq!(alerts(&after, Some(&before)), vec![100]);
+    }
+}
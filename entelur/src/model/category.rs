@@ -0,0 +1,93 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::datamodel::{CategoryId, CategoryKeyword};
+
+/// Categories every group starts with and the keywords that pick them. The
+/// first sqlite migration that creates the categories seeds the same list.
+pub const DEFAULT_CATEGORIES: [(&str, &[&str]); 8] = [
+    (
+        "Food",
+        &[
+            "food",
+            "lunch",
+            "dinner",
+            "breakfast",
+            "pizza",
+            "restaurant",
+            "cafe",
+            "coffee",
+            "swiggy",
+            "zomato",
+        ],
+    ),
+    (
+        "Groceries",
+        &["groceries", "grocery", "vegetables", "milk", "supermarket"],
+    ),
+    (
+        "Travel",
+        &[
+            "travel", "uber", "ola", "taxi", "cab", "flight", "train", "bus", "fuel", "petrol",
+        ],
+    ),
+    ("Rent", &["rent", "lease", "deposit"]),
+    (
+        "Utilities",
+        &[
+            "electricity",
+            "water",
+            "gas",
+            "internet",
+            "wifi",
+            "broadband",
+            "phone",
+            "recharge",
+        ],
+    ),
+    (
+        "Entertainment",
+        &["movie", "movies", "concert", "netflix", "tickets", "party"],
+    ),
+    ("Shopping", &["shopping", "clothes", "amazon", "flipkart"]),
+    ("Other", &[]),
+];
+
+/// Finds the category for an expense title. A keyword matches when it is one
+/// of the words of the title, ignoring case. Keywords of the group win over the
+/// shared ones, after that the longest keyword wins.
+pub fn match_category(title: &str, keywords: &[CategoryKeyword]) -> Option<CategoryId> {
+    let title = title.to_lowercase();
+    let words: Vec<&str> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    keywords
+        .iter()
+        .filter(|keyword| {
+            let keyword = keyword.keyword.to_lowercase();
+            let keyword_words: Vec<&str> = keyword
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .collect();
+            !keyword_words.is_empty()
+                && words
+                    .windows(keyword_words.len())
+                    .any(|window| window == keyword_words.as_slice())
+        })
+        .max_by_key(|keyword| (keyword.group.is_some(), keyword.keyword.len()))
+        .map(|keyword| keyword.category)
+}
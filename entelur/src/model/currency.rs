@@ -0,0 +1,223 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::NaiveDate;
+
+use super::datamodel::{currency_exponent, CurrencyRate, RATE_SCALE};
+
+/// ISO 4217 codes of the currencies in use, sorted.
+const CURRENCY_CODES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB",
+    "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWL",
+];
+
+/// Codes that are also common English words. Written in lower case these are
+/// read as words, so `/add 12 cup coffee` is about a cup rather than pesos.
+const WORD_CODES: &[&str] = &[
+    "ALL", "BOB", "CUP", "GEL", "MAD", "MOP", "PEN", "SOS", "TOP", "TRY",
+];
+
+/// The ISO 4217 code `word` stands for, in any case. Codes that are also
+/// English words are only recognised in upper case.
+pub fn currency_code(word: &str) -> Option<String> {
+    let code = word.to_ascii_uppercase();
+    if CURRENCY_CODES.binary_search(&code.as_str()).is_err()
+        || (word != code && WORD_CODES.contains(&code.as_str()))
+    {
+        return None;
+    }
+    Some(code)
+}
+
+/// Parses an exchange rate file. Every line has the form
+/// `date,currency,base_currency,rate`, for example `2026-03-14,EUR,INR,90.25`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_rates(contents: &str) -> Result<Vec<CurrencyRate>, String> {
+    let mut rates = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [date, currency, base_currency, rate] = fields[..] else {
+            return Err(format!("line {}: expected 4 fields", index + 1));
+        };
+        let Ok(rate_date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
+            return Err(format!("line {}: invalid date '{date}'", index + 1));
+        };
+        let Some(rate) = parse_rate(rate) else {
+            return Err(format!("line {}: invalid rate '{rate}'", index + 1));
+        };
+        rates.push(CurrencyRate {
+            currency: currency.to_uppercase(),
+            base_currency: base_currency.to_uppercase(),
+            rate_date,
+            rate,
+        });
+    }
+    Ok(rates)
+}
+
+/// Parses a decimal rate like `90.25` into a value scaled by `RATE_SCALE`.
+fn parse_rate(value: &str) -> Option<u64> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let scale_digits = RATE_SCALE.ilog10() as usize;
+    if whole.is_empty() || fraction.len() > scale_digits {
+        return None;
+    }
+    let whole: u64 = whole.parse().ok()?;
+    let fraction: u64 = if fraction.is_empty() {
+        0
+    } else {
+        format!("{fraction:0<scale_digits$}").parse().ok()?
+    };
+    let rate = whole.checked_mul(RATE_SCALE)?.checked_add(fraction)?;
+    (rate > 0).then_some(rate)
+}
+
+/// Finds the rate for converting `currency` into `base_currency` on `date`, the
+/// latest rate on or before the date. A rate from after the date is never used,
+/// without an earlier one there is no rate.
+pub fn find_rate<'a>(
+    rates: &'a [CurrencyRate],
+    currency: &str,
+    base_currency: &str,
+    date: NaiveDate,
+) -> Option<&'a CurrencyRate> {
+    rates
+        .iter()
+        .filter(|rate| {
+            rate.currency == currency
+                && rate.base_currency == base_currency
+                && rate.rate_date <= date
+        })
+        .max_by_key(|rate| rate.rate_date)
+}
+
+/// Converts an amount in minor units of `rate.currency` into minor units of
+/// `rate.base_currency`, rounding half away from zero.
+pub fn convert(amount: i64, rate: &CurrencyRate) -> i64 {
+    let from_scale = 10i128.pow(currency_exponent(&rate.currency));
+    let to_scale = 10i128.pow(currency_exponent(&rate.base_currency));
+    let numerator = amount as i128 * rate.rate as i128 * to_scale;
+    let denominator = RATE_SCALE as i128 * from_scale;
+    let rounded = (numerator.abs() + denominator / 2) / denominator;
+    (rounded * numerator.signum()) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(currency: &str, base_currency: &str, date: &str, rate: u64) -> CurrencyRate {
+        CurrencyRate {
+            currency: currency.to_string(),
+            base_currency: base_currency.to_string(),
+            rate_date: date.parse().unwrap(),
+            rate,
+        }
+    }
+
+    #[test]
+    fn rate_files_are_parsed() {
+        let rates = parse_rates("# date,currency,base_currency,rate\n\n2026-03-14, eur ,INR,90.25\n2026-03-15,JPY,inr,0.5612\n").unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].currency, "EUR");
+        assert_eq!(rates[0].base_currency, "INR");
+        assert_eq!(rates[0].rate_date.to_string(), "2026-03-14");
+        assert_eq!(rates[0].rate, 90_250_000);
+        assert_eq!(rates[1].base_currency, "INR");
+        assert_eq!(rates[1].rate, 561_200);
+
+        assert_eq!(
+            parse_rates("2026-03-14,EUR,INR").unwrap_err(),
+            "line 1: expected 4 fields"
+        );
+        assert_eq!(
+            parse_rates("\n2026-14-03,EUR,INR,90").unwrap_err(),
+            "line 2: invalid date '2026-14-03'"
+        );
+    }
+
+    #[test]
+    fn rates_are_positive_with_at_most_six_decimals() {
+        assert_eq!(parse_rate("90"), Some(90_000_000));
+        assert_eq!(parse_rate("0.000001"), Some(1));
+        assert_eq!(parse_rate("1.5"), Some(1_500_000));
+        assert_eq!(parse_rate("0.0000001"), None);
+        assert_eq!(parse_rate("0"), None);
+        assert_eq!(parse_rate(".5"), None);
+        assert_eq!(parse_rate("-1"), None);
+        assert_eq!(parse_rate("1,5"), None);
+        assert_eq!(parse_rate("99999999999999999"), None);
+    }
+
+    #[test]
+    fn rates_from_after_the_date_are_not_used() {
+        let date = |text: &str| text.parse::<NaiveDate>().unwrap();
+        let rates = [
+            rate("EUR", "INR", "2026-03-10", 89_000_000),
+            rate("EUR", "INR", "2026-03-14", 90_000_000),
+            rate("USD", "INR", "2026-03-01", 83_000_000),
+        ];
+        let found = |on: &str| find_rate(&rates, "EUR", "INR", date(on)).map(|rate| rate.rate);
+
+        assert_eq!(found("2026-03-14"), Some(90_000_000));
+        assert_eq!(found("2026-03-13"), Some(89_000_000));
+        assert_eq!(found("2026-04-01"), Some(90_000_000));
+        assert_eq!(found("2026-03-09"), None);
+        assert!(find_rate(&rates, "INR", "EUR", date("2026-03-14")).is_none());
+    }
+
+    #[test]
+    fn conversion_follows_the_exponent_of_each_currency() {
+        // 12.50 EUR at 90.25 is 1128.125 INR
+        assert_eq!(
+            convert(1250, &rate("EUR", "INR", "2026-03-14", 90_250_000)),
+            112813
+        );
+        assert_eq!(
+            convert(-1250, &rate("EUR", "INR", "2026-03-14", 90_250_000)),
+            -112813
+        );
+        // 1000 JPY at 0.5612 is 561.20 INR
+        assert_eq!(
+            convert(1000, &rate("JPY", "INR", "2026-03-14", 561_200)),
+            56120
+        );
+        // 10.00 EUR at 162.5 is 1625 JPY
+        assert_eq!(
+            convert(1000, &rate("EUR", "JPY", "2026-03-14", 162_500_000)),
+            1625
+        );
+        // 1.000 KWD at 3.25 is 3.25 USD
+        assert_eq!(
+            convert(1000, &rate("KWD", "USD", "2026-03-14", 3_250_000)),
+            325
+        );
+    }
+}
@@ -14,15 +14,57 @@ You should have received a copy of the GNU General Public License along with Foo
 If not, see <https://www.gnu.org/licenses/>.
 */
 
-use super::DataError;
+use std::fmt;
+
+use chrono::{DateTime, Days, Months, NaiveDate, Utc};
+
+use super::{
+    balance::{compute_balances, compute_pairwise_debts},
+    budget::{budget_alerts, month_spend, BudgetAlert},
+    category::match_category,
+    debt::simplify_debts,
+    DataError,
+};
 
 pub type UserId = String;
 pub type GroupId = u32;
+pub type CategoryId = u32;
+pub type RecurringExpenseId = u32;
+pub type BudgetId = u32;
+pub type AuditEntryId = u32;
+
+pub const DEFAULT_CURRENCY: &str = "INR";
+/// Minutes east of UTC used for users who haven't set their timezone, matches
+/// the default currency.
+pub const DEFAULT_UTC_OFFSET: i32 = 330;
+pub const RATE_SCALE: u64 = 1_000_000;
 
 pub enum SplitType {
     Equal,
     Percent,
     Amount,
+    Shares,
+}
+
+/// Decides who gets the units left over when an amount can't be divided
+/// exactly between the members.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum RemainderRule {
+    /// Leftover units go to the members in the order they are listed.
+    #[default]
+    InOrder,
+    /// Leftover units go to the members in the reverse order they are listed.
+    Reverse,
+    /// Leftover units go to the first payer of the expense, then in order.
+    PayerFirst,
+}
+
+/// An amount in the minor units of a currency (paise, cents). How many minor
+/// units make up a major unit depends on the currency, see `currency_exponent`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Money {
+    pub minor_units: i64,
+    pub currency: String,
 }
 
 #[derive(Debug, Clone)]
@@ -30,6 +72,8 @@ pub struct User {
     pub user_id: UserId,
     pub name: String,
     pub username: String,
+    /// Minutes east of UTC, used to work out what "today" is for the user.
+    pub utc_offset: i32,
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +82,9 @@ pub struct Group {
     pub name: String,
     pub description: String,
     pub created_by: UserId,
+    pub base_currency: String,
+    /// Show the simplified set of payments instead of the raw debts between members.
+    pub simplify_debts: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -51,10 +98,173 @@ pub struct Expense {
     pub id: Option<u32>,
     pub added_by: UserId,
     pub group: GroupId,
-    pub amount: u32,
+    /// What was spent. Payer amounts and split values are in minor units of the
+    /// same currency.
+    pub amount: Money,
     pub title: String,
     pub description: String,
-    pub split_type: u32
+    pub split_type: u32,
+    pub split_values: Vec<SplitValue>,
+    /// Who paid for the expense and how much each of them contributed. When
+    /// empty the user who added the expense paid the whole amount.
+    pub paid_by: Vec<ExpensePayer>,
+    /// Users sharing the expense. When empty every member of the group shares it.
+    pub participants: Vec<UserId>,
+    pub created_at: DateTime<Utc>,
+    /// The day the money was spent, in the timezone of the user who added it.
+    pub incurred_on: NaiveDate,
+    pub category: Option<CategoryId>,
+    /// The recurring expense this expense is an occurrence of. Together with
+    /// `incurred_on` it identifies the occurrence, so it is never added twice.
+    pub recurring: Option<RecurringExpenseId>,
+}
+
+/// A category expenses can be filed under. Categories without a group are
+/// available to every group.
+#[derive(Debug, Clone)]
+pub struct Category {
+    pub id: Option<CategoryId>,
+    pub group: Option<GroupId>,
+    pub name: String,
+}
+
+/// Files expenses whose title contains `keyword` under `category`. Keywords
+/// without a group apply to every group.
+#[derive(Debug, Clone)]
+pub struct CategoryKeyword {
+    pub keyword: String,
+    pub group: Option<GroupId>,
+    pub category: CategoryId,
+}
+
+/// How often a recurring expense repeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// An expense that is added to its group again on a schedule. `template` holds
+/// everything but the date, which comes from the schedule.
+#[derive(Debug, Clone)]
+pub struct RecurringExpense {
+    pub id: Option<RecurringExpenseId>,
+    pub template: Expense,
+    pub schedule: Schedule,
+    pub start_date: NaiveDate,
+    /// Number of occurrences that have been added as expenses so far.
+    pub occurrences: u32,
+    pub active: bool,
+}
+
+/// A monthly spending limit for a group, or for one of its categories when
+/// `category` is set. The limit is in minor units of the group's base currency.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    pub id: Option<BudgetId>,
+    pub group: GroupId,
+    pub category: Option<CategoryId>,
+    pub amount: u32,
+}
+
+/// The kind of record an audit log entry is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEntity {
+    User,
+    Group,
+    Membership,
+    Expense,
+    Settlement,
+    Category,
+    CategoryKeyword,
+    RecurringExpense,
+    Budget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Add,
+    Update,
+    Delete,
+}
+
+/// A change made to the stored data and who made it. `before` and `after` are
+/// snapshots of the record (see `model::audit`), `before` is missing for
+/// records that were added and `after` for records that were deleted.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: Option<AuditEntryId>,
+    /// The group the record belongs to, none for users.
+    pub group: Option<GroupId>,
+    pub actor: UserId,
+    pub entity: AuditEntity,
+    /// Id of the record. Memberships use the id of the member.
+    pub entity_id: String,
+    pub action: AuditAction,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// The entry this change undid, set for changes made by /undo.
+    pub reverts: Option<AuditEntryId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpensePayer {
+    pub user_id: UserId,
+    /// Amount contributed in minor units of the expense currency.
+    pub amount: u32,
+}
+
+/// Per user input for a split. How `value` is read depends on the split type,
+/// for `SplitType::Percent` it is the percentage owed by the user and for
+/// `SplitType::Amount` it is the exact amount owed by the user. For
+/// `SplitType::Shares` it is the weight of the user, half shares are expressed by
+/// doubling every weight (4 for a couple, 2 for an adult and 1 for a kid).
+#[derive(Debug, Clone)]
+pub struct SplitValue {
+    pub user_id: UserId,
+    pub value: u32,
+}
+
+/// Exchange rate for a day, imported by the admin.
+#[derive(Debug, Clone)]
+pub struct CurrencyRate {
+    pub currency: String,
+    pub base_currency: String,
+    pub rate_date: NaiveDate,
+    /// Units of `base_currency` for one unit of `currency`, scaled by `RATE_SCALE`.
+    pub rate: u64,
+}
+
+/// A payment made by one member of a group to another to settle debts. The
+/// amount is in minor units of the group's base currency.
+#[derive(Debug, Clone)]
+pub struct Settlement {
+    pub id: Option<u32>,
+    pub group: GroupId,
+    pub payer: UserId,
+    pub payee: UserId,
+    pub amount: u32,
+    pub settled_at: DateTime<Utc>,
+}
+
+/// Net position of a user in a group.
+#[derive(Debug, Clone)]
+pub struct Balance {
+    pub user_id: UserId,
+    /// Minor units of the group's base currency. Positive when the user is owed
+    /// money, negative when the user owes money.
+    pub amount: i64,
+}
+
+/// A payment of `amount` from one user to another, in minor units of the
+/// group's base currency.
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub from: UserId,
+    pub to: UserId,
+    pub amount: i64,
 }
 
 #[derive(Debug, Clone)]
@@ -70,6 +280,7 @@ impl User {
             user_id,
             name,
             username,
+            utc_offset: DEFAULT_UTC_OFFSET,
         }
     }
 }
@@ -81,10 +292,51 @@ impl Group {
             name,
             description,
             created_by,
+            base_currency: DEFAULT_CURRENCY.to_string(),
+            simplify_debts: true,
+        }
+    }
+}
+
+impl Settlement {
+    pub fn new(group: GroupId, payer: UserId, payee: UserId, amount: u32) -> Settlement {
+        Settlement {
+            id: Option::None,
+            group,
+            payer,
+            payee,
+            amount,
+            settled_at: Utc::now(),
+        }
+    }
+}
+
+impl Category {
+    pub fn new(group: Option<GroupId>, name: String) -> Category {
+        Category {
+            id: Option::None,
+            group,
+            name,
+        }
+    }
+}
+
+impl CategoryKeyword {
+    pub fn new(keyword: String, group: Option<GroupId>, category: CategoryId) -> CategoryKeyword {
+        CategoryKeyword {
+            keyword,
+            group,
+            category,
         }
     }
 }
 
+impl Transfer {
+    pub fn new(from: UserId, to: UserId, amount: i64) -> Transfer {
+        Transfer { from, to, amount }
+    }
+}
+
 impl TryFrom<u32> for SplitType {
     type Error = DataError;
     fn try_from(value: u32) -> Result<Self, Self::Error> {
@@ -92,6 +344,7 @@ impl TryFrom<u32> for SplitType {
             0 => Ok(SplitType::Equal),
             1 => Ok(SplitType::Percent),
             2 => Ok(SplitType::Amount),
+            3 => Ok(SplitType::Shares),
             _ => Err(DataError::InvalidColumnType),
         }
     }
@@ -104,7 +357,177 @@ impl TryInto<u32> for SplitType {
             SplitType::Equal => Ok(0),
             SplitType::Percent => Ok(1),
             SplitType::Amount => Ok(2),
+            SplitType::Shares => Ok(3),
+        }
+    }
+}
+
+impl TryFrom<u32> for Schedule {
+    type Error = DataError;
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Schedule::Daily),
+            1 => Ok(Schedule::Weekly),
+            2 => Ok(Schedule::Monthly),
+            _ => Err(DataError::InvalidColumnType),
+        }
+    }
+}
+
+impl From<Schedule> for u32 {
+    fn from(value: Schedule) -> u32 {
+        match value {
+            Schedule::Daily => 0,
+            Schedule::Weekly => 1,
+            Schedule::Monthly => 2,
+        }
+    }
+}
+
+impl TryFrom<u32> for AuditEntity {
+    type Error = DataError;
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AuditEntity::User),
+            1 => Ok(AuditEntity::Group),
+            2 => Ok(AuditEntity::Membership),
+            3 => Ok(AuditEntity::Expense),
+            4 => Ok(AuditEntity::Settlement),
+            5 => Ok(AuditEntity::Category),
+            6 => Ok(AuditEntity::CategoryKeyword),
+            7 => Ok(AuditEntity::RecurringExpense),
+            8 => Ok(AuditEntity::Budget),
+            _ => Err(DataError::InvalidColumnType),
+        }
+    }
+}
+
+impl From<AuditEntity> for u32 {
+    fn from(value: AuditEntity) -> u32 {
+        match value {
+            AuditEntity::User => 0,
+            AuditEntity::Group => 1,
+            AuditEntity::Membership => 2,
+            AuditEntity::Expense => 3,
+            AuditEntity::Settlement => 4,
+            AuditEntity::Category => 5,
+            AuditEntity::CategoryKeyword => 6,
+            AuditEntity::RecurringExpense => 7,
+            AuditEntity::Budget => 8,
+        }
+    }
+}
+
+impl fmt::Display for AuditEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditEntity::User => write!(f, "user"),
+            AuditEntity::Group => write!(f, "group"),
+            AuditEntity::Membership => write!(f, "member"),
+            AuditEntity::Expense => write!(f, "expense"),
+            AuditEntity::Settlement => write!(f, "settlement"),
+            AuditEntity::Category => write!(f, "category"),
+            AuditEntity::CategoryKeyword => write!(f, "category keyword"),
+            AuditEntity::RecurringExpense => write!(f, "recurring expense"),
+            AuditEntity::Budget => write!(f, "budget"),
+        }
+    }
+}
+
+impl TryFrom<u32> for AuditAction {
+    type Error = DataError;
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AuditAction::Add),
+            1 => Ok(AuditAction::Update),
+            2 => Ok(AuditAction::Delete),
+            _ => Err(DataError::InvalidColumnType),
+        }
+    }
+}
+
+impl From<AuditAction> for u32 {
+    fn from(value: AuditAction) -> u32 {
+        match value {
+            AuditAction::Add => 0,
+            AuditAction::Update => 1,
+            AuditAction::Delete => 2,
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditAction::Add => write!(f, "added"),
+            AuditAction::Update => write!(f, "changed"),
+            AuditAction::Delete => write!(f, "removed"),
+        }
+    }
+}
+
+impl Schedule {
+    /// Date of occurrence `n`, counting from zero, of a schedule starting on
+    /// `start`. Monthly schedules keep the day of `start` and fall back to the
+    /// last day of shorter months.
+    pub fn occurrence(&self, start: NaiveDate, n: u32) -> Option<NaiveDate> {
+        match self {
+            Schedule::Daily => start.checked_add_days(Days::new(n as u64)),
+            Schedule::Weekly => start.checked_add_days(Days::new(7 * n as u64)),
+            Schedule::Monthly => start.checked_add_months(Months::new(n)),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Daily => write!(f, "daily"),
+            Schedule::Weekly => write!(f, "weekly"),
+            Schedule::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+impl Money {
+    pub fn new(minor_units: i64, currency: &str) -> Money {
+        Money {
+            minor_units,
+            currency: currency.to_string(),
+        }
+    }
+
+    /// Number of minor unit digits of the currency.
+    pub fn exponent(&self) -> u32 {
+        currency_exponent(&self.currency)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.minor_units < 0 { "-" } else { "" };
+        let units = self.minor_units.unsigned_abs();
+        let exponent = self.exponent();
+        if exponent == 0 {
+            return write!(f, "{sign}{units}");
         }
+        let scale = 10u64.pow(exponent);
+        write!(
+            f,
+            "{sign}{}.{:0width$}",
+            units / scale,
+            units % scale,
+            width = exponent as usize
+        )
+    }
+}
+
+/// Number of minor unit digits for an ISO 4217 currency code.
+pub fn currency_exponent(currency: &str) -> u32 {
+    match currency {
+        "JPY" | "KRW" | "VND" | "CLP" | "ISK" | "UGX" => 0,
+        "BHD" | "KWD" | "OMR" | "JOD" | "TND" | "LYD" | "IQD" => 3,
+        _ => 2,
     }
 }
 
@@ -118,7 +541,7 @@ impl Expense {
     pub fn new(
         added_by: UserId,
         group: GroupId,
-        amount: u32,
+        amount: Money,
         title: String,
         description: String,
     ) -> Expense {
@@ -129,16 +552,94 @@ impl Expense {
             amount,
             title,
             description,
-            split_type: SplitType::Equal.try_into().unwrap()
+            split_type: SplitType::Equal.try_into().unwrap(),
+            split_values: Vec::new(),
+            paid_by: Vec::new(),
+            participants: Vec::new(),
+            created_at: Utc::now(),
+            incurred_on: Utc::now().date_naive(),
+            category: None,
+            recurring: None,
+        }
+    }
+
+    /// The effective payers of the expense, falling back to the user who added it.
+    pub fn payers(&self) -> Vec<ExpensePayer> {
+        if self.paid_by.is_empty() {
+            return vec![ExpensePayer::new(
+                self.added_by.clone(),
+                self.amount.minor_units as u32,
+            )];
+        }
+        self.paid_by.clone()
+    }
+}
+
+impl RecurringExpense {
+    pub fn new(template: Expense, schedule: Schedule, start_date: NaiveDate) -> RecurringExpense {
+        RecurringExpense {
+            id: None,
+            template,
+            schedule,
+            start_date,
+            occurrences: 0,
+            active: true,
+        }
+    }
+
+    /// The date the next occurrence is due on.
+    pub fn next_due(&self) -> Option<NaiveDate> {
+        self.schedule.occurrence(self.start_date, self.occurrences)
+    }
+
+    /// The expense to add for the next occurrence.
+    pub fn next_expense(&self) -> Option<Expense> {
+        let incurred_on = self.next_due()?;
+        Some(Expense {
+            id: None,
+            created_at: Utc::now(),
+            incurred_on,
+            recurring: self.id,
+            ..self.template.clone()
+        })
+    }
+}
+
+impl Budget {
+    pub fn new(group: GroupId, category: Option<CategoryId>, amount: u32) -> Budget {
+        Budget {
+            id: None,
+            group,
+            category,
+            amount,
         }
     }
 }
 
+impl ExpensePayer {
+    pub fn new(user_id: UserId, amount: u32) -> ExpensePayer {
+        ExpensePayer { user_id, amount }
+    }
+}
+
+impl SplitValue {
+    pub fn new(user_id: UserId, value: u32) -> SplitValue {
+        SplitValue { user_id, value }
+    }
+}
+
+/// Changes made by users are recorded in the audit log. Methods that can't tell
+/// who is making the change from their arguments take the user as `actor`.
 pub trait Datamodel {
     async fn add_user(&self, user: User) -> Result<(), DataError>;
+    /// Fails with `DataError::UnknownUser` when the creator hasn't registered.
     async fn add_group(&self, group: Group) -> Result<(), DataError>;
-    async fn add_user_to_group(&self, group_id: GroupId, user_id: UserId) -> Result<(), DataError>;
+    /// Fails with `DataError::UnknownUser` when the user hasn't registered.
+    async fn add_user_to_group(&self, group_id: GroupId, user_id: UserId, actor: UserId) -> Result<(), DataError>;
     async fn add_expense(&self, expense: Expense) -> Result<(), DataError>;
+    /// Replaces the stored expense with the same id and works out the shares of
+    /// its participants again.
+    async fn update_expense(&self, expense: Expense, actor: UserId) -> Result<(), DataError>;
 
     async fn get_user(&self, user_id: UserId) -> Result<User, DataError>;
     async fn get_group(&self, group_id: GroupId) -> Result<Group, DataError>;
@@ -149,11 +650,123 @@ pub trait Datamodel {
         &self,
         group_id: GroupId,
         user_id: UserId,
+        actor: UserId,
     ) -> Result<(), DataError>;
-    async fn delete_group(&self, group_id: GroupId) -> Result<(), DataError>;
+    /// Marks the group and its expenses deleted and removes its members. Like
+    /// deleted expenses the group is kept until it is purged, its settlements,
+    /// categories, recurring expenses and budgets are removed with it then.
+    async fn delete_group(&self, group_id: GroupId, actor: UserId) -> Result<(), DataError>;
+    /// Users are only deleted while nothing in a ledger refers to them, so
+    /// expenses and settlements always keep who they belong to. Otherwise fails
+    /// with `DataError::UserHasHistory` and the user is left as it is.
     async fn delete_user(&self, user_id: UserId) -> Result<(), DataError>;
-    async fn delete_expense(&self, expense_id: u32) -> Result<(), DataError>;
+    /// Marks the expense deleted. Deleted expenses are left out of everything
+    /// read back until they are purged, which removes them for good.
+    async fn delete_expense(&self, expense_id: u32, actor: UserId) -> Result<(), DataError>;
+    /// Removes for good the expenses and groups deleted before `deleted_before`,
+    /// returning how many expenses were purged.
+    async fn purge_deleted_expenses(&self, deleted_before: DateTime<Utc>) -> Result<usize, DataError>;
 
     async fn get_membership(&self, user_id: UserId) -> Result<Vec<GroupMembership>, DataError>;
+    /// Expenses across all groups that the user paid for or has a share in.
     async fn get_user_expenses(&self, user_id: UserId) -> Result<Vec<Expense>, DataError>;
+    async fn get_expense_splits(&self, group_id: GroupId) -> Result<Vec<UserExpenses>, DataError>;
+
+    async fn add_settlement(&self, settlement: Settlement, actor: UserId) -> Result<(), DataError>;
+    async fn get_settlements(&self, group_id: GroupId) -> Result<Vec<Settlement>, DataError>;
+
+    async fn set_group_simplify_debts(&self, group_id: GroupId, simplify: bool, actor: UserId) -> Result<(), DataError>;
+    async fn set_user_utc_offset(&self, user_id: UserId, utc_offset: i32) -> Result<(), DataError>;
+
+    async fn add_currency_rate(&self, rate: CurrencyRate) -> Result<(), DataError>;
+    async fn get_currency_rates(&self, base_currency: String) -> Result<Vec<CurrencyRate>, DataError>;
+
+    /// Adds a category and returns its id.
+    async fn add_category(&self, category: Category, actor: UserId) -> Result<CategoryId, DataError>;
+    /// Categories available to the group, the shared ones first.
+    async fn get_categories(&self, group_id: GroupId) -> Result<Vec<Category>, DataError>;
+    async fn add_category_keyword(&self, keyword: CategoryKeyword, actor: UserId) -> Result<(), DataError>;
+    /// Keywords that apply to the group, including the shared ones.
+    async fn get_category_keywords(&self, group_id: GroupId) -> Result<Vec<CategoryKeyword>, DataError>;
+    async fn add_recurring_expense(&self, recurring: RecurringExpense) -> Result<RecurringExpenseId, DataError>;
+    /// Recurring expenses that haven't been cancelled, across all groups.
+    async fn get_recurring_expenses(&self) -> Result<Vec<RecurringExpense>, DataError>;
+    /// Records that the first `occurrences` occurrences have been added as expenses.
+    async fn set_recurring_occurrences(&self, recurring_id: RecurringExpenseId, occurrences: u32) -> Result<(), DataError>;
+    async fn cancel_recurring_expense(&self, recurring_id: RecurringExpenseId, actor: UserId) -> Result<(), DataError>;
+    /// Sets the budget of the group or category, replacing the one set before.
+    async fn set_budget(&self, budget: Budget, actor: UserId) -> Result<(), DataError>;
+    async fn get_budgets(&self, group_id: GroupId) -> Result<Vec<Budget>, DataError>;
+    async fn remove_budget(&self, group_id: GroupId, category: Option<CategoryId>, actor: UserId) -> Result<(), DataError>;
+    /// The latest `limit` changes to the group, newest first.
+    async fn get_audit_log(&self, group_id: GroupId, limit: u32) -> Result<Vec<AuditEntry>, DataError>;
+    /// The latest change to an expense made by the user that can still be
+    /// undone, skipping changes that were undone already.
+    async fn get_last_undoable_change(&self, actor: UserId) -> Result<Option<AuditEntry>, DataError>;
+    /// Reverts the change recorded in `entry`. Fails with
+    /// `DataError::ConflictingChange` when the expense was changed after it.
+    async fn undo_change(&self, entry: AuditEntry, actor: UserId) -> Result<(), DataError>;
+
+    /// Picks a category for an expense of the group from its title.
+    async fn suggest_category(&self, group_id: GroupId, title: &str) -> Result<Option<CategoryId>, DataError> {
+        let keywords = self.get_category_keywords(group_id).await?;
+        Ok(match_category(title, &keywords))
+    }
+
+    /// Nets what every member paid against what they owe. Members without any
+    /// expenses are listed with a zero balance.
+    async fn get_group_balances(&self, group_id: GroupId) -> Result<Vec<Balance>, DataError> {
+        let group = self.get_group(group_id).await?;
+        let members = self.get_group_members(group_id).await?;
+        let expenses = self.get_expenses(group_id).await?;
+        let splits = self.get_expense_splits(group_id).await?;
+        let settlements = self.get_settlements(group_id).await?;
+        let rates = self.get_currency_rates(group.base_currency.clone()).await?;
+
+        let mut balances = compute_balances(&group, &expenses, &splits, &settlements, &rates)?;
+        for member in members {
+            balances.entry(member.user_id).or_insert(0);
+        }
+        Ok(balances
+            .into_iter()
+            .map(|(user_id, amount)| Balance { user_id, amount })
+            .collect())
+    }
+
+    /// What the group spent in the month `month` falls in, in its base currency,
+    /// optionally only under one category.
+    async fn get_month_spend(&self, group_id: GroupId, month: NaiveDate, category: Option<CategoryId>) -> Result<i64, DataError> {
+        let group = self.get_group(group_id).await?;
+        let expenses = self.get_expenses(group_id).await?;
+        let rates = self.get_currency_rates(group.base_currency.clone()).await?;
+        month_spend(&group, &expenses, &rates, month, category)
+    }
+
+    /// Budget thresholds crossed by `expense`, which must already be stored.
+    /// `previous` is the expense as it was before an edit.
+    async fn get_budget_alerts(&self, expense: &Expense, previous: Option<&Expense>) -> Result<Vec<BudgetAlert>, DataError> {
+        let budgets = self.get_budgets(expense.group).await?;
+        if budgets.is_empty() {
+            return Ok(Vec::new());
+        }
+        let group = self.get_group(expense.group).await?;
+        let expenses = self.get_expenses(expense.group).await?;
+        let rates = self.get_currency_rates(group.base_currency.clone()).await?;
+        budget_alerts(&group, &budgets, &expenses, &rates, expense, previous)
+    }
+
+    /// Payments needed to settle the group, simplified or not depending on the
+    /// group's `simplify_debts` option.
+    async fn get_group_debts(&self, group_id: GroupId) -> Result<Vec<Transfer>, DataError> {
+        let group = self.get_group(group_id).await?;
+        if group.simplify_debts {
+            let balances = self.get_group_balances(group_id).await?;
+            return Ok(simplify_debts(&balances));
+        }
+        let expenses = self.get_expenses(group_id).await?;
+        let splits = self.get_expense_splits(group_id).await?;
+        let settlements = self.get_settlements(group_id).await?;
+        let rates = self.get_currency_rates(group.base_currency.clone()).await?;
+        compute_pairwise_debts(&group, &expenses, &splits, &settlements, &rates)
+    }
 }
@@ -0,0 +1,189 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::datamodel::{Balance, Transfer, UserId};
+
+/// Groups with more non zero balances than this are settled greedily, finding
+/// the fewest payments takes time exponential in the number of balances.
+const EXACT_LIMIT: usize = 16;
+
+/// Reduces the net balances of a group to the fewest payments that settle
+/// everyone. Members whose balances cancel out among themselves are settled
+/// separately, a set of `k` such members needs `k - 1` payments, so the
+/// balances are split into as many of these sets as possible. Above
+/// `EXACT_LIMIT` members with a non zero balance only the greedy settling is
+/// used, which needs at most one payment less than that number.
+pub fn simplify_debts(balances: &[Balance]) -> Vec<Transfer> {
+    let mut open: Vec<(UserId, i64)> = balances
+        .iter()
+        .filter(|balance| balance.amount != 0)
+        .map(|balance| (balance.user_id.clone(), balance.amount))
+        .collect();
+    // Sorted so the result does not depend on the input order
+    open.sort();
+    if open.len() > EXACT_LIMIT {
+        return settle(&open);
+    }
+    zero_sum_sets(&open)
+        .iter()
+        .flat_map(|set| settle(set))
+        .collect()
+}
+
+/// Splits balances adding up to zero into the largest number of disjoint sets
+/// that each add up to zero.
+fn zero_sum_sets(balances: &[(UserId, i64)]) -> Vec<Vec<(UserId, i64)>> {
+    let count = balances.len();
+    let full = (1usize << count) - 1;
+    let mut sums = vec![0i64; full + 1];
+    // Most zero sum sets the members in a mask can be split into, and the
+    // member to take out of the mask to get there
+    let mut sets = vec![0u32; full + 1];
+    let mut taken = vec![0usize; full + 1];
+    for mask in 1..=full {
+        let lowest = mask.trailing_zeros() as usize;
+        sums[mask] = sums[mask & (mask - 1)] + balances[lowest].1;
+        let (member, best) = (0..count)
+            .filter(|member| mask & (1 << member) != 0)
+            .map(|member| (member, sets[mask ^ (1 << member)]))
+            .rev()
+            .max_by_key(|(_, sets)| *sets)
+            .unwrap_or_default();
+        sets[mask] = best + u32::from(sums[mask] == 0);
+        taken[mask] = member;
+    }
+
+    // Members taken out between two masks adding up to zero form one set
+    let mut result = Vec::new();
+    let mut current = Vec::new();
+    let mut mask = full;
+    while mask != 0 {
+        let member = taken[mask];
+        current.push(balances[member].clone());
+        mask ^= 1 << member;
+        if sums[mask] == 0 {
+            result.push(std::mem::take(&mut current));
+        }
+    }
+    result
+}
+
+/// Settles balances adding up to zero by having the largest debtor pay the
+/// largest creditor until everyone is settled. Every payment settles at least
+/// one member and the last one settles two.
+fn settle(balances: &[(UserId, i64)]) -> Vec<Transfer> {
+    let mut debtors: Vec<(UserId, i64)> = balances
+        .iter()
+        .filter(|(_, amount)| *amount < 0)
+        .map(|(user_id, amount)| (user_id.clone(), -amount))
+        .collect();
+    let mut creditors: Vec<(UserId, i64)> = balances
+        .iter()
+        .filter(|(_, amount)| *amount > 0)
+        .cloned()
+        .collect();
+    let mut transfers = Vec::new();
+    while let (Some(debtor), Some(creditor)) = (largest(&debtors), largest(&creditors)) {
+        let amount = debtors[debtor].1.min(creditors[creditor].1);
+        transfers.push(Transfer::new(
+            debtors[debtor].0.clone(),
+            creditors[creditor].0.clone(),
+            amount,
+        ));
+        debtors[debtor].1 -= amount;
+        creditors[creditor].1 -= amount;
+        debtors.retain(|(_, remaining)| *remaining > 0);
+        creditors.retain(|(_, remaining)| *remaining > 0);
+    }
+    transfers
+}
+
+/// Index of the largest amount, ties go to the smallest user id so the result
+/// does not depend on the input order.
+fn largest(amounts: &[(UserId, i64)]) -> Option<usize> {
+    amounts
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balances(amounts: &[i64]) -> Vec<Balance> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| Balance {
+                user_id: index.to_string(),
+                amount: *amount,
+            })
+            .collect()
+    }
+
+    /// Applies the transfers and checks that everyone ends up settled.
+    fn assert_settled(balances: &[Balance], transfers: &[Transfer]) {
+        for balance in balances {
+            let paid: i64 = transfers
+                .iter()
+                .filter(|transfer| transfer.from == balance.user_id)
+                .map(|transfer| transfer.amount)
+                .sum();
+            let received: i64 = transfers
+                .iter()
+                .filter(|transfer| transfer.to == balance.user_id)
+                .map(|transfer| transfer.amount)
+                .sum();
+            assert_eq!(balance.amount + paid - received, 0, "{}", balance.user_id);
+        }
+        assert!(transfers.iter().all(|transfer| transfer.amount > 0));
+    }
+
+    #[test]
+    fn balances_cancelling_out_are_settled_separately() {
+        // Largest debtor first would need 4 payments
+        let balances = balances(&[5, 4, -4, -3, -2]);
+        let transfers = simplify_debts(&balances);
+        assert_settled(&balances, &transfers);
+        assert_eq!(transfers.len(), 3);
+    }
+
+    #[test]
+    fn pairs_are_settled_directly() {
+        let balances = balances(&[10, -10, 7, -7, 3, -3]);
+        let transfers = simplify_debts(&balances);
+        assert_settled(&balances, &transfers);
+        assert_eq!(transfers.len(), 3);
+    }
+
+    #[test]
+    fn large_groups_are_still_settled() {
+        let mut amounts: Vec<i64> = (1..=20).map(|amount| amount * 3).collect();
+        amounts.push(-amounts.iter().sum::<i64>());
+        let balances = balances(&amounts);
+        let transfers = simplify_debts(&balances);
+        assert_settled(&balances, &transfers);
+        assert_eq!(transfers.len(), 20);
+    }
+
+    #[test]
+    fn settled_groups_need_no_payments() {
+        assert!(simplify_debts(&balances(&[0, 0])).is_empty());
+        assert!(simplify_debts(&[]).is_empty());
+    }
+}
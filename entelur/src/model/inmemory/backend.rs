@@ -0,0 +1,852 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+
+use crate::model::{
+    audit,
+    balance::validate_settlement,
+    category::DEFAULT_CATEGORIES,
+    datamodel::{
+        AuditAction, AuditEntity, AuditEntry, AuditEntryId, Budget, BudgetId, Category, CategoryId,
+        CategoryKeyword, CurrencyRate, Datamodel, Expense, Group, GroupId, GroupMembership,
+        RecurringExpense, RecurringExpenseId, RemainderRule, Settlement, User, UserExpenses,
+        UserId,
+    },
+    split::calculate_split,
+    DataError,
+};
+
+#[derive(Default)]
+struct InMemoryData {
+    users: HashMap<UserId, User>,
+    groups: BTreeMap<GroupId, Group>,
+    /// Deleted groups and when they were deleted, kept until they are purged.
+    deleted_groups: BTreeMap<GroupId, (Group, DateTime<Utc>)>,
+    memberships: Vec<GroupMembership>,
+    expenses: BTreeMap<u32, Expense>,
+    /// Deleted expenses and when they were deleted, kept until they are purged.
+    deleted_expenses: BTreeMap<u32, (Expense, DateTime<Utc>)>,
+    user_expenses: Vec<UserExpenses>,
+    currency_rates: Vec<CurrencyRate>,
+    settlements: Vec<Settlement>,
+    categories: Vec<Category>,
+    category_keywords: Vec<CategoryKeyword>,
+    recurring_expenses: BTreeMap<RecurringExpenseId, RecurringExpense>,
+    budgets: Vec<Budget>,
+    audit_log: Vec<AuditEntry>,
+    last_group_id: GroupId,
+    last_expense_id: u32,
+    last_settlement_id: u32,
+    last_category_id: CategoryId,
+    last_recurring_id: RecurringExpenseId,
+    last_budget_id: BudgetId,
+    last_audit_id: AuditEntryId,
+}
+
+/// Backend that keeps everything in process memory. Nothing is persisted, so all
+/// data is lost when the bot stops. Useful for demos and tests.
+pub struct InMemoryBackend {
+    data: RwLock<Box<InMemoryData>>,
+    remainder_rule: RemainderRule,
+}
+
+impl InMemoryBackend {
+    pub fn new(remainder_rule: RemainderRule) -> InMemoryBackend {
+        let mut data = InMemoryData::default();
+        for (name, keywords) in DEFAULT_CATEGORIES {
+            let category = data.insert_category(Category::new(None, name.to_string()));
+            for keyword in keywords {
+                data.category_keywords.push(CategoryKeyword::new(
+                    keyword.to_string(),
+                    None,
+                    category,
+                ));
+            }
+        }
+        InMemoryBackend {
+            data: RwLock::new(Box::new(data)),
+            remainder_rule,
+        }
+    }
+
+    pub fn remainder_rule(&self) -> RemainderRule {
+        self.remainder_rule
+    }
+}
+
+impl Default for InMemoryBackend {
+    fn default() -> Self {
+        Self::new(RemainderRule::default())
+    }
+}
+
+impl InMemoryData {
+    fn insert_category(&mut self, category: Category) -> CategoryId {
+        self.last_category_id += 1;
+        let category_id = self.last_category_id;
+        self.categories.push(Category {
+            id: Some(category_id),
+            ..category
+        });
+        category_id
+    }
+
+    fn record(&mut self, entry: AuditEntry) {
+        self.last_audit_id += 1;
+        self.audit_log.push(AuditEntry {
+            id: Some(self.last_audit_id),
+            ..entry
+        });
+    }
+
+    /// Whether the change recorded in `entry` is in effect, it neither undid
+    /// another change nor was undone itself.
+    fn is_live(&self, entry: &AuditEntry) -> bool {
+        entry.reverts.is_none() && !self.audit_log.iter().any(|later| later.reverts == entry.id)
+    }
+
+    /// Stores `expense` in place of the expense with the same id and works out
+    /// its shares again.
+    fn replace_expense(
+        &mut self,
+        expense_id: u32,
+        expense: Expense,
+        remainder_rule: RemainderRule,
+    ) -> Result<(), DataError> {
+        let users = self.group_members(expense.group);
+        let split = calculate_split(&expense, &users, remainder_rule)?;
+
+        self.user_expenses
+            .retain(|user_expense| user_expense.expenses_id != expense_id);
+        for (user_id, amount) in split {
+            self.user_expenses.push(UserExpenses {
+                user_id,
+                expenses_id: expense_id,
+                split: amount.minor_units as u32,
+            });
+        }
+        self.expenses.insert(expense_id, expense);
+        Ok(())
+    }
+
+    fn ensure_group_exists(&self, group_id: GroupId) -> Result<(), DataError> {
+        if !self.groups.contains_key(&group_id) {
+            return Err(DataError::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    fn ensure_user_exists(&self, user_id: &UserId) -> Result<(), DataError> {
+        if !self.users.contains_key(user_id) {
+            return Err(DataError::UnknownUser);
+        }
+        Ok(())
+    }
+
+    /// Whether anything in a ledger refers to the user, the same rows that
+    /// restrict deleting a user in the sqlite backend.
+    fn has_history(&self, user_id: &UserId) -> bool {
+        let refers = |expense: &Expense| {
+            &expense.added_by == user_id
+                || expense
+                    .paid_by
+                    .iter()
+                    .any(|payer| &payer.user_id == user_id)
+                || expense
+                    .split_values
+                    .iter()
+                    .any(|value| &value.user_id == user_id)
+        };
+        self.groups
+            .values()
+            .any(|group| &group.created_by == user_id)
+            || self
+                .deleted_groups
+                .values()
+                .any(|(group, _)| &group.created_by == user_id)
+            || self.expenses.values().any(refers)
+            || self
+                .deleted_expenses
+                .values()
+                .any(|(expense, _)| refers(expense))
+            || self
+                .recurring_expenses
+                .values()
+                .any(|recurring| refers(&recurring.template))
+            || self
+                .user_expenses
+                .iter()
+                .any(|user_expense| &user_expense.user_id == user_id)
+            || self
+                .settlements
+                .iter()
+                .any(|settlement| &settlement.payer == user_id || &settlement.payee == user_id)
+    }
+
+    fn group_members(&self, group_id: GroupId) -> Vec<User> {
+        self.memberships
+            .iter()
+            .filter(|membership| membership.group_id == group_id)
+            .filter_map(|membership| self.users.get(&membership.user_id))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Datamodel for InMemoryBackend {
+    async fn add_user(&self, user: User) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        if data.users.contains_key(&user.user_id) {
+            return Err(DataError::DatabaseError);
+        }
+        data.record(audit::added(user.user_id.clone(), &user));
+        data.users.insert(user.user_id.clone(), user);
+        Ok(())
+    }
+
+    async fn add_group(&self, group: Group) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        data.ensure_user_exists(&group.created_by)?;
+        data.last_group_id += 1;
+        let group_id = data.last_group_id;
+        let group = Group { group_id, ..group };
+        let membership = GroupMembership::new(group.created_by.clone(), group_id);
+        data.record(audit::added(group.created_by.clone(), &group));
+        data.record(audit::added(group.created_by.clone(), &membership));
+        data.memberships.push(membership);
+        data.groups.insert(group_id, group);
+        Ok(())
+    }
+
+    async fn add_user_to_group(
+        &self,
+        group_id: GroupId,
+        user_id: UserId,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        data.ensure_group_exists(group_id)?;
+        data.ensure_user_exists(&user_id)?;
+        if data
+            .memberships
+            .iter()
+            .any(|membership| membership.group_id == group_id && membership.user_id == user_id)
+        {
+            return Ok(());
+        }
+        let membership = GroupMembership::new(user_id, group_id);
+        data.record(audit::added(actor, &membership));
+        data.memberships.push(membership);
+        Ok(())
+    }
+
+    async fn add_expense(&self, expense: Expense) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        data.ensure_group_exists(expense.group)?;
+        let users = data.group_members(expense.group);
+        let split = calculate_split(&expense, &users, self.remainder_rule)?;
+        // Deleted occurrences count too, like the unique index of the sqlite
+        // backend, so a deleted occurrence isn't added again
+        let is_occurrence = |existing: &Expense| {
+            existing.recurring == expense.recurring && existing.incurred_on == expense.incurred_on
+        };
+        if expense.recurring.is_some()
+            && (data.expenses.values().any(is_occurrence)
+                || data
+                    .deleted_expenses
+                    .values()
+                    .any(|(existing, _)| is_occurrence(existing)))
+        {
+            return Err(DataError::DuplicateExpense);
+        }
+
+        data.last_expense_id += 1;
+        let expense_id = data.last_expense_id;
+        for (user_id, amount) in split {
+            data.user_expenses.push(UserExpenses {
+                user_id,
+                expenses_id: expense_id,
+                split: amount.minor_units as u32,
+            });
+        }
+        let expense = Expense {
+            id: Some(expense_id),
+            ..expense
+        };
+        data.record(audit::added(expense.added_by.clone(), &expense));
+        data.expenses.insert(expense_id, expense);
+        Ok(())
+    }
+
+    async fn update_expense(&self, expense: Expense, actor: UserId) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let Some(expense_id) = expense.id else {
+            return Err(DataError::LogicalError);
+        };
+        let Some(before) = data.expenses.get(&expense_id).cloned() else {
+            return Err(DataError::QueryReturnedNoRows);
+        };
+        data.replace_expense(expense_id, expense.clone(), self.remainder_rule)?;
+        data.record(audit::updated(actor, &before, &expense));
+        Ok(())
+    }
+
+    async fn get_user(&self, user_id: UserId) -> Result<User, DataError> {
+        let data = self.data.read().await;
+        data.users
+            .get(&user_id)
+            .cloned()
+            .ok_or(DataError::QueryReturnedNoRows)
+    }
+
+    async fn get_group(&self, group_id: GroupId) -> Result<Group, DataError> {
+        let data = self.data.read().await;
+        data.groups
+            .get(&group_id)
+            .cloned()
+            .ok_or(DataError::QueryReturnedNoRows)
+    }
+
+    async fn get_group_members(&self, group_id: GroupId) -> Result<Vec<User>, DataError> {
+        let data = self.data.read().await;
+        Ok(data.group_members(group_id))
+    }
+
+    async fn get_expenses(&self, group_id: GroupId) -> Result<Vec<Expense>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .expenses
+            .values()
+            .filter(|expense| expense.group == group_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn remove_user_from_group(
+        &self,
+        group_id: GroupId,
+        user_id: UserId,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let membership = GroupMembership::new(user_id, group_id);
+        let count = data.memberships.len();
+        data.memberships.retain(|existing| {
+            !(existing.group_id == group_id && existing.user_id == membership.user_id)
+        });
+        if data.memberships.len() != count {
+            data.record(audit::deleted(actor, &membership));
+        }
+        Ok(())
+    }
+
+    async fn delete_group(&self, group_id: GroupId, actor: UserId) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let Some(group) = data.groups.remove(&group_id) else {
+            return Err(DataError::QueryReturnedNoRows);
+        };
+        let deleted_at = Utc::now();
+        let expense_ids: Vec<u32> = data
+            .expenses
+            .iter()
+            .filter(|(_, expense)| expense.group == group_id)
+            .map(|(expense_id, _)| *expense_id)
+            .collect();
+        for expense_id in expense_ids {
+            if let Some(expense) = data.expenses.remove(&expense_id) {
+                data.deleted_expenses
+                    .insert(expense_id, (expense, deleted_at));
+            }
+        }
+        data.memberships
+            .retain(|membership| membership.group_id != group_id);
+        for recurring in data.recurring_expenses.values_mut() {
+            if recurring.template.group == group_id {
+                recurring.active = false;
+            }
+        }
+        data.record(audit::deleted(actor, &group));
+        data.deleted_groups.insert(group_id, (group, deleted_at));
+        Ok(())
+    }
+
+    async fn delete_user(&self, user_id: UserId) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        if data.has_history(&user_id) {
+            return Err(DataError::UserHasHistory);
+        }
+        data.memberships
+            .retain(|membership| membership.user_id != user_id);
+        if let Some(user) = data.users.remove(&user_id) {
+            data.record(audit::deleted(user_id, &user));
+        }
+        Ok(())
+    }
+
+    async fn delete_expense(&self, expense_id: u32, actor: UserId) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let Some(expense) = data.expenses.remove(&expense_id) else {
+            return Err(DataError::QueryReturnedNoRows);
+        };
+        data.record(audit::deleted(actor, &expense));
+        data.deleted_expenses
+            .insert(expense_id, (expense, Utc::now()));
+        Ok(())
+    }
+
+    async fn purge_deleted_expenses(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> Result<usize, DataError> {
+        let mut data = self.data.write().await;
+        let purged: Vec<u32> = data
+            .deleted_expenses
+            .iter()
+            .filter(|(_, (_, deleted_at))| *deleted_at < deleted_before)
+            .map(|(expense_id, _)| *expense_id)
+            .collect();
+        for expense_id in &purged {
+            data.deleted_expenses.remove(expense_id);
+        }
+        data.user_expenses
+            .retain(|user_expense| !purged.contains(&user_expense.expenses_id));
+
+        // Everything left in a purged group goes with it
+        data.deleted_groups
+            .retain(|_, (_, deleted_at)| *deleted_at >= deleted_before);
+        let data = &mut **data;
+        let groups: Vec<GroupId> = data
+            .groups
+            .keys()
+            .chain(data.deleted_groups.keys())
+            .copied()
+            .collect();
+        let exists = |group_id: &GroupId| groups.contains(group_id);
+        data.settlements
+            .retain(|settlement| exists(&settlement.group));
+        data.categories
+            .retain(|category| category.group.as_ref().is_none_or(exists));
+        data.category_keywords
+            .retain(|keyword| keyword.group.as_ref().is_none_or(exists));
+        data.recurring_expenses
+            .retain(|_, recurring| exists(&recurring.template.group));
+        data.budgets.retain(|budget| exists(&budget.group));
+        Ok(purged.len())
+    }
+
+    async fn get_membership(&self, user_id: UserId) -> Result<Vec<GroupMembership>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .memberships
+            .iter()
+            .filter(|membership| membership.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_user_expenses(&self, user_id: UserId) -> Result<Vec<Expense>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .expenses
+            .values()
+            .filter(|expense| {
+                expense
+                    .payers()
+                    .iter()
+                    .any(|payer| payer.user_id == user_id)
+                    || data.user_expenses.iter().any(|user_expense| {
+                        user_expense.user_id == user_id
+                            && Some(user_expense.expenses_id) == expense.id
+                    })
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_expense_splits(&self, group_id: GroupId) -> Result<Vec<UserExpenses>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .user_expenses
+            .iter()
+            .filter(|user_expense| {
+                data.expenses
+                    .get(&user_expense.expenses_id)
+                    .is_some_and(|expense| expense.group == group_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn add_settlement(&self, settlement: Settlement, actor: UserId) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        data.ensure_group_exists(settlement.group)?;
+        validate_settlement(&settlement, &data.group_members(settlement.group))?;
+        data.last_settlement_id += 1;
+        let settlement = Settlement {
+            id: Some(data.last_settlement_id),
+            ..settlement
+        };
+        data.record(audit::added(actor, &settlement));
+        data.settlements.push(settlement);
+        Ok(())
+    }
+
+    async fn get_settlements(&self, group_id: GroupId) -> Result<Vec<Settlement>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .settlements
+            .iter()
+            .filter(|settlement| settlement.group == group_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn set_group_simplify_debts(
+        &self,
+        group_id: GroupId,
+        simplify: bool,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let group = data
+            .groups
+            .get_mut(&group_id)
+            .ok_or(DataError::QueryReturnedNoRows)?;
+        let before = group.clone();
+        group.simplify_debts = simplify;
+        let entry = audit::updated(actor, &before, group);
+        data.record(entry);
+        Ok(())
+    }
+
+    async fn set_user_utc_offset(&self, user_id: UserId, utc_offset: i32) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let user = data
+            .users
+            .get_mut(&user_id)
+            .ok_or(DataError::QueryReturnedNoRows)?;
+        let before = user.clone();
+        user.utc_offset = utc_offset;
+        let entry = audit::updated(user_id, &before, user);
+        data.record(entry);
+        Ok(())
+    }
+
+    async fn add_currency_rate(&self, rate: CurrencyRate) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        data.currency_rates.retain(|existing| {
+            !(existing.currency == rate.currency
+                && existing.base_currency == rate.base_currency
+                && existing.rate_date == rate.rate_date)
+        });
+        data.currency_rates.push(rate);
+        Ok(())
+    }
+
+    async fn get_currency_rates(
+        &self,
+        base_currency: String,
+    ) -> Result<Vec<CurrencyRate>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .currency_rates
+            .iter()
+            .filter(|rate| rate.base_currency == base_currency)
+            .cloned()
+            .collect())
+    }
+
+    async fn add_category(
+        &self,
+        category: Category,
+        actor: UserId,
+    ) -> Result<CategoryId, DataError> {
+        let mut data = self.data.write().await;
+        let exists = data.categories.iter().any(|existing| {
+            existing.group == category.group && existing.name.eq_ignore_ascii_case(&category.name)
+        });
+        if exists {
+            return Err(DataError::LogicalError);
+        }
+        let category_id = data.insert_category(category.clone());
+        data.record(audit::added(
+            actor,
+            &Category {
+                id: Some(category_id),
+                ..category
+            },
+        ));
+        Ok(category_id)
+    }
+
+    async fn get_categories(&self, group_id: GroupId) -> Result<Vec<Category>, DataError> {
+        let data = self.data.read().await;
+        let mut categories: Vec<Category> = data
+            .categories
+            .iter()
+            .filter(|category| category.group.is_none() || category.group == Some(group_id))
+            .cloned()
+            .collect();
+        categories.sort_by_key(|category| (category.group.is_some(), category.id));
+        Ok(categories)
+    }
+
+    async fn add_category_keyword(
+        &self,
+        keyword: CategoryKeyword,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        data.record(audit::added(actor, &keyword));
+        data.category_keywords.push(keyword);
+        Ok(())
+    }
+
+    async fn get_category_keywords(
+        &self,
+        group_id: GroupId,
+    ) -> Result<Vec<CategoryKeyword>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .category_keywords
+            .iter()
+            .filter(|keyword| keyword.group.is_none() || keyword.group == Some(group_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn add_recurring_expense(
+        &self,
+        recurring: RecurringExpense,
+    ) -> Result<RecurringExpenseId, DataError> {
+        let mut data = self.data.write().await;
+        data.ensure_group_exists(recurring.template.group)?;
+        data.last_recurring_id += 1;
+        let recurring_id = data.last_recurring_id;
+        let recurring = RecurringExpense {
+            id: Some(recurring_id),
+            ..recurring
+        };
+        data.record(audit::added(
+            recurring.template.added_by.clone(),
+            &recurring,
+        ));
+        data.recurring_expenses.insert(recurring_id, recurring);
+        Ok(recurring_id)
+    }
+
+    async fn get_recurring_expenses(&self) -> Result<Vec<RecurringExpense>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .recurring_expenses
+            .values()
+            .filter(|recurring| recurring.active)
+            .cloned()
+            .collect())
+    }
+
+    async fn set_recurring_occurrences(
+        &self,
+        recurring_id: RecurringExpenseId,
+        occurrences: u32,
+    ) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let recurring = data
+            .recurring_expenses
+            .get_mut(&recurring_id)
+            .ok_or(DataError::QueryReturnedNoRows)?;
+        recurring.occurrences = occurrences;
+        Ok(())
+    }
+
+    async fn cancel_recurring_expense(
+        &self,
+        recurring_id: RecurringExpenseId,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let entry = match data.recurring_expenses.get_mut(&recurring_id) {
+            Some(recurring) if recurring.active => {
+                let before = recurring.clone();
+                recurring.active = false;
+                audit::updated(actor, &before, recurring)
+            }
+            _ => return Err(DataError::QueryReturnedNoRows),
+        };
+        data.record(entry);
+        Ok(())
+    }
+
+    async fn set_budget(&self, budget: Budget, actor: UserId) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        data.ensure_group_exists(budget.group)?;
+        let before = data
+            .budgets
+            .iter()
+            .find(|existing| existing.group == budget.group && existing.category == budget.category)
+            .cloned();
+        data.budgets.retain(|existing| {
+            existing.group != budget.group || existing.category != budget.category
+        });
+        data.last_budget_id += 1;
+        let budget = Budget {
+            id: Some(data.last_budget_id),
+            ..budget
+        };
+        let entry = match before {
+            Some(before) => audit::updated(actor, &before, &budget),
+            None => audit::added(actor, &budget),
+        };
+        data.record(entry);
+        data.budgets.push(budget);
+        Ok(())
+    }
+
+    async fn get_budgets(&self, group_id: GroupId) -> Result<Vec<Budget>, DataError> {
+        let data = self.data.read().await;
+        let mut budgets: Vec<Budget> = data
+            .budgets
+            .iter()
+            .filter(|budget| budget.group == group_id)
+            .cloned()
+            .collect();
+        budgets.sort_by_key(|budget| (budget.category.is_some(), budget.id));
+        Ok(budgets)
+    }
+
+    async fn remove_budget(
+        &self,
+        group_id: GroupId,
+        category: Option<CategoryId>,
+        actor: UserId,
+    ) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let Some(budget) = data
+            .budgets
+            .iter()
+            .find(|budget| budget.group == group_id && budget.category == category)
+            .cloned()
+        else {
+            return Err(DataError::QueryReturnedNoRows);
+        };
+        data.budgets
+            .retain(|budget| budget.group != group_id || budget.category != category);
+        data.record(audit::deleted(actor, &budget));
+        Ok(())
+    }
+
+    async fn get_audit_log(
+        &self,
+        group_id: GroupId,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .audit_log
+            .iter()
+            .rev()
+            .filter(|entry| entry.group == Some(group_id))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_last_undoable_change(
+        &self,
+        actor: UserId,
+    ) -> Result<Option<AuditEntry>, DataError> {
+        let data = self.data.read().await;
+        Ok(data
+            .audit_log
+            .iter()
+            .rev()
+            .find(|entry| {
+                entry.actor == actor && entry.entity == AuditEntity::Expense && data.is_live(entry)
+            })
+            .cloned())
+    }
+
+    async fn undo_change(&self, entry: AuditEntry, actor: UserId) -> Result<(), DataError> {
+        let mut data = self.data.write().await;
+        let (Some(entry_id), Ok(expense_id), AuditEntity::Expense) =
+            (entry.id, entry.entity_id.parse::<u32>(), entry.entity)
+        else {
+            return Err(DataError::LogicalError);
+        };
+        let changed_since = data.audit_log.iter().any(|later| {
+            later.entity == entry.entity
+                && later.entity_id == entry.entity_id
+                && later.id > entry.id
+                && data.is_live(later)
+        });
+        if changed_since || !data.is_live(&entry) {
+            return Err(DataError::ConflictingChange);
+        }
+
+        let revert = match entry.action {
+            AuditAction::Add => {
+                let expense = data
+                    .expenses
+                    .remove(&expense_id)
+                    .ok_or(DataError::QueryReturnedNoRows)?;
+                data.deleted_expenses
+                    .insert(expense_id, (expense.clone(), Utc::now()));
+                audit::deleted(actor, &expense)
+            }
+            AuditAction::Delete => {
+                let group_id = data
+                    .deleted_expenses
+                    .get(&expense_id)
+                    .map(|(expense, _)| expense.group)
+                    .ok_or(DataError::QueryReturnedNoRows)?;
+                data.ensure_group_exists(group_id)?;
+                let (expense, _) = data
+                    .deleted_expenses
+                    .remove(&expense_id)
+                    .ok_or(DataError::QueryReturnedNoRows)?;
+                data.expenses.insert(expense_id, expense.clone());
+                audit::added(actor, &expense)
+            }
+            AuditAction::Update => {
+                let current = data
+                    .expenses
+                    .get(&expense_id)
+                    .cloned()
+                    .ok_or(DataError::QueryReturnedNoRows)?;
+                let restored = entry
+                    .before
+                    .as_deref()
+                    .and_then(|snapshot| audit::expense_from_snapshot(expense_id, snapshot))
+                    .ok_or(DataError::LogicalError)?;
+                let restored = Expense {
+                    created_at: current.created_at,
+                    ..restored
+                };
+                data.replace_expense(expense_id, restored.clone(), self.remainder_rule)?;
+                audit::updated(actor, &current, &restored)
+            }
+        };
+        data.record(AuditEntry {
+            reverts: Some(entry_id),
+            ..revert
+        });
+        Ok(())
+    }
+}
@@ -14,9 +14,19 @@ You should have received a copy of the GNU General Public License along with Foo
 If not, see <https://www.gnu.org/licenses/>.
 */
 
+pub mod audit;
+pub mod backend;
+pub mod balance;
+pub mod budget;
+pub mod category;
+pub mod currency;
 pub mod datamodel;
+pub mod debt;
+pub mod inmemory;
 pub mod migrations;
+pub mod split;
 pub mod sqlite;
+pub mod statement;
 
 #[derive(Debug, Clone, Copy)]
 pub enum DataError {
@@ -40,4 +50,26 @@ pub enum DataError {
     InvalidParameterCount,
     LogicalError,
     InvalidSplitType,
-}
\ No newline at end of file
+    InvalidAmount,
+    InvalidSplitPercentage,
+    SplitAmountMismatch,
+    InvalidSplitShares,
+    DuplicateSplitUser,
+    UnknownUser,
+    UserHasHistory,
+    MissingCurrencyRate,
+    PayerAmountMismatch,
+    DuplicateExpense,
+    ConflictingChange,
+}
+
+impl DataError {
+    /// Whether the same call may succeed when it is tried again later, because
+    /// the storage was busy or unreachable rather than the data being wrong.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            DataError::DatabaseError | DataError::ConnectionError | DataError::UnknownError
+        )
+    }
+}
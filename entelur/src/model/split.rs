@@ -0,0 +1,302 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use super::{
+    datamodel::{Expense, Money, RemainderRule, SplitType, SplitValue, User, UserId},
+    DataError,
+};
+
+/// Computes how much each user owes for the expense, in the currency of the
+/// expense. The returned amounts always add up to `expense.amount`, units that
+/// can't be divided evenly are handed out according to `rule`.
+pub fn calculate_split(
+    expense: &Expense,
+    users: &[User],
+    rule: RemainderRule,
+) -> Result<Vec<(UserId, Money)>, DataError> {
+    if users.is_empty() {
+        return Err(DataError::LogicalError);
+    }
+    // Shares are stored as u32 minor units, so the whole amount has to fit
+    let amount = match u32::try_from(expense.amount.minor_units) {
+        Ok(amount) if amount > 0 => amount as u64,
+        _ => return Err(DataError::InvalidAmount),
+    };
+    validate_payers(expense, users, amount)?;
+    let users = &select_participants(expense, users)?;
+    let share = |user_id: &UserId, minor_units: u64| {
+        (
+            user_id.clone(),
+            Money::new(minor_units as i64, &expense.amount.currency),
+        )
+    };
+    let split_type: SplitType = SplitType::try_from(expense.split_type)?;
+    let weights: Vec<SplitValue> = match split_type {
+        SplitType::Equal => users
+            .iter()
+            .map(|user| SplitValue::new(user.user_id.clone(), 1))
+            .collect(),
+        SplitType::Percent => {
+            validate_split_users(&expense.split_values, users)?;
+            let total: u64 = expense
+                .split_values
+                .iter()
+                .map(|value| value.value as u64)
+                .sum();
+            if total != 100 {
+                return Err(DataError::InvalidSplitPercentage);
+            }
+            expense.split_values.clone()
+        }
+        SplitType::Shares => {
+            validate_split_users(&expense.split_values, users)?;
+            if expense.split_values.iter().all(|value| value.value == 0) {
+                return Err(DataError::InvalidSplitShares);
+            }
+            expense.split_values.clone()
+        }
+        SplitType::Amount => {
+            validate_split_users(&expense.split_values, users)?;
+            let total: u64 = expense
+                .split_values
+                .iter()
+                .map(|value| value.value as u64)
+                .sum();
+            if total != amount {
+                return Err(DataError::SplitAmountMismatch);
+            }
+            return Ok(expense
+                .split_values
+                .iter()
+                .map(|value| share(&value.user_id, value.value as u64))
+                .collect());
+        }
+    };
+
+    let payers = expense.payers();
+    let split = distribute_by_weight(amount, &weights, rule, &payers[0].user_id)
+        .iter()
+        .map(|(user_id, minor_units)| share(user_id, *minor_units))
+        .collect();
+    Ok(split)
+}
+
+/// The users the expense is split between. Participants must be members of the
+/// group, when none are given every member takes part.
+fn select_participants(expense: &Expense, users: &[User]) -> Result<Vec<User>, DataError> {
+    if expense.participants.is_empty() {
+        return Ok(users.to_vec());
+    }
+    expense
+        .participants
+        .iter()
+        .map(|user_id| {
+            users
+                .iter()
+                .find(|user| &user.user_id == user_id)
+                .cloned()
+                .ok_or(DataError::LogicalError)
+        })
+        .collect()
+}
+
+/// Payers must be members of the group and their contributions must add up to
+/// the expense amount.
+fn validate_payers(expense: &Expense, users: &[User], amount: u64) -> Result<(), DataError> {
+    let payers = expense.payers();
+    for payer in &payers {
+        if !users.iter().any(|user| user.user_id == payer.user_id) {
+            return Err(DataError::LogicalError);
+        }
+    }
+    let total: u64 = payers.iter().map(|payer| payer.amount as u64).sum();
+    if total != amount {
+        return Err(DataError::PayerAmountMismatch);
+    }
+    Ok(())
+}
+
+/// Split values must belong to members taking part in the expense, with at
+/// most one value per member.
+fn validate_split_users(values: &[SplitValue], users: &[User]) -> Result<(), DataError> {
+    for (index, value) in values.iter().enumerate() {
+        if !users.iter().any(|user| user.user_id == value.user_id) {
+            return Err(DataError::LogicalError);
+        }
+        if values[..index]
+            .iter()
+            .any(|other| other.user_id == value.user_id)
+        {
+            return Err(DataError::DuplicateSplitUser);
+        }
+    }
+    Ok(())
+}
+
+/// Splits `amount` proportionally to the weights using the largest remainder
+/// method. Units left over after flooring go to the users with the largest
+/// fractional part, ties are broken by `rule`.
+pub(crate) fn distribute_by_weight(
+    amount: u64,
+    weights: &[SplitValue],
+    rule: RemainderRule,
+    payer: &UserId,
+) -> Vec<(UserId, u64)> {
+    let total_weight: u128 = weights.iter().map(|weight| weight.value as u128).sum();
+    if total_weight == 0 {
+        return weights
+            .iter()
+            .map(|weight| (weight.user_id.clone(), 0))
+            .collect();
+    }
+
+    let mut split: Vec<(UserId, u64)> = Vec::with_capacity(weights.len());
+    let mut fractions: Vec<(usize, u128)> = Vec::with_capacity(weights.len());
+    for (index, weight) in weights.iter().enumerate() {
+        let exact = amount as u128 * weight.value as u128;
+        split.push((weight.user_id.clone(), (exact / total_weight) as u64));
+        fractions.push((index, exact % total_weight));
+    }
+
+    let allocated: u64 = split.iter().map(|(_, share)| share).sum();
+    let remainder = (amount - allocated) as usize;
+    fractions.sort_by_key(|(index, fraction)| {
+        (
+            std::cmp::Reverse(*fraction),
+            remainder_priority(rule, *index, weights.len(), &weights[*index].user_id, payer),
+        )
+    });
+    for (index, _) in fractions.into_iter().take(remainder) {
+        split[index].1 += 1;
+    }
+    split
+}
+
+fn remainder_priority(
+    rule: RemainderRule,
+    index: usize,
+    count: usize,
+    user_id: &UserId,
+    payer: &UserId,
+) -> usize {
+    match rule {
+        RemainderRule::InOrder => index,
+        RemainderRule::Reverse => count - 1 - index,
+        RemainderRule::PayerFirst if user_id == payer => 0,
+        RemainderRule::PayerFirst => index + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::datamodel::ExpensePayer;
+
+    fn members() -> Vec<User> {
+        ["1", "2", "3"]
+            .iter()
+            .map(|id| User::new(id.to_string(), id.to_string(), id.to_string()))
+            .collect()
+    }
+
+    fn expense(amount: i64, split_type: SplitType, values: &[(&str, u32)]) -> Expense {
+        let amount = Money::new(amount, "INR");
+        let mut expense = Expense::new("1".into(), 1, amount, "test".into(), "".into());
+        expense.split_type = split_type.try_into().unwrap();
+        expense.split_values = values
+            .iter()
+            .map(|(user_id, value)| SplitValue::new(user_id.to_string(), *value))
+            .collect();
+        expense
+    }
+
+    #[test]
+    fn percentages_overflowing_u32_are_rejected() {
+        let expense = expense(100, SplitType::Percent, &[("1", u32::MAX), ("2", 101)]);
+        assert!(matches!(
+            calculate_split(&expense, &members(), RemainderRule::InOrder),
+            Err(DataError::InvalidSplitPercentage)
+        ));
+    }
+
+    #[test]
+    fn duplicate_split_users_are_rejected() {
+        let expense = expense(100, SplitType::Percent, &[("1", 50), ("1", 50)]);
+        assert!(matches!(
+            calculate_split(&expense, &members(), RemainderRule::InOrder),
+            Err(DataError::DuplicateSplitUser)
+        ));
+    }
+
+    #[test]
+    fn shares_add_up_to_the_amount_for_every_rule() {
+        for rule in [
+            RemainderRule::InOrder,
+            RemainderRule::Reverse,
+            RemainderRule::PayerFirst,
+        ] {
+            for amount in (1..=1000).chain([u32::MAX as i64]) {
+                let expenses = [
+                    expense(amount, SplitType::Equal, &[]),
+                    expense(amount, SplitType::Shares, &[("1", 1), ("2", 2), ("3", 4)]),
+                    expense(amount, SplitType::Shares, &[("1", 0), ("2", 3), ("3", 3)]),
+                    expense(
+                        amount,
+                        SplitType::Percent,
+                        &[("1", 33), ("2", 33), ("3", 34)],
+                    ),
+                    expense(amount, SplitType::Percent, &[("1", 1), ("2", 0), ("3", 99)]),
+                ];
+                for expense in expenses {
+                    let split = calculate_split(&expense, &members(), rule).unwrap();
+                    let total: i64 = split.iter().map(|(_, share)| share.minor_units).sum();
+                    assert_eq!(total, amount, "{:?} {:?}", rule, expense.split_values);
+                    assert!(split.iter().all(|(_, share)| share.currency == "INR"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn leftover_units_follow_the_rule() {
+        let mut expense = expense(100, SplitType::Equal, &[]);
+        expense.paid_by = vec![ExpensePayer::new("2".into(), 100)];
+        let shares = |rule| -> Vec<i64> {
+            calculate_split(&expense, &members(), rule)
+                .unwrap()
+                .into_iter()
+                .map(|(_, share)| share.minor_units)
+                .collect()
+        };
+        assert_eq!(shares(RemainderRule::InOrder), vec![34, 33, 33]);
+        assert_eq!(shares(RemainderRule::Reverse), vec![33, 33, 34]);
+        assert_eq!(shares(RemainderRule::PayerFirst), vec![33, 34, 33]);
+    }
+
+    #[test]
+    fn amounts_that_dont_fit_are_rejected() {
+        for amount in [0, -100, u32::MAX as i64 + 1] {
+            assert!(matches!(
+                calculate_split(
+                    &expense(amount, SplitType::Equal, &[]),
+                    &members(),
+                    RemainderRule::InOrder
+                ),
+                Err(DataError::InvalidAmount)
+            ));
+        }
+    }
+}
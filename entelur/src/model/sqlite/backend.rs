@@ -16,13 +16,19 @@ If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
     model::DataError,
+    model::audit,
+    model::balance::validate_settlement,
     model::datamodel::{
-    Datamodel, Expense, Group, GroupId, GroupMembership, SplitType, User, UserId
+    AuditAction, AuditEntity, AuditEntry, Budget, BudgetId, Category, CategoryId, CategoryKeyword, CurrencyRate, Datamodel, Expense, ExpensePayer, Group, GroupId, GroupMembership, Money, RecurringExpense, RecurringExpenseId, RemainderRule, Schedule, Settlement, SplitType, SplitValue, User, UserExpenses, UserId
     },
+    model::split::calculate_split,
     DbBackend,
 };
 
-use rusqlite::{params, Connection, Result};
+use super::queries;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, Result, ToSql};
 use std::{
     path::{Path, PathBuf}, rc::Rc
 };
@@ -31,77 +37,87 @@ use tokio::sync::RwLock;
 pub struct SqliteBackend {
     file_path: PathBuf,
     pub(super) rw_lock: RwLock<u32>,
+    remainder_rule: RemainderRule,
 }
 
 impl SqliteBackend {
-    pub fn new(file_path: std::path::PathBuf, readers: u32) -> SqliteBackend {
+    pub fn new(
+        file_path: std::path::PathBuf,
+        readers: u32,
+        remainder_rule: RemainderRule,
+    ) -> SqliteBackend {
         SqliteBackend {
             file_path,
             rw_lock: RwLock::new(readers),
+            remainder_rule,
         }
     }
 
+    pub fn remainder_rule(&self) -> RemainderRule {
+        self.remainder_rule
+    }
+
     pub fn get_new_connection(&self) -> Result<Connection> {
-        Connection::open(self.file_path.clone())
+        let connection = Connection::open(self.file_path.clone())?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        Ok(connection)
     }
 
-    async fn ensure_group_exists(
-        &self,
-        connection: &Connection,
-        group_id: u32,
-    ) -> Result<(), DataError> {
+    async fn ensure_group_exists(&self, group_id: u32) -> Result<(), DataError> {
         let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
         connection.query_row(
-            "SELECT group_id FROM EXPENSE_GROUP WHERE group_id = ?",
+            queries::SELECT_GROUP_ID,
             [group_id],
             |row| Ok(()),
         )?;
         Ok(())
     }
-    
-    fn calculate_split(&self, expense: &Expense, users: &[User]) -> Result<Vec<(String, u32)>, DataError> {
-        let amount = expense.amount;
-        let users_count = users.len() as u32;
-        let split_type: SplitType = SplitType::try_from(expense.split_type)?;
-        let mut split = vec![(String::new(),0); users_count as usize];
-        match split_type {
-            SplitType::Equal => {
-                let final_amount = amount / users_count;
-                for i in 0..users_count {
-                    split[i as usize].0 = users[i as usize].user_id.clone();
-                    split[i as usize].1 = final_amount;
-                }
-            },
-            _ => return Err(DataError::InvalidSplitType),
+
+    async fn ensure_user_exists(&self, user_id: &UserId) -> Result<(), DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        match connection.query_row(queries::SELECT_USER_ID, [user_id], |row| Ok(())) {
+            Ok(()) => Ok(()),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(DataError::UnknownUser),
+            Err(e) => Err(e.into()),
         }
-        Ok(split)
     }
-
 }
+
 impl Datamodel for SqliteBackend {
     async fn add_user(&self, user: User) -> Result<(), DataError> {
         let write_lock = self.rw_lock.write().await;
-        let connection = self.get_new_connection()?;
-        connection.execute(
-            "INSERT INTO User(user_id, username, name) VALUES (?1, ?2, ?3) ",
-            (user.user_id, user.username, user.name),
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        tx.execute(
+            queries::INSERT_USER,
+            (&user.user_id, &user.username, &user.name, user.utc_offset),
         )?;
+        insert_audit_entry(&tx, audit::added(user.user_id.clone(), &user))?;
+        tx.commit()?;
         Result::Ok(())
     }
 
     async fn add_group(&self, group: Group) -> std::prelude::v1::Result<(), DataError> {
+        self.ensure_user_exists(&group.created_by).await?;
         let write_lock = self.rw_lock.write().await;
-        let connection = self.get_new_connection()?;
-        connection.execute(
-            "INSERT INTO EXPENSE_GROUP(name, description, created_by) VALUES (?1, ?2, ?3) ",
-            (group.name, group.description, group.created_by.to_owned()),
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        tx.execute(
+            queries::INSERT_GROUP,
+            (&group.name, &group.description, group.created_by.to_owned(), &group.base_currency, group.simplify_debts),
         )?;
-        let group_id = connection.query_row("select last_insert_rowid()", [], |row| {
-            row.get::<usize,usize>(0)      })?;
-        connection.execute(
-            "INSERT INTO GROUP_MEMBERSHIP(user_id, group_id) VALUES (?1, ?2) ",
-            params![group_id, group.created_by.to_owned()],
+        let group_id = tx.query_row(queries::LAST_INSERT_ROWID, [], |row| {
+            row.get::<usize,GroupId>(0)      })?;
+        tx.execute(
+            queries::INSERT_MEMBERSHIP,
+            params![group.created_by.to_owned(), group_id],
         )?;
+        let group = Group { group_id, ..group };
+        insert_audit_entry(&tx, audit::added(group.created_by.clone(), &group))?;
+        insert_audit_entry(&tx, audit::added(group.created_by.clone(), &GroupMembership::new(group.created_by.clone(), group_id)))?;
+        tx.commit()?;
         Result::Ok(())
     }
 
@@ -109,54 +125,81 @@ impl Datamodel for SqliteBackend {
         &self,
         group_id: GroupId,
         user_id: UserId,
+        actor: UserId,
     ) -> std::prelude::v1::Result<(), DataError> {
-        let connection = self.get_new_connection()?;
-
-        self.ensure_group_exists(&connection, group_id).await?;
+        self.ensure_group_exists(group_id).await?;
+        self.ensure_user_exists(&user_id).await?;
         let write_lock = self.rw_lock.write().await;
-        connection.execute(
-            "INSERT INTO GROUP_MEMBERSHIP(user_id, group_id) VALUES (?1, ?2) ",
-            (user_id, group_id),
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        let added = tx.execute(
+            queries::INSERT_MEMBERSHIP_IF_MISSING,
+            (&user_id, group_id),
         )?;
+        if added > 0 {
+            insert_audit_entry(&tx, audit::added(actor, &GroupMembership::new(user_id, group_id)))?;
+        }
+        tx.commit()?;
 
         Result::Ok(())
     }
 
     async fn add_expense(&self, expense: Expense) -> std::prelude::v1::Result<(), DataError> {
+        let write_lock = self.rw_lock.write().await;
         let mut connection = self.get_new_connection()?;
-        self.ensure_group_exists(&connection, expense.group).await?;
-        let users = self.get_group_members(expense.group).await?;
+        let tx = connection.transaction()?;
+        // Members are read under the write lock so none can leave before the shares are stored
+        let split = split_for_group(&tx, &expense, self.remainder_rule)?;
+        match tx.execute(
+            queries::INSERT_EXPENSE,
+            params![expense.added_by, expense.group, expense.amount.minor_units, expense.amount.currency, expense.title, expense.description, expense.split_type, expense.created_at, expense.incurred_on, expense.category, expense.recurring],
+        ) {
+            Ok(_) => {}
+            // Only the occurrence index is unique among the columns written to EXPENSE
+            Err(rusqlite::Error::SqliteFailure(error, _)) if error.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE => {
+                return Err(DataError::DuplicateExpense);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let expense_id = tx.last_insert_rowid();
+        insert_shares(&tx, expense_id, &expense, split)?;
+        let expense = Expense { id: Some(expense_id as u32), ..expense };
+        insert_audit_entry(&tx, audit::added(expense.added_by.clone(), &expense))?;
+        tx.commit()?;
 
-        let split = self.calculate_split(&expense, &users)?;
+        Result::Ok(())
+    }
 
+    async fn update_expense(&self, expense: Expense, actor: UserId) -> Result<(), DataError> {
+        let Some(expense_id) = expense.id else {
+            return Err(DataError::LogicalError);
+        };
         let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
         let tx = connection.transaction()?;
-        tx.execute(
-            "INSERT INTO Expense(added_by, group, amount, title, description) VALUES (?1, ?2, ?3, ?4, ?5) ",
-            (expense.added_by, expense.group, expense.amount, expense.title, expense.description),
-        )?;
-        for (user_id, amount) in split {
-            tx.execute(
-                "INSERT INTO UserExpenses(user_id, expense_id, split) VALUES (?1, ?2, ?3) ",
-                (user_id, expense.id, amount),
-            )?;
-        }
+        let split = split_for_group(&tx, &expense, self.remainder_rule)?;
+        let Some(before) = load_expenses(&tx, queries::EXPENSE_BY_ID, expense_id)?.pop() else {
+            return Err(DataError::QueryReturnedNoRows);
+        };
+        replace_expense(&tx, expense_id, &expense, split)?;
+        insert_audit_entry(&tx, audit::updated(actor, &before, &expense))?;
         tx.commit()?;
 
-        Result::Ok(())
+        Ok(())
     }
 
     async fn get_user(&self, user_id: UserId) -> std::prelude::v1::Result<User, DataError> {
         let read_lock = self.rw_lock.read().await;
         let connection = self.get_new_connection()?;
         let user = connection.query_row(
-            "SELECT user_id, username, name FROM User WHERE user_id = ?",
+            queries::SELECT_USER,
             [user_id],
             |row| {
                 Ok(User {
                     user_id: row.get(0)?,
                     username: row.get(1)?,
                     name: row.get(2)?,
+                    utc_offset: row.get(3)?,
                 })
             },
         )?;
@@ -168,7 +211,7 @@ impl Datamodel for SqliteBackend {
         let read_lock = self.rw_lock.read().await;
         let connection = self.get_new_connection()?;
         let group = connection.query_row(
-            "SELECT group_id, name, description, created_by FROM ExpenseGroup WHERE group_id = ?",
+            queries::SELECT_GROUP,
             [group_id],
             |row| {
                 Ok(Group {
@@ -176,6 +219,8 @@ impl Datamodel for SqliteBackend {
                     name: row.get(1)?,
                     description: row.get(2)?,
                     created_by: row.get(3)?,
+                    base_currency: row.get(4)?,
+                    simplify_debts: row.get(5)?,
                 })
             },
         )?;
@@ -186,92 +231,93 @@ impl Datamodel for SqliteBackend {
     async fn get_group_members(&self, group_id: GroupId) -> Result<Vec<User>, DataError> {
         let read_lock = self.rw_lock.read().await;
         let connection = self.get_new_connection()?;
-        let mut members_query = connection.prepare("SELECT user_id, username, name FROM User WHERE user_id IN (SELECT user_id FROM GROUP_MEMBERSHIP WHERE group_id = ?)")?;
-        let members_query_result = members_query.query_map([group_id], |row| {
-            Ok(User {
-                user_id: row.get(0)?,
-                username: row.get(1)?,
-                name: row.get(2)?,
-            })
-        })?;
-        let mut members_list: Vec<User> = Vec::new();
-        for member_encap in members_query_result {
-            members_list.push(member_encap?);
-        }
-        Result::Ok(members_list)
+        load_group_members(&connection, group_id)
     }
 
     async fn get_expenses(&self, group_id: GroupId) -> Result<Vec<Expense>, DataError> {
         let read_lock = self.rw_lock.read().await;
         let connection = self.get_new_connection()?;
-
-        let mut expenses_query = connection.prepare("SELECT id, added_by, group, amount, title, description, split_type FROM Expense WHERE group = ?")?;
-        let expenses_query_result = expenses_query.query_map([group_id], |row| {
-            Ok(Expense {
-                id: row.get(0)?,
-                added_by: row.get(1)?,
-                group: row.get(2)?,
-                amount: row.get(3)?,
-                title: row.get(4)?,
-                description: row.get(5)?,
-                split_type: row.get(6)?,
-            })
-        })?;
-        let mut expenses_list: Vec<Expense> = Vec::new();
-        for expense_encap in expenses_query_result {
-            expenses_list.push(expense_encap?);
-        }
-        Result::Ok(expenses_list)
+        load_expenses(&connection, queries::EXPENSES_OF_GROUP, group_id)
     }
 
     async fn remove_user_from_group(
         &self,
         group_id: GroupId,
         user_id: UserId,
+        actor: UserId,
     ) -> std::prelude::v1::Result<(), DataError> {
         let write_lock = self.rw_lock.write().await;
-        let connection = self.get_new_connection()?;
-        connection.execute(
-            "DELETE FROM GROUP_MEMBERSHIP WHERE group_id = ?1 AND user_id = ?2",
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        let deleted = tx.execute(
+            queries::DELETE_MEMBERSHIP,
             params![group_id, user_id],
         )?;
+        if deleted > 0 {
+            insert_audit_entry(&tx, audit::deleted(actor, &GroupMembership::new(user_id, group_id)))?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
-    async fn delete_group(&self, group_id: GroupId) -> std::prelude::v1::Result<(), DataError> {
+    async fn delete_group(&self, group_id: GroupId, actor: UserId) -> std::prelude::v1::Result<(), DataError> {
+        let group = self.get_group(group_id).await?;
         let write_lock = self.rw_lock.write().await;
         let mut connection = self.get_new_connection()?;
         let tx = connection.transaction()?;
-        tx.execute(
-            "DELETE FROM GROUP_MEMBERSHIP WHERE group_id = ?1",
-            params![group_id],
-        )?;
-        tx.execute(
-            "DELETE FROM ExpenseGroup WHERE group_id = ?1",
-            params![group_id],
-        )?;
+        // The group and its expenses are kept until purged, settlements, categories,
+        // recurring expenses and budgets go with the group then
+        let deleted_at = Utc::now();
+        tx.execute(queries::SOFT_DELETE_GROUP, params![group_id, deleted_at])?;
+        tx.execute(queries::SOFT_DELETE_GROUP_EXPENSES, params![group_id, deleted_at])?;
+        tx.execute(queries::DELETE_GROUP_MEMBERSHIPS, params![group_id])?;
+        tx.execute(queries::CANCEL_GROUP_RECURRING_EXPENSES, params![group_id])?;
+        insert_audit_entry(&tx, audit::deleted(actor, &group))?;
         tx.commit()?;
         Ok(())
     }
 
     async fn delete_user(&self, user_id: UserId) -> std::prelude::v1::Result<(), DataError> {
+        let user = self.get_user(user_id.clone()).await?;
         let write_lock = self.rw_lock.write().await;
         let mut connection = self.get_new_connection()?;
         let tx = connection.transaction()?;
-        tx.execute("DELETE FROM User WHERE user_id = ?1", params![user_id])?;
+        match tx.execute(queries::DELETE_USER, params![user_id]) {
+            Ok(_) => {}
+            // Ledger rows restrict deleting the users they refer to. SQLite reports
+            // ON DELETE RESTRICT as a trigger failure rather than a foreign key one
+            Err(rusqlite::Error::SqliteFailure(error, _)) if matches!(error.extended_code, rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY | rusqlite::ffi::SQLITE_CONSTRAINT_TRIGGER) => {
+                return Err(DataError::UserHasHistory);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        insert_audit_entry(&tx, audit::deleted(user_id, &user))?;
         tx.commit()?;
         Ok(())
     }
 
-    async fn delete_expense(&self, expense_id: u32) -> std::prelude::v1::Result<(), DataError> {
+    async fn delete_expense(&self, expense_id: u32, actor: UserId) -> std::prelude::v1::Result<(), DataError> {
         let write_lock = self.rw_lock.write().await;
         let mut connection = self.get_new_connection()?;
         let tx = connection.transaction()?;
-        tx.execute("DELETE FROM Expense WHERE id = ?1", params![expense_id])?;
+        let expense = soft_delete_expense(&tx, expense_id)?;
+        insert_audit_entry(&tx, audit::deleted(actor, &expense))?;
         tx.commit()?;
         Ok(())
     }
 
+    async fn purge_deleted_expenses(&self, deleted_before: DateTime<Utc>) -> Result<usize, DataError> {
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        // Shares and payers are removed along with the expense, and everything
+        // left in a group along with the group
+        let purged = tx.execute(queries::PURGE_DELETED_EXPENSES, [deleted_before])?;
+        tx.execute(queries::PURGE_DELETED_GROUPS, [deleted_before])?;
+        tx.commit()?;
+        Ok(purged)
+    }
+
     async fn get_membership(
         &self,
         user_id: UserId,
@@ -279,7 +325,7 @@ impl Datamodel for SqliteBackend {
         let read_lock = self.rw_lock.read().await;
         let connection = self.get_new_connection()?;
         let mut membership_query =
-            connection.prepare("SELECT group_id FROM GROUP_MEMBERSHIP WHERE user_id = ?")?;
+            connection.prepare(queries::SELECT_MEMBERSHIPS)?;
         let membership_query_result = membership_query.query_map([user_id], |row| {
             Ok(GroupMembership {
                 group_id: row.get(0)?,
@@ -297,7 +343,478 @@ impl Datamodel for SqliteBackend {
         &self,
         user_id: UserId,
     ) -> std::prelude::v1::Result<Vec<Expense>, DataError> {
-        todo!()
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        load_expenses(
+            &connection,
+            queries::EXPENSES_OF_USER,
+            user_id,
+        )
+    }
+
+    async fn get_expense_splits(&self, group_id: GroupId) -> Result<Vec<UserExpenses>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut splits_query = connection.prepare(queries::SELECT_GROUP_SHARES)?;
+        let splits_query_result = splits_query.query_map([group_id], |row| {
+            Ok(UserExpenses {
+                user_id: row.get(0)?,
+                expenses_id: row.get(1)?,
+                split: row.get(2)?,
+            })
+        })?;
+        let mut splits_list: Vec<UserExpenses> = Vec::new();
+        for split_encap in splits_query_result {
+            splits_list.push(split_encap?);
+        }
+        Ok(splits_list)
+    }
+
+    async fn add_settlement(&self, settlement: Settlement, actor: UserId) -> Result<(), DataError> {
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        tx.query_row(queries::SELECT_GROUP_ID, [settlement.group], |row| Ok(()))?;
+        validate_settlement(&settlement, &load_group_members(&tx, settlement.group)?)?;
+        tx.execute(
+            queries::INSERT_SETTLEMENT,
+            params![settlement.group, settlement.payer, settlement.payee, settlement.amount, settlement.settled_at],
+        )?;
+        let settlement = Settlement { id: Some(tx.last_insert_rowid() as u32), ..settlement };
+        insert_audit_entry(&tx, audit::added(actor, &settlement))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn get_settlements(&self, group_id: GroupId) -> Result<Vec<Settlement>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut settlements_query = connection.prepare(queries::SELECT_SETTLEMENTS)?;
+        let settlements_query_result = settlements_query.query_map([group_id], |row| {
+            Ok(Settlement {
+                id: row.get(0)?,
+                group: row.get(1)?,
+                payer: row.get(2)?,
+                payee: row.get(3)?,
+                amount: row.get(4)?,
+                settled_at: row.get(5)?,
+            })
+        })?;
+        let mut settlements_list: Vec<Settlement> = Vec::new();
+        for settlement_encap in settlements_query_result {
+            settlements_list.push(settlement_encap?);
+        }
+        Ok(settlements_list)
+    }
+
+    async fn set_group_simplify_debts(&self, group_id: GroupId, simplify: bool, actor: UserId) -> Result<(), DataError> {
+        let before = self.get_group(group_id).await?;
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        tx.execute(
+            queries::UPDATE_SIMPLIFY_DEBTS,
+            params![simplify, group_id],
+        )?;
+        let after = Group { simplify_debts: simplify, ..before.clone() };
+        insert_audit_entry(&tx, audit::updated(actor, &before, &after))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn set_user_utc_offset(&self, user_id: UserId, utc_offset: i32) -> Result<(), DataError> {
+        let before = self.get_user(user_id.clone()).await?;
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        let updated = tx.execute(
+            queries::UPDATE_UTC_OFFSET,
+            params![utc_offset, user_id],
+        )?;
+        if updated == 0 {
+            return Err(DataError::QueryReturnedNoRows);
+        }
+        let after = User { utc_offset, ..before.clone() };
+        insert_audit_entry(&tx, audit::updated(user_id, &before, &after))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn add_currency_rate(&self, rate: CurrencyRate) -> Result<(), DataError> {
+        let write_lock = self.rw_lock.write().await;
+        let connection = self.get_new_connection()?;
+        connection.execute(
+            queries::INSERT_CURRENCY_RATE,
+            params![rate.currency, rate.base_currency, rate.rate_date, rate.rate],
+        )?;
+        Ok(())
+    }
+
+    async fn get_currency_rates(&self, base_currency: String) -> Result<Vec<CurrencyRate>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut rates_query = connection.prepare(queries::SELECT_CURRENCY_RATES)?;
+        let rates_query_result = rates_query.query_map([base_currency], |row| {
+            Ok(CurrencyRate {
+                currency: row.get(0)?,
+                base_currency: row.get(1)?,
+                rate_date: row.get(2)?,
+                rate: row.get(3)?,
+            })
+        })?;
+        let mut rates_list: Vec<CurrencyRate> = Vec::new();
+        for rate_encap in rates_query_result {
+            rates_list.push(rate_encap?);
+        }
+        Ok(rates_list)
+    }
+
+    async fn add_category(&self, category: Category, actor: UserId) -> Result<CategoryId, DataError> {
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        let exists: bool = tx.query_row(
+            queries::CATEGORY_EXISTS,
+            params![category.group, category.name],
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(DataError::LogicalError);
+        }
+        tx.execute(
+            queries::INSERT_CATEGORY,
+            params![category.group, category.name],
+        )?;
+        let category_id = tx.last_insert_rowid() as CategoryId;
+        insert_audit_entry(&tx, audit::added(actor, &Category { id: Some(category_id), ..category }))?;
+        tx.commit()?;
+        Ok(category_id)
+    }
+
+    async fn get_categories(&self, group_id: GroupId) -> Result<Vec<Category>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut categories_query = connection.prepare(queries::SELECT_CATEGORIES)?;
+        let categories_query_result = categories_query.query_map([group_id], |row| {
+            Ok(Category {
+                id: row.get(0)?,
+                group: row.get(1)?,
+                name: row.get(2)?,
+            })
+        })?;
+        let mut categories_list: Vec<Category> = Vec::new();
+        for category_encap in categories_query_result {
+            categories_list.push(category_encap?);
+        }
+        Ok(categories_list)
+    }
+
+    async fn add_category_keyword(&self, keyword: CategoryKeyword, actor: UserId) -> Result<(), DataError> {
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        tx.execute(
+            queries::INSERT_CATEGORY_KEYWORD,
+            params![keyword.keyword, keyword.group, keyword.category],
+        )?;
+        insert_audit_entry(&tx, audit::added(actor, &keyword))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn get_category_keywords(&self, group_id: GroupId) -> Result<Vec<CategoryKeyword>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut keywords_query = connection.prepare(queries::SELECT_CATEGORY_KEYWORDS)?;
+        let keywords_query_result = keywords_query.query_map([group_id], |row| {
+            Ok(CategoryKeyword {
+                keyword: row.get(0)?,
+                group: row.get(1)?,
+                category: row.get(2)?,
+            })
+        })?;
+        let mut keywords_list: Vec<CategoryKeyword> = Vec::new();
+        for keyword_encap in keywords_query_result {
+            keywords_list.push(keyword_encap?);
+        }
+        Ok(keywords_list)
+    }
+
+    async fn add_recurring_expense(&self, recurring: RecurringExpense) -> Result<RecurringExpenseId, DataError> {
+        self.ensure_group_exists(recurring.template.group).await?;
+        let template = &recurring.template;
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        tx.execute(
+            queries::INSERT_RECURRING_EXPENSE,
+            params![template.added_by, template.group, template.amount.minor_units, template.amount.currency, template.title, template.description, template.split_type, template.category, u32::from(recurring.schedule), recurring.start_date, recurring.occurrences, recurring.active],
+        )?;
+        let recurring_id = tx.last_insert_rowid();
+        // One row per participant, carrying their split value when the split isn't equal
+        let mut split_users = template.participants.clone();
+        for split_value in &template.split_values {
+            if !split_users.contains(&split_value.user_id) {
+                split_users.push(split_value.user_id.clone());
+            }
+        }
+        for user_id in split_users {
+            let value = template
+                .split_values
+                .iter()
+                .find(|split_value| split_value.user_id == user_id)
+                .map(|split_value| split_value.value)
+                .unwrap_or_default();
+            tx.execute(
+                queries::INSERT_RECURRING_SPLIT,
+                (recurring_id, user_id, value),
+            )?;
+        }
+        for payer in &template.paid_by {
+            tx.execute(
+                queries::INSERT_RECURRING_PAYER,
+                (recurring_id, &payer.user_id, payer.amount),
+            )?;
+        }
+        let recurring = RecurringExpense { id: Some(recurring_id as RecurringExpenseId), ..recurring.clone() };
+        insert_audit_entry(&tx, audit::added(recurring.template.added_by.clone(), &recurring))?;
+        tx.commit()?;
+        Ok(recurring_id as RecurringExpenseId)
+    }
+
+    async fn get_recurring_expenses(&self) -> Result<Vec<RecurringExpense>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut recurring_query = connection.prepare(queries::SELECT_RECURRING_EXPENSES)?;
+        let recurring_query_result = recurring_query.query_map([], |row| {
+            let amount = Money::new(row.get(3)?, &row.get::<usize, String>(4)?);
+            let mut template = Expense::new(row.get(1)?, row.get(2)?, amount, row.get(5)?, row.get(6)?);
+            template.split_type = row.get(7)?;
+            template.category = row.get(8)?;
+            Ok((row.get::<usize, u32>(9)?, RecurringExpense {
+                id: row.get(0)?,
+                template,
+                schedule: Schedule::Daily,
+                start_date: row.get(10)?,
+                occurrences: row.get(11)?,
+                active: row.get(12)?,
+            }))
+        })?;
+        let mut recurring_list: Vec<RecurringExpense> = Vec::new();
+        for recurring_encap in recurring_query_result {
+            let (schedule, recurring) = recurring_encap?;
+            recurring_list.push(RecurringExpense {
+                schedule: schedule.try_into()?,
+                ..recurring
+            });
+        }
+
+        let mut splits_query = connection.prepare(queries::SELECT_RECURRING_SPLITS)?;
+        let splits_query_result = splits_query.query_map([], |row| {
+            Ok((row.get::<usize, u32>(0)?, SplitValue::new(row.get(1)?, row.get(2)?)))
+        })?;
+        for split_encap in splits_query_result {
+            let (recurring_id, split_value) = split_encap?;
+            if let Some(recurring) = recurring_list.iter_mut().find(|recurring| recurring.id == Some(recurring_id)) {
+                recurring.template.participants.push(split_value.user_id.clone());
+                if !matches!(SplitType::try_from(recurring.template.split_type)?, SplitType::Equal) {
+                    recurring.template.split_values.push(split_value);
+                }
+            }
+        }
+
+        let mut payers_query = connection.prepare(queries::SELECT_RECURRING_PAYERS)?;
+        let payers_query_result = payers_query.query_map([], |row| {
+            Ok((row.get::<usize, u32>(0)?, ExpensePayer::new(row.get(1)?, row.get(2)?)))
+        })?;
+        for payer_encap in payers_query_result {
+            let (recurring_id, payer) = payer_encap?;
+            if let Some(recurring) = recurring_list.iter_mut().find(|recurring| recurring.id == Some(recurring_id)) {
+                recurring.template.paid_by.push(payer);
+            }
+        }
+        Ok(recurring_list)
+    }
+
+    async fn set_recurring_occurrences(&self, recurring_id: RecurringExpenseId, occurrences: u32) -> Result<(), DataError> {
+        let write_lock = self.rw_lock.write().await;
+        let connection = self.get_new_connection()?;
+        let updated = connection.execute(
+            queries::UPDATE_RECURRING_OCCURRENCES,
+            (occurrences, recurring_id),
+        )?;
+        if updated == 0 {
+            return Err(DataError::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    async fn cancel_recurring_expense(&self, recurring_id: RecurringExpenseId, actor: UserId) -> Result<(), DataError> {
+        let Some(before) = self
+            .get_recurring_expenses()
+            .await?
+            .into_iter()
+            .find(|recurring| recurring.id == Some(recurring_id))
+        else {
+            return Err(DataError::QueryReturnedNoRows);
+        };
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        let updated = tx.execute(
+            queries::CANCEL_RECURRING_EXPENSE,
+            [recurring_id],
+        )?;
+        if updated == 0 {
+            return Err(DataError::QueryReturnedNoRows);
+        }
+        let after = RecurringExpense { active: false, ..before.clone() };
+        insert_audit_entry(&tx, audit::updated(actor, &before, &after))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn set_budget(&self, budget: Budget, actor: UserId) -> Result<(), DataError> {
+        self.ensure_group_exists(budget.group).await?;
+        let before = self
+            .get_budgets(budget.group)
+            .await?
+            .into_iter()
+            .find(|existing| existing.category == budget.category);
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        tx.execute(
+            queries::DELETE_BUDGET,
+            params![budget.group, budget.category],
+        )?;
+        tx.execute(
+            queries::INSERT_BUDGET,
+            params![budget.group, budget.category, budget.amount],
+        )?;
+        let budget = Budget { id: Some(tx.last_insert_rowid() as BudgetId), ..budget };
+        let entry = match before {
+            Some(before) => audit::updated(actor, &before, &budget),
+            None => audit::added(actor, &budget),
+        };
+        insert_audit_entry(&tx, entry)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn get_budgets(&self, group_id: GroupId) -> Result<Vec<Budget>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut budgets_query = connection.prepare(queries::SELECT_BUDGETS)?;
+        let budgets_query_result = budgets_query.query_map([group_id], |row| {
+            Ok(Budget {
+                id: row.get(0)?,
+                group: row.get(1)?,
+                category: row.get(2)?,
+                amount: row.get(3)?,
+            })
+        })?;
+        let mut budgets_list: Vec<Budget> = Vec::new();
+        for budget_encap in budgets_query_result {
+            budgets_list.push(budget_encap?);
+        }
+        Ok(budgets_list)
+    }
+
+    async fn remove_budget(&self, group_id: GroupId, category: Option<CategoryId>, actor: UserId) -> Result<(), DataError> {
+        let Some(budget) = self
+            .get_budgets(group_id)
+            .await?
+            .into_iter()
+            .find(|budget| budget.category == category)
+        else {
+            return Err(DataError::QueryReturnedNoRows);
+        };
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        let deleted = tx.execute(
+            queries::DELETE_BUDGET,
+            params![group_id, category],
+        )?;
+        if deleted == 0 {
+            return Err(DataError::QueryReturnedNoRows);
+        }
+        insert_audit_entry(&tx, audit::deleted(actor, &budget))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn get_audit_log(&self, group_id: GroupId, limit: u32) -> Result<Vec<AuditEntry>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut audit_query = connection.prepare(queries::SELECT_AUDIT_LOG)?;
+        let audit_query_result = audit_query.query_map([group_id, limit], read_audit_entry)?;
+        let mut audit_log: Vec<AuditEntry> = Vec::new();
+        for entry_encap in audit_query_result {
+            audit_log.push(entry_encap??);
+        }
+        Ok(audit_log)
+    }
+
+    async fn get_last_undoable_change(&self, actor: UserId) -> Result<Option<AuditEntry>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut audit_query = connection.prepare(queries::SELECT_LAST_UNDOABLE_CHANGE)?;
+        let mut audit_query_result = audit_query.query_map(params![actor, u32::from(AuditEntity::Expense)], read_audit_entry)?;
+        match audit_query_result.next() {
+            Some(entry_encap) => Ok(Some(entry_encap??)),
+            None => Ok(None),
+        }
+    }
+
+    async fn undo_change(&self, entry: AuditEntry, actor: UserId) -> Result<(), DataError> {
+        let (Some(entry_id), Ok(expense_id), AuditEntity::Expense) = (entry.id, entry.entity_id.parse::<u32>(), entry.entity) else {
+            return Err(DataError::LogicalError);
+        };
+        // Undoing an edit stores the expense as it was before, with its shares worked out again
+        let restored = match (entry.action, entry.before.as_deref()) {
+            (AuditAction::Update, Some(snapshot)) => {
+                let expense = audit::expense_from_snapshot(expense_id, snapshot).ok_or(DataError::LogicalError)?;
+                let users = self.get_group_members(expense.group).await?;
+                let split = calculate_split(&expense, &users, self.remainder_rule)?;
+                Some((expense, split))
+            }
+            _ => None,
+        };
+
+        let write_lock = self.rw_lock.write().await;
+        let mut connection = self.get_new_connection()?;
+        let tx = connection.transaction()?;
+        let conflicting: bool = tx.query_row(
+            queries::SELECT_CONFLICTING_CHANGE,
+            params![entry_id, u32::from(entry.entity), entry.entity_id],
+            |row| row.get(0),
+        )?;
+        if conflicting {
+            return Err(DataError::ConflictingChange);
+        }
+        let revert = match (entry.action, restored) {
+            (AuditAction::Add, _) => audit::deleted(actor, &soft_delete_expense(&tx, expense_id)?),
+            (AuditAction::Delete, _) => {
+                let restored = tx.execute(queries::RESTORE_EXPENSE, [expense_id])?;
+                let Some(expense) = load_expenses(&tx, queries::EXPENSE_BY_ID, expense_id)?.pop().filter(|_| restored > 0) else {
+                    return Err(DataError::QueryReturnedNoRows);
+                };
+                audit::added(actor, &expense)
+            }
+            (AuditAction::Update, Some((expense, split))) => {
+                let Some(current) = load_expenses(&tx, queries::EXPENSE_BY_ID, expense_id)?.pop() else {
+                    return Err(DataError::QueryReturnedNoRows);
+                };
+                replace_expense(&tx, expense_id, &expense, split)?;
+                audit::updated(actor, &current, &expense)
+            }
+            (AuditAction::Update, None) => return Err(DataError::LogicalError),
+        };
+        insert_audit_entry(&tx, AuditEntry { reverts: Some(entry_id), ..revert })?;
+        tx.commit()?;
+        Ok(())
     }
 }
 
@@ -329,8 +846,200 @@ impl From<rusqlite::Error> for DataError {
                 msg,
                 sql,
                 offset,
-            } => panic!("{} {} {} {}", error, msg, sql, offset),
+            } => {
+                log::error!("Invalid query at offset {}: {} ({}) in {}", offset, msg, error, sql);
+                DataError::InvalidQuery
+            }
             _ => DataError::UnknownError,
         }
     }
 }
+
+/// Works out the shares of `expense` among the members of its group, which must
+/// not be deleted.
+fn split_for_group(connection: &Connection, expense: &Expense, remainder_rule: RemainderRule) -> Result<Vec<(UserId, Money)>, DataError> {
+    connection.query_row(queries::SELECT_GROUP_ID, [expense.group], |row| Ok(()))?;
+    let users = load_group_members(connection, expense.group)?;
+    calculate_split(expense, &users, remainder_rule)
+}
+
+fn load_group_members(connection: &Connection, group_id: GroupId) -> Result<Vec<User>, DataError> {
+    let mut members_query = connection.prepare(queries::SELECT_GROUP_MEMBERS)?;
+    let members_query_result = members_query.query_map([group_id], |row| {
+        Ok(User {
+            user_id: row.get(0)?,
+            username: row.get(1)?,
+            name: row.get(2)?,
+            utc_offset: row.get(3)?,
+        })
+    })?;
+    let mut members_list: Vec<User> = Vec::new();
+    for member_encap in members_query_result {
+        members_list.push(member_encap?);
+    }
+    Ok(members_list)
+}
+
+/// Appends `entry` to the audit log, in the same transaction as the change it
+/// records.
+fn insert_audit_entry(connection: &Connection, entry: AuditEntry) -> Result<(), DataError> {
+    connection.execute(
+        queries::INSERT_AUDIT_ENTRY,
+        params![entry.group, entry.actor, u32::from(entry.entity), entry.entity_id, u32::from(entry.action), entry.before, entry.after, entry.created_at, entry.reverts],
+    )?;
+    Ok(())
+}
+
+/// Reads an audit entry selected with the columns in `audit_columns!`. The entity and action
+/// are checked separately so an unknown value is reported as a `DataError`.
+fn read_audit_entry(row: &rusqlite::Row) -> Result<Result<AuditEntry, DataError>> {
+    let entity = AuditEntity::try_from(row.get::<usize, u32>(3)?);
+    let action = AuditAction::try_from(row.get::<usize, u32>(5)?);
+    let (Ok(entity), Ok(action)) = (entity, action) else {
+        return Ok(Err(DataError::InvalidColumnType));
+    };
+    Ok(Ok(AuditEntry {
+        id: row.get(0)?,
+        group: row.get(1)?,
+        actor: row.get(2)?,
+        entity,
+        entity_id: row.get(4)?,
+        action,
+        before: row.get(6)?,
+        after: row.get(7)?,
+        created_at: row.get(8)?,
+        reverts: row.get(9)?,
+    }))
+}
+
+/// Marks the expense deleted and returns it as it was stored. Its shares are
+/// kept until the expense is purged.
+fn soft_delete_expense(connection: &Connection, expense_id: u32) -> Result<Expense, DataError> {
+    let Some(expense) = load_expenses(connection, queries::EXPENSE_BY_ID, expense_id)?.pop() else {
+        return Err(DataError::QueryReturnedNoRows);
+    };
+    connection.execute(
+        queries::SOFT_DELETE_EXPENSE,
+        params![expense_id, Utc::now()],
+    )?;
+    Ok(expense)
+}
+
+/// Overwrites a stored expense with `expense` and replaces its shares with `split`.
+fn replace_expense(
+    connection: &Connection,
+    expense_id: u32,
+    expense: &Expense,
+    split: Vec<(UserId, Money)>,
+) -> Result<(), DataError> {
+    let updated = connection.execute(
+        queries::UPDATE_EXPENSE,
+        params![expense.amount.minor_units, expense.amount.currency, expense.title, expense.description, expense.split_type, expense.incurred_on, expense.category, expense_id],
+    )?;
+    if updated == 0 {
+        return Err(DataError::QueryReturnedNoRows);
+    }
+    connection.execute(queries::DELETE_SHARES, [expense_id])?;
+    connection.execute(queries::DELETE_PAYERS, [expense_id])?;
+    insert_shares(connection, expense_id as i64, expense, split)
+}
+
+/// Stores the share of every participant of an expense, along with the split
+/// value they were given, and who paid for it.
+fn insert_shares(
+    connection: &Connection,
+    expense_id: i64,
+    expense: &Expense,
+    split: Vec<(UserId, Money)>,
+) -> Result<(), DataError> {
+    for (user_id, amount) in split {
+        let value = expense
+            .split_values
+            .iter()
+            .find(|split_value| split_value.user_id == user_id)
+            .map(|split_value| split_value.value);
+        connection.execute(
+            queries::INSERT_SHARE,
+            params![user_id, expense_id, amount.minor_units, value],
+        )?;
+    }
+    for payer in expense.payers() {
+        connection.execute(
+            queries::INSERT_PAYER,
+            (expense_id, payer.user_id, payer.amount),
+        )?;
+    }
+    Ok(())
+}
+
+/// Loads the expenses whose ids are returned by `id_query` along with their
+/// payers and participants. `id_query` takes a single parameter.
+fn load_expenses<P: ToSql>(
+    connection: &Connection,
+    id_query: &str,
+    param: P,
+) -> Result<Vec<Expense>, DataError> {
+    let mut expenses_query = connection.prepare(&queries::select_expenses(id_query))?;
+    let expenses_query_result = expenses_query.query_map([&param], |row| {
+        Ok(Expense {
+            id: row.get(0)?,
+            added_by: row.get(1)?,
+            group: row.get(2)?,
+            amount: Money::new(row.get(3)?, &row.get::<usize, String>(4)?),
+            title: row.get(5)?,
+            description: row.get(6)?,
+            split_type: row.get(7)?,
+            split_values: Vec::new(),
+            paid_by: Vec::new(),
+            participants: Vec::new(),
+            created_at: row.get(8)?,
+            incurred_on: row.get(9)?,
+            category: row.get(10)?,
+            recurring: row.get(11)?,
+        })
+    })?;
+    let mut expenses_list: Vec<Expense> = Vec::new();
+    for expense_encap in expenses_query_result {
+        expenses_list.push(expense_encap?);
+    }
+
+    let mut payers_query = connection.prepare(&queries::select_payers(id_query))?;
+    let payers_query_result = payers_query.query_map([&param], |row| {
+        Ok((row.get::<usize, u32>(0)?, ExpensePayer::new(row.get(1)?, row.get(2)?)))
+    })?;
+    for payer_encap in payers_query_result {
+        let (expense_id, payer) = payer_encap?;
+        if let Some(expense) = expenses_list.iter_mut().find(|expense| expense.id == Some(expense_id)) {
+            expense.paid_by.push(payer);
+        }
+    }
+
+    let mut participants_query = connection.prepare(&queries::select_shares(id_query))?;
+    let participants_query_result = participants_query.query_map([&param], |row| {
+        Ok((row.get::<usize, u32>(0)?, row.get::<usize, UserId>(1)?, row.get::<usize, u32>(2)?, row.get::<usize, Option<u32>>(3)?))
+    })?;
+    let mut shares: Vec<(u32, SplitValue)> = Vec::new();
+    for participant_encap in participants_query_result {
+        let (expense_id, user_id, share, value) = participant_encap?;
+        if let Some(expense) = expenses_list.iter_mut().find(|expense| expense.id == Some(expense_id)) {
+            expense.participants.push(user_id.clone());
+            if let Some(value) = value {
+                expense.split_values.push(SplitValue::new(user_id.clone(), value));
+            }
+            shares.push((expense_id, SplitValue::new(user_id, share)));
+        }
+    }
+    // Expenses added before split values were stored keep their shares as exact amounts
+    for expense in expenses_list.iter_mut() {
+        let is_equal = matches!(SplitType::try_from(expense.split_type), Ok(SplitType::Equal));
+        if !is_equal && expense.split_values.is_empty() {
+            expense.split_type = SplitType::Amount.try_into()?;
+            expense.split_values = shares
+                .iter()
+                .filter(|(expense_id, _)| expense.id == Some(*expense_id))
+                .map(|(_, share)| share.clone())
+                .collect();
+        }
+    }
+    Ok(expenses_list)
+}
@@ -24,7 +24,7 @@ use crate::model::sqlite::backend::SqliteBackend;
 
 const C_MIGRATION_TABLE_NAME: &str = "migrations";
 
-static C_MIGRATION_LIST: [Migration;1] = [
+static C_MIGRATION_LIST: [Migration;14] = [
     Migration {
         version: 1,
         sql_statements: "
@@ -34,6 +34,197 @@ static C_MIGRATION_LIST: [Migration;1] = [
         CREATE TABLE EXPENSE(id INTEGER PRIMARY KEY AUTOINCREMENT,added_by STRING, group_id STRING, amount INTEGER, title STRING, description STRING, split_type INTEGER);
         CREATE TABLE USER_EXPENSES(user_id STRING, expense_id INTEGER, split INTEGER);
         "
+    },
+    Migration {
+        version: 2,
+        sql_statements: "
+        ALTER TABLE EXPENSE ADD COLUMN currency TEXT NOT NULL DEFAULT 'INR';
+        ALTER TABLE EXPENSE_GROUP ADD COLUMN base_currency TEXT NOT NULL DEFAULT 'INR';
+        CREATE TABLE CURRENCY_RATE(currency TEXT NOT NULL, base_currency TEXT NOT NULL, rate_date DATE NOT NULL, rate INTEGER NOT NULL, PRIMARY KEY(currency, base_currency, rate_date));
+        "
+    },
+    Migration {
+        version: 3,
+        sql_statements: "
+        ALTER TABLE EXPENSE_GROUP ADD COLUMN simplify_debts INTEGER NOT NULL DEFAULT 1;
+        "
+    },
+    Migration {
+        version: 4,
+        sql_statements: "
+        CREATE TABLE SETTLEMENT(id INTEGER PRIMARY KEY AUTOINCREMENT, group_id INTEGER NOT NULL, payer TEXT NOT NULL, payee TEXT NOT NULL, amount INTEGER NOT NULL, settled_at DATETIME NOT NULL);
+        "
+    },
+    Migration {
+        version: 5,
+        sql_statements: "
+        CREATE TABLE EXPENSE_PAYER(expense_id INTEGER NOT NULL, user_id TEXT NOT NULL, amount INTEGER NOT NULL, PRIMARY KEY(expense_id, user_id));
+        INSERT INTO EXPENSE_PAYER(expense_id, user_id, amount) SELECT id, added_by, amount FROM EXPENSE;
+        "
+    },
+    // Expenses from before dates were recorded get the time the database was
+    // created, the earliest they could have been added
+    Migration {
+        version: 6,
+        sql_statements: "
+        ALTER TABLE EXPENSE ADD COLUMN created_at DATETIME;
+        ALTER TABLE EXPENSE ADD COLUMN incurred_on DATE;
+        UPDATE EXPENSE SET created_at = (SELECT migration_time FROM migrations WHERE version = 1), incurred_on = (SELECT DATE(migration_time) FROM migrations WHERE version = 1);
+        ALTER TABLE USER ADD COLUMN utc_offset INTEGER NOT NULL DEFAULT 330;
+        "
+    },
+    Migration {
+        version: 7,
+        sql_statements: "
+        CREATE TABLE CATEGORY(category_id INTEGER PRIMARY KEY AUTOINCREMENT, group_id INTEGER, name TEXT NOT NULL);
+        CREATE UNIQUE INDEX CATEGORY_NAME ON CATEGORY(IFNULL(group_id, 0), name COLLATE NOCASE);
+        CREATE TABLE CATEGORY_KEYWORD(keyword TEXT NOT NULL, group_id INTEGER, category_id INTEGER NOT NULL);
+        ALTER TABLE EXPENSE ADD COLUMN category_id INTEGER;
+        INSERT INTO CATEGORY(name) VALUES ('Food'), ('Groceries'), ('Travel'), ('Rent'), ('Utilities'), ('Entertainment'), ('Shopping'), ('Other');
+        INSERT INTO CATEGORY_KEYWORD(keyword, category_id) VALUES ('food', 1), ('lunch', 1), ('dinner', 1), ('breakfast', 1), ('pizza', 1), ('restaurant', 1), ('cafe', 1), ('coffee', 1), ('swiggy', 1), ('zomato', 1), ('groceries', 2), ('grocery', 2), ('vegetables', 2), ('milk', 2), ('supermarket', 2), ('travel', 3), ('uber', 3), ('ola', 3), ('taxi', 3), ('cab', 3), ('flight', 3), ('train', 3), ('bus', 3), ('fuel', 3), ('petrol', 3), ('rent', 4), ('lease', 4), ('deposit', 4), ('electricity', 5), ('water', 5), ('gas', 5), ('internet', 5), ('wifi', 5), ('broadband', 5), ('phone', 5), ('recharge', 5), ('movie', 6), ('movies', 6), ('concert', 6), ('netflix', 6), ('tickets', 6), ('party', 6), ('shopping', 7), ('clothes', 7), ('amazon', 7), ('flipkart', 7);
+        "
+    },
+    Migration {
+        version: 8,
+        sql_statements: "
+        CREATE TABLE RECURRING_EXPENSE(recurring_id INTEGER PRIMARY KEY AUTOINCREMENT, added_by TEXT NOT NULL, group_id INTEGER NOT NULL, amount INTEGER NOT NULL, currency TEXT NOT NULL, title TEXT NOT NULL, description TEXT NOT NULL, split_type INTEGER NOT NULL, category_id INTEGER, schedule INTEGER NOT NULL, start_date TEXT NOT NULL, occurrences INTEGER NOT NULL DEFAULT 0, active INTEGER NOT NULL DEFAULT 1);
+        CREATE TABLE RECURRING_EXPENSE_SPLIT(recurring_id INTEGER NOT NULL, user_id TEXT NOT NULL, value INTEGER NOT NULL);
+        CREATE TABLE RECURRING_EXPENSE_PAYER(recurring_id INTEGER NOT NULL, user_id TEXT NOT NULL, amount INTEGER NOT NULL);
+        ALTER TABLE EXPENSE ADD COLUMN recurring_id INTEGER;
+        CREATE UNIQUE INDEX EXPENSE_OCCURRENCE ON EXPENSE(recurring_id, incurred_on) WHERE recurring_id IS NOT NULL;
+        "
+    },
+    Migration {
+        version: 9,
+        sql_statements: "
+        CREATE TABLE BUDGET(budget_id INTEGER PRIMARY KEY AUTOINCREMENT, group_id INTEGER NOT NULL, category_id INTEGER, amount INTEGER NOT NULL);
+        CREATE UNIQUE INDEX BUDGET_SCOPE ON BUDGET(group_id, IFNULL(category_id, 0));
+        "
+    },
+    Migration {
+        version: 10,
+        sql_statements: "
+        ALTER TABLE USER_EXPENSES ADD COLUMN split_value INTEGER;
+        "
+    },
+    Migration {
+        version: 11,
+        sql_statements: "
+        CREATE TABLE AUDIT_LOG(audit_id INTEGER PRIMARY KEY AUTOINCREMENT, group_id INTEGER, actor TEXT NOT NULL, entity INTEGER NOT NULL, entity_id TEXT NOT NULL, action INTEGER NOT NULL, before TEXT, after TEXT, created_at DATETIME NOT NULL);
+        CREATE INDEX AUDIT_LOG_GROUP ON AUDIT_LOG(group_id, audit_id);
+        "
+    },
+    Migration {
+        version: 12,
+        sql_statements: "
+        ALTER TABLE EXPENSE ADD COLUMN deleted_at DATETIME;
+        ALTER TABLE AUDIT_LOG ADD COLUMN reverts INTEGER;
+        CREATE INDEX AUDIT_LOG_ACTOR ON AUDIT_LOG(actor, audit_id);
+        "
+    },
+    Migration {
+        version: 13,
+        sql_statements: "
+        ALTER TABLE USER RENAME TO USER_OLD;
+        ALTER TABLE EXPENSE_GROUP RENAME TO EXPENSE_GROUP_OLD;
+        ALTER TABLE GROUP_MEMBERSHIP RENAME TO GROUP_MEMBERSHIP_OLD;
+        ALTER TABLE CATEGORY RENAME TO CATEGORY_OLD;
+        ALTER TABLE CATEGORY_KEYWORD RENAME TO CATEGORY_KEYWORD_OLD;
+        ALTER TABLE RECURRING_EXPENSE RENAME TO RECURRING_EXPENSE_OLD;
+        ALTER TABLE RECURRING_EXPENSE_SPLIT RENAME TO RECURRING_EXPENSE_SPLIT_OLD;
+        ALTER TABLE RECURRING_EXPENSE_PAYER RENAME TO RECURRING_EXPENSE_PAYER_OLD;
+        ALTER TABLE EXPENSE RENAME TO EXPENSE_OLD;
+        ALTER TABLE USER_EXPENSES RENAME TO USER_EXPENSES_OLD;
+        ALTER TABLE EXPENSE_PAYER RENAME TO EXPENSE_PAYER_OLD;
+        ALTER TABLE SETTLEMENT RENAME TO SETTLEMENT_OLD;
+        ALTER TABLE BUDGET RENAME TO BUDGET_OLD;
+
+        CREATE TABLE USER(user_id TEXT PRIMARY KEY NOT NULL, name TEXT, username TEXT, utc_offset INTEGER NOT NULL DEFAULT 330);
+        CREATE TABLE EXPENSE_GROUP(group_id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, description TEXT, created_by TEXT REFERENCES USER(user_id) ON DELETE RESTRICT, base_currency TEXT NOT NULL DEFAULT 'INR', simplify_debts INTEGER NOT NULL DEFAULT 1);
+        CREATE TABLE GROUP_MEMBERSHIP(user_id TEXT NOT NULL REFERENCES USER(user_id) ON DELETE CASCADE, group_id INTEGER NOT NULL REFERENCES EXPENSE_GROUP(group_id) ON DELETE CASCADE, PRIMARY KEY(user_id, group_id));
+        CREATE TABLE CATEGORY(category_id INTEGER PRIMARY KEY AUTOINCREMENT, group_id INTEGER REFERENCES EXPENSE_GROUP(group_id) ON DELETE CASCADE, name TEXT NOT NULL);
+        CREATE TABLE CATEGORY_KEYWORD(keyword TEXT NOT NULL, group_id INTEGER REFERENCES EXPENSE_GROUP(group_id) ON DELETE CASCADE, category_id INTEGER NOT NULL REFERENCES CATEGORY(category_id) ON DELETE CASCADE);
+        CREATE TABLE RECURRING_EXPENSE(recurring_id INTEGER PRIMARY KEY AUTOINCREMENT, added_by TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, group_id INTEGER NOT NULL REFERENCES EXPENSE_GROUP(group_id) ON DELETE CASCADE, amount INTEGER NOT NULL, currency TEXT NOT NULL, title TEXT NOT NULL, description TEXT NOT NULL, split_type INTEGER NOT NULL, category_id INTEGER REFERENCES CATEGORY(category_id) ON DELETE SET NULL, schedule INTEGER NOT NULL, start_date TEXT NOT NULL, occurrences INTEGER NOT NULL DEFAULT 0, active INTEGER NOT NULL DEFAULT 1);
+        CREATE TABLE RECURRING_EXPENSE_SPLIT(recurring_id INTEGER NOT NULL REFERENCES RECURRING_EXPENSE(recurring_id) ON DELETE CASCADE, user_id TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, value INTEGER NOT NULL);
+        CREATE TABLE RECURRING_EXPENSE_PAYER(recurring_id INTEGER NOT NULL REFERENCES RECURRING_EXPENSE(recurring_id) ON DELETE CASCADE, user_id TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, amount INTEGER NOT NULL);
+        CREATE TABLE EXPENSE(id INTEGER PRIMARY KEY AUTOINCREMENT, added_by TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, group_id INTEGER NOT NULL REFERENCES EXPENSE_GROUP(group_id) ON DELETE CASCADE, amount INTEGER, title TEXT, description TEXT, split_type INTEGER, currency TEXT NOT NULL DEFAULT 'INR', created_at DATETIME, incurred_on DATE, category_id INTEGER REFERENCES CATEGORY(category_id) ON DELETE SET NULL, recurring_id INTEGER REFERENCES RECURRING_EXPENSE(recurring_id) ON DELETE SET NULL, deleted_at DATETIME);
+        CREATE TABLE USER_EXPENSES(user_id TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, expense_id INTEGER NOT NULL REFERENCES EXPENSE(id) ON DELETE CASCADE, split INTEGER, split_value INTEGER);
+        CREATE TABLE EXPENSE_PAYER(expense_id INTEGER NOT NULL REFERENCES EXPENSE(id) ON DELETE CASCADE, user_id TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, amount INTEGER NOT NULL, PRIMARY KEY(expense_id, user_id));
+        CREATE TABLE SETTLEMENT(id INTEGER PRIMARY KEY AUTOINCREMENT, group_id INTEGER NOT NULL REFERENCES EXPENSE_GROUP(group_id) ON DELETE CASCADE, payer TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, payee TEXT NOT NULL REFERENCES USER(user_id) ON DELETE RESTRICT, amount INTEGER NOT NULL, settled_at DATETIME NOT NULL);
+        CREATE TABLE BUDGET(budget_id INTEGER PRIMARY KEY AUTOINCREMENT, group_id INTEGER NOT NULL REFERENCES EXPENSE_GROUP(group_id) ON DELETE CASCADE, category_id INTEGER REFERENCES CATEGORY(category_id) ON DELETE CASCADE, amount INTEGER NOT NULL);
+
+        -- STRING columns had numeric affinity, so numeric ids and titles were stored as numbers
+        INSERT INTO USER(user_id, name, username, utc_offset) SELECT CAST(user_id AS TEXT), CAST(name AS TEXT), CAST(username AS TEXT), utc_offset FROM USER_OLD WHERE user_id IS NOT NULL;
+        -- Users that show up in the ledger without having registered are kept under their id
+        INSERT OR IGNORE INTO USER(user_id, name, username) SELECT user_id, user_id, '' FROM (
+            SELECT CAST(created_by AS TEXT) AS user_id FROM EXPENSE_GROUP_OLD
+            UNION SELECT CAST(user_id AS TEXT) FROM GROUP_MEMBERSHIP_OLD
+            UNION SELECT CAST(added_by AS TEXT) FROM EXPENSE_OLD
+            UNION SELECT CAST(user_id AS TEXT) FROM USER_EXPENSES_OLD
+            UNION SELECT user_id FROM EXPENSE_PAYER_OLD
+            UNION SELECT payer FROM SETTLEMENT_OLD
+            UNION SELECT payee FROM SETTLEMENT_OLD
+            UNION SELECT added_by FROM RECURRING_EXPENSE_OLD
+            UNION SELECT user_id FROM RECURRING_EXPENSE_SPLIT_OLD
+            UNION SELECT user_id FROM RECURRING_EXPENSE_PAYER_OLD
+        ) WHERE user_id IS NOT NULL;
+        INSERT INTO EXPENSE_GROUP(group_id, name, description, created_by, base_currency, simplify_debts) SELECT group_id, CAST(name AS TEXT), CAST(description AS TEXT), CAST(created_by AS TEXT), base_currency, simplify_debts FROM EXPENSE_GROUP_OLD;
+        -- Rows left behind by deleted groups, categories and expenses are dropped
+        INSERT OR IGNORE INTO GROUP_MEMBERSHIP(user_id, group_id) SELECT CAST(user_id AS TEXT), CAST(group_id AS INTEGER) FROM GROUP_MEMBERSHIP_OLD WHERE user_id IS NOT NULL AND group_id IN (SELECT group_id FROM EXPENSE_GROUP);
+        INSERT INTO CATEGORY(category_id, group_id, name) SELECT category_id, group_id, name FROM CATEGORY_OLD WHERE group_id IS NULL OR group_id IN (SELECT group_id FROM EXPENSE_GROUP);
+        INSERT INTO CATEGORY_KEYWORD(keyword, group_id, category_id) SELECT keyword, group_id, category_id FROM CATEGORY_KEYWORD_OLD WHERE category_id IN (SELECT category_id FROM CATEGORY) AND (group_id IS NULL OR group_id IN (SELECT group_id FROM EXPENSE_GROUP));
+        INSERT INTO RECURRING_EXPENSE(recurring_id, added_by, group_id, amount, currency, title, description, split_type, category_id, schedule, start_date, occurrences, active) SELECT recurring_id, added_by, group_id, amount, currency, title, description, split_type, CASE WHEN category_id IN (SELECT category_id FROM CATEGORY) THEN category_id END, schedule, start_date, occurrences, active FROM RECURRING_EXPENSE_OLD WHERE group_id IN (SELECT group_id FROM EXPENSE_GROUP);
+        INSERT INTO RECURRING_EXPENSE_SPLIT(recurring_id, user_id, value) SELECT recurring_id, user_id, value FROM RECURRING_EXPENSE_SPLIT_OLD WHERE recurring_id IN (SELECT recurring_id FROM RECURRING_EXPENSE);
+        INSERT INTO RECURRING_EXPENSE_PAYER(recurring_id, user_id, amount) SELECT recurring_id, user_id, amount FROM RECURRING_EXPENSE_PAYER_OLD WHERE recurring_id IN (SELECT recurring_id FROM RECURRING_EXPENSE);
+        INSERT INTO EXPENSE(id, added_by, group_id, amount, title, description, split_type, currency, created_at, incurred_on, category_id, recurring_id, deleted_at) SELECT id, CAST(added_by AS TEXT), CAST(group_id AS INTEGER), amount, CAST(title AS TEXT), CAST(description AS TEXT), split_type, currency, created_at, incurred_on, CASE WHEN category_id IN (SELECT category_id FROM CATEGORY) THEN category_id END, CASE WHEN recurring_id IN (SELECT recurring_id FROM RECURRING_EXPENSE) THEN recurring_id END, deleted_at FROM EXPENSE_OLD WHERE group_id IN (SELECT group_id FROM EXPENSE_GROUP);
+        INSERT INTO USER_EXPENSES(user_id, expense_id, split, split_value) SELECT CAST(user_id AS TEXT), expense_id, split, split_value FROM USER_EXPENSES_OLD WHERE expense_id IN (SELECT id FROM EXPENSE);
+        INSERT INTO EXPENSE_PAYER(expense_id, user_id, amount) SELECT expense_id, user_id, amount FROM EXPENSE_PAYER_OLD WHERE expense_id IN (SELECT id FROM EXPENSE);
+        INSERT INTO SETTLEMENT(id, group_id, payer, payee, amount, settled_at) SELECT id, group_id, payer, payee, amount, settled_at FROM SETTLEMENT_OLD WHERE group_id IN (SELECT group_id FROM EXPENSE_GROUP);
+        INSERT INTO BUDGET(budget_id, group_id, category_id, amount) SELECT budget_id, group_id, category_id, amount FROM BUDGET_OLD WHERE group_id IN (SELECT group_id FROM EXPENSE_GROUP) AND (category_id IS NULL OR category_id IN (SELECT category_id FROM CATEGORY));
+
+        -- Ids are never handed out twice, the audit log still refers to deleted rows
+        DELETE FROM sqlite_sequence WHERE name IN ('EXPENSE_GROUP', 'CATEGORY', 'RECURRING_EXPENSE', 'EXPENSE', 'SETTLEMENT', 'BUDGET');
+        UPDATE sqlite_sequence SET name = substr(name, 1, length(name) - 4) WHERE name IN ('EXPENSE_GROUP_OLD', 'CATEGORY_OLD', 'RECURRING_EXPENSE_OLD', 'EXPENSE_OLD', 'SETTLEMENT_OLD', 'BUDGET_OLD');
+
+        DROP TABLE USER_OLD;
+        DROP TABLE EXPENSE_GROUP_OLD;
+        DROP TABLE GROUP_MEMBERSHIP_OLD;
+        DROP TABLE CATEGORY_OLD;
+        DROP TABLE CATEGORY_KEYWORD_OLD;
+        DROP TABLE RECURRING_EXPENSE_OLD;
+        DROP TABLE RECURRING_EXPENSE_SPLIT_OLD;
+        DROP TABLE RECURRING_EXPENSE_PAYER_OLD;
+        DROP TABLE EXPENSE_OLD;
+        DROP TABLE USER_EXPENSES_OLD;
+        DROP TABLE EXPENSE_PAYER_OLD;
+        DROP TABLE SETTLEMENT_OLD;
+        DROP TABLE BUDGET_OLD;
+
+        CREATE INDEX GROUP_MEMBERSHIP_GROUP ON GROUP_MEMBERSHIP(group_id);
+        CREATE UNIQUE INDEX CATEGORY_NAME ON CATEGORY(IFNULL(group_id, 0), name COLLATE NOCASE);
+        CREATE INDEX CATEGORY_KEYWORD_GROUP ON CATEGORY_KEYWORD(group_id);
+        CREATE INDEX CATEGORY_KEYWORD_CATEGORY ON CATEGORY_KEYWORD(category_id);
+        CREATE INDEX RECURRING_EXPENSE_GROUP ON RECURRING_EXPENSE(group_id);
+        CREATE INDEX RECURRING_EXPENSE_SPLIT_RECURRING ON RECURRING_EXPENSE_SPLIT(recurring_id);
+        CREATE INDEX RECURRING_EXPENSE_PAYER_RECURRING ON RECURRING_EXPENSE_PAYER(recurring_id);
+        CREATE INDEX EXPENSE_GROUP_ID ON EXPENSE(group_id);
+        CREATE INDEX EXPENSE_ADDED_BY ON EXPENSE(added_by);
+        CREATE INDEX EXPENSE_CATEGORY ON EXPENSE(category_id);
+        CREATE UNIQUE INDEX EXPENSE_OCCURRENCE ON EXPENSE(recurring_id, incurred_on) WHERE recurring_id IS NOT NULL;
+        CREATE INDEX EXPENSE_DELETED_AT ON EXPENSE(deleted_at) WHERE deleted_at IS NOT NULL;
+        CREATE INDEX USER_EXPENSES_EXPENSE ON USER_EXPENSES(expense_id);
+        CREATE INDEX USER_EXPENSES_USER ON USER_EXPENSES(user_id);
+        CREATE INDEX EXPENSE_PAYER_USER ON EXPENSE_PAYER(user_id);
+        CREATE INDEX SETTLEMENT_GROUP ON SETTLEMENT(group_id);
+        CREATE UNIQUE INDEX BUDGET_SCOPE ON BUDGET(group_id, IFNULL(category_id, 0));
+        "
+    },
+    Migration {
+        version: 14,
+        sql_statements: "
+        ALTER TABLE EXPENSE_GROUP ADD COLUMN deleted_at DATETIME;
+        CREATE INDEX EXPENSE_GROUP_DELETED_AT ON EXPENSE_GROUP(deleted_at) WHERE deleted_at IS NOT NULL;
+        "
     }
 ];
 
@@ -41,6 +232,9 @@ impl SqliteBackend {
     pub async fn migrate_database(&mut self) -> Result<(), DataError> {
         let lock = self.rw_lock.write().await;
         let mut connection = self.get_new_connection()?;
+        // Tables are rebuilt by copying them, which can't be done while foreign
+        // keys are enforced. They are checked before the migrations are committed.
+        connection.pragma_update(None, "foreign_keys", false)?;
         if !check_if_migration_table_exists(&mut connection) {
             create_migrations_table(&mut connection)?;
         }
@@ -73,6 +267,13 @@ fn apply_migrations(
             params![migration.version],
         )?;
     }
+    let violations: u32 = tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))?;
+    if violations > 0 {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+            Some(format!("{} rows violate foreign keys after migrating", violations)),
+        ));
+    }
 
     tx.commit()?;
     Ok(())
@@ -119,3 +320,37 @@ fn check_if_migration_table_exists(connection: &mut Connection) -> bool {
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        datamodel::{Datamodel, RemainderRule},
+        sqlite::testing::TempDatabase,
+    };
+
+    #[tokio::test]
+    async fn expenses_from_before_dates_get_the_database_creation_date() {
+        let database = TempDatabase::new("undated");
+        let mut connection = Connection::open(database.path()).unwrap();
+        create_migrations_table(&mut connection).unwrap();
+        apply_migrations(&mut C_MIGRATION_LIST[..5].iter(), &mut connection).unwrap();
+        connection.execute_batch("
+            UPDATE migrations SET migration_time = '2020-05-01 10:00:00' WHERE version = 1;
+            INSERT INTO USER(user_id, name, username) VALUES ('1', 'Alice', 'alice');
+            INSERT INTO EXPENSE_GROUP(name, description, created_by) VALUES ('flat', '', '1');
+            INSERT INTO GROUP_MEMBERSHIP(user_id, group_id) VALUES ('1', 1);
+            INSERT INTO EXPENSE(added_by, group_id, amount, title, description, split_type) VALUES ('1', 1, 500, 'pizza', '', 0);
+            INSERT INTO USER_EXPENSES(user_id, expense_id, split) VALUES ('1', 1, 500);
+            INSERT INTO EXPENSE_PAYER(expense_id, user_id, amount) VALUES (1, '1', 500);
+        ").unwrap();
+        drop(connection);
+
+        let mut backend = SqliteBackend::new(database.path(), 1, RemainderRule::default());
+        backend.migrate_database().await.unwrap();
+        let expenses = backend.get_expenses(1).await.unwrap();
+
+        assert_eq!(expenses[0].incurred_on.to_string(), "2020-05-01");
+        assert_eq!(expenses[0].created_at.to_rfc3339(), "2020-05-01T10:00:00+00:00");
+    }
+}
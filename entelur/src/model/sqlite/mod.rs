@@ -16,3 +16,35 @@ If not, see <https://www.gnu.org/licenses/>.
 
 pub mod backend;
 pub mod migrations;
+mod queries;
+pub mod verify;
+
+#[cfg(test)]
+pub mod testing {
+    use std::path::PathBuf;
+
+    /// A database file in the temp directory for one test, removed when dropped.
+    pub struct TempDatabase(PathBuf);
+
+    impl TempDatabase {
+        pub fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "entelur-{}-{}.sqlite",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            TempDatabase(path)
+        }
+
+        pub fn path(&self) -> PathBuf {
+            self.0.clone()
+        }
+    }
+
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! The SQL statements run by `SqliteBackend`. They are kept in one place so
+//! `verify_queries` can prepare every one of them against the migrated schema
+//! at startup.
+
+/// The columns `read_audit_entry` expects, in order.
+macro_rules! audit_columns {
+    () => {
+        "audit_id, group_id, actor, entity, entity_id, action, before, after, created_at, reverts"
+    };
+}
+
+/// Matches audit entries, aliased `entry`, whose change is in effect. They
+/// didn't undo another change and weren't undone themselves.
+macro_rules! live_entry {
+    () => {
+        "entry.reverts IS NULL AND NOT EXISTS(SELECT 1 FROM AUDIT_LOG AS revert WHERE revert.reverts = entry.audit_id)"
+    };
+}
+
+/// Declares each statement as a constant and lists them all in `STATEMENTS`,
+/// so a new statement can't be left out of the startup check.
+macro_rules! statements {
+    ($($name:ident = $sql:expr;)*) => {
+        $(pub(super) const $name: &str = $sql;)*
+
+        const STATEMENTS: &[(&str, &str)] = &[$((stringify!($name), $name)),*];
+    };
+}
+
+statements! {
+    LAST_INSERT_ROWID = "SELECT last_insert_rowid()";
+    SELECT_GROUP_ID = "SELECT group_id FROM EXPENSE_GROUP WHERE group_id = ? AND deleted_at IS NULL";
+    SELECT_USER_ID = "SELECT user_id FROM USER WHERE user_id = ?";
+    INSERT_USER = "INSERT INTO User(user_id, username, name, utc_offset) VALUES (?1, ?2, ?3, ?4)";
+    INSERT_GROUP = "INSERT INTO EXPENSE_GROUP(name, description, created_by, base_currency, simplify_debts) VALUES (?1, ?2, ?3, ?4, ?5)";
+    INSERT_MEMBERSHIP = "INSERT INTO GROUP_MEMBERSHIP(user_id, group_id) VALUES (?1, ?2)";
+    INSERT_MEMBERSHIP_IF_MISSING = "INSERT OR IGNORE INTO GROUP_MEMBERSHIP(user_id, group_id) VALUES (?1, ?2)";
+    INSERT_EXPENSE = "INSERT INTO EXPENSE(added_by, group_id, amount, currency, title, description, split_type, created_at, incurred_on, category_id, recurring_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)";
+    SELECT_USER = "SELECT user_id, username, name, utc_offset FROM User WHERE user_id = ?";
+    SELECT_GROUP = "SELECT group_id, name, description, created_by, base_currency, simplify_debts FROM EXPENSE_GROUP WHERE group_id = ? AND deleted_at IS NULL";
+    SELECT_GROUP_MEMBERS = "SELECT user_id, username, name, utc_offset FROM User WHERE user_id IN (SELECT user_id FROM GROUP_MEMBERSHIP WHERE group_id = ?)";
+    DELETE_MEMBERSHIP = "DELETE FROM GROUP_MEMBERSHIP WHERE group_id = ?1 AND user_id = ?2";
+    SOFT_DELETE_GROUP = "UPDATE EXPENSE_GROUP SET deleted_at = ?2 WHERE group_id = ?1 AND deleted_at IS NULL";
+    SOFT_DELETE_GROUP_EXPENSES = "UPDATE EXPENSE SET deleted_at = ?2 WHERE group_id = ?1 AND deleted_at IS NULL";
+    DELETE_GROUP_MEMBERSHIPS = "DELETE FROM GROUP_MEMBERSHIP WHERE group_id = ?1";
+    CANCEL_GROUP_RECURRING_EXPENSES = "UPDATE RECURRING_EXPENSE SET active = 0 WHERE group_id = ?1";
+    DELETE_USER = "DELETE FROM User WHERE user_id = ?1";
+    PURGE_DELETED_EXPENSES = "DELETE FROM EXPENSE WHERE deleted_at < ?1";
+    PURGE_DELETED_GROUPS = "DELETE FROM EXPENSE_GROUP WHERE deleted_at < ?1";
+    SELECT_MEMBERSHIPS = "SELECT group_id, user_id FROM GROUP_MEMBERSHIP WHERE user_id = ?";
+    SELECT_GROUP_SHARES = "SELECT user_id, expense_id, split FROM USER_EXPENSES WHERE expense_id IN (SELECT id FROM EXPENSE WHERE group_id = ? AND deleted_at IS NULL)";
+    INSERT_SETTLEMENT = "INSERT INTO SETTLEMENT(group_id, payer, payee, amount, settled_at) VALUES (?1, ?2, ?3, ?4, ?5)";
+    SELECT_SETTLEMENTS = "SELECT id, group_id, payer, payee, amount, settled_at FROM SETTLEMENT WHERE group_id = ? ORDER BY settled_at";
+    UPDATE_SIMPLIFY_DEBTS = "UPDATE EXPENSE_GROUP SET simplify_debts = ?1 WHERE group_id = ?2";
+    UPDATE_UTC_OFFSET = "UPDATE USER SET utc_offset = ?1 WHERE user_id = ?2";
+    INSERT_CURRENCY_RATE = "INSERT OR REPLACE INTO CURRENCY_RATE(currency, base_currency, rate_date, rate) VALUES (?1, ?2, ?3, ?4)";
+    SELECT_CURRENCY_RATES = "SELECT currency, base_currency, rate_date, rate FROM CURRENCY_RATE WHERE base_currency = ?";
+    CATEGORY_EXISTS = "SELECT EXISTS(SELECT 1 FROM CATEGORY WHERE IFNULL(group_id, 0) = IFNULL(?1, 0) AND name = ?2 COLLATE NOCASE)";
+    INSERT_CATEGORY = "INSERT INTO CATEGORY(group_id, name) VALUES (?1, ?2)";
+    SELECT_CATEGORIES = "SELECT category_id, group_id, name FROM CATEGORY WHERE group_id IS NULL OR group_id = ? ORDER BY group_id IS NOT NULL, category_id";
+    INSERT_CATEGORY_KEYWORD = "INSERT INTO CATEGORY_KEYWORD(keyword, group_id, category_id) VALUES (?1, ?2, ?3)";
+    SELECT_CATEGORY_KEYWORDS = "SELECT keyword, group_id, category_id FROM CATEGORY_KEYWORD WHERE group_id IS NULL OR group_id = ?";
+    INSERT_RECURRING_EXPENSE = "INSERT INTO RECURRING_EXPENSE(added_by, group_id, amount, currency, title, description, split_type, category_id, schedule, start_date, occurrences, active) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)";
+    INSERT_RECURRING_SPLIT = "INSERT INTO RECURRING_EXPENSE_SPLIT(recurring_id, user_id, value) VALUES (?1, ?2, ?3)";
+    INSERT_RECURRING_PAYER = "INSERT INTO RECURRING_EXPENSE_PAYER(recurring_id, user_id, amount) VALUES (?1, ?2, ?3)";
+    SELECT_RECURRING_EXPENSES = "SELECT recurring_id, added_by, group_id, amount, currency, title, description, split_type, category_id, schedule, start_date, occurrences, active FROM RECURRING_EXPENSE WHERE active = 1 ORDER BY recurring_id";
+    SELECT_RECURRING_SPLITS = "SELECT recurring_id, user_id, value FROM RECURRING_EXPENSE_SPLIT WHERE recurring_id IN (SELECT recurring_id FROM RECURRING_EXPENSE WHERE active = 1)";
+    SELECT_RECURRING_PAYERS = "SELECT recurring_id, user_id, amount FROM RECURRING_EXPENSE_PAYER WHERE recurring_id IN (SELECT recurring_id FROM RECURRING_EXPENSE WHERE active = 1)";
+    UPDATE_RECURRING_OCCURRENCES = "UPDATE RECURRING_EXPENSE SET occurrences = ?1 WHERE recurring_id = ?2";
+    CANCEL_RECURRING_EXPENSE = "UPDATE RECURRING_EXPENSE SET active = 0 WHERE recurring_id = ?1 AND active = 1";
+    DELETE_BUDGET = "DELETE FROM BUDGET WHERE group_id = ?1 AND category_id IS ?2";
+    INSERT_BUDGET = "INSERT INTO BUDGET(group_id, category_id, amount) VALUES (?1, ?2, ?3)";
+    SELECT_BUDGETS = "SELECT budget_id, group_id, category_id, amount FROM BUDGET WHERE group_id = ? ORDER BY category_id IS NOT NULL, budget_id";
+    SELECT_AUDIT_LOG = concat!("SELECT ", audit_columns!(), " FROM AUDIT_LOG WHERE group_id = ?1 ORDER BY audit_id DESC LIMIT ?2");
+    SELECT_LAST_UNDOABLE_CHANGE = concat!("SELECT ", audit_columns!(), " FROM AUDIT_LOG AS entry WHERE actor = ?1 AND entity = ?2 AND ", live_entry!(), " ORDER BY audit_id DESC LIMIT 1");
+    SELECT_CONFLICTING_CHANGE = concat!("SELECT NOT EXISTS(SELECT 1 FROM AUDIT_LOG AS entry WHERE audit_id = ?1 AND ", live_entry!(), ") OR EXISTS(SELECT 1 FROM AUDIT_LOG AS entry WHERE entity = ?2 AND entity_id = ?3 AND audit_id > ?1 AND ", live_entry!(), ")");
+    RESTORE_EXPENSE = "UPDATE EXPENSE SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL AND group_id IN (SELECT group_id FROM EXPENSE_GROUP WHERE deleted_at IS NULL)";
+    INSERT_AUDIT_ENTRY = "INSERT INTO AUDIT_LOG(group_id, actor, entity, entity_id, action, before, after, created_at, reverts) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+    SOFT_DELETE_EXPENSE = "UPDATE EXPENSE SET deleted_at = ?2 WHERE id = ?1";
+    UPDATE_EXPENSE = "UPDATE EXPENSE SET amount = ?1, currency = ?2, title = ?3, description = ?4, split_type = ?5, incurred_on = ?6, category_id = ?7 WHERE id = ?8 AND deleted_at IS NULL";
+    DELETE_SHARES = "DELETE FROM USER_EXPENSES WHERE expense_id = ?1";
+    DELETE_PAYERS = "DELETE FROM EXPENSE_PAYER WHERE expense_id = ?1";
+    INSERT_SHARE = "INSERT INTO USER_EXPENSES(user_id, expense_id, split, split_value) VALUES (?1, ?2, ?3, ?4)";
+    INSERT_PAYER = "INSERT INTO EXPENSE_PAYER(expense_id, user_id, amount) VALUES (?1, ?2, ?3)";
+
+    // Queries returning the ids of the expenses to load, taking a single parameter
+    EXPENSE_BY_ID = "SELECT ?1";
+    EXPENSES_OF_GROUP = "SELECT id FROM EXPENSE WHERE group_id = ?1";
+    EXPENSES_OF_USER = "SELECT expense_id FROM USER_EXPENSES WHERE user_id = ?1 UNION SELECT expense_id FROM EXPENSE_PAYER WHERE user_id = ?1";
+}
+
+/// The id queries `load_expenses` is called with.
+const EXPENSE_ID_QUERIES: &[(&str, &str)] = &[
+    ("EXPENSE_BY_ID", EXPENSE_BY_ID),
+    ("EXPENSES_OF_GROUP", EXPENSES_OF_GROUP),
+    ("EXPENSES_OF_USER", EXPENSES_OF_USER),
+];
+
+pub(super) fn select_expenses(id_query: &str) -> String {
+    format!("SELECT id, added_by, group_id, amount, currency, title, description, split_type, created_at, incurred_on, category_id, recurring_id FROM EXPENSE WHERE id IN ({}) AND deleted_at IS NULL ORDER BY id", id_query)
+}
+
+pub(super) fn select_payers(id_query: &str) -> String {
+    format!(
+        "SELECT expense_id, user_id, amount FROM EXPENSE_PAYER WHERE expense_id IN ({})",
+        id_query
+    )
+}
+
+pub(super) fn select_shares(id_query: &str) -> String {
+    format!("SELECT expense_id, user_id, split, split_value FROM USER_EXPENSES WHERE expense_id IN ({})", id_query)
+}
+
+/// Every statement the backend runs, named after its constant. The expense
+/// loading queries are built once for each id query they are used with.
+pub(super) fn all() -> Vec<(String, String)> {
+    let mut statements: Vec<(String, String)> = STATEMENTS
+        .iter()
+        .map(|(name, sql)| (name.to_string(), sql.to_string()))
+        .collect();
+    for (id_name, id_query) in EXPENSE_ID_QUERIES {
+        statements.push((
+            format!("select_expenses({})", id_name),
+            select_expenses(id_query),
+        ));
+        statements.push((
+            format!("select_payers({})", id_name),
+            select_payers(id_query),
+        ));
+        statements.push((
+            format!("select_shares({})", id_name),
+            select_shares(id_query),
+        ));
+    }
+    statements
+}
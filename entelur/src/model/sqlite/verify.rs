@@ -0,0 +1,87 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::fmt;
+
+use crate::model::DataError;
+
+use super::{backend::SqliteBackend, queries};
+
+/// A backend statement SQLite refused to prepare against the database.
+#[derive(Debug)]
+pub struct QueryFailure {
+    pub name: String,
+    pub sql: String,
+    pub error: String,
+}
+
+impl fmt::Display for QueryFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}\n    {}", self.name, self.error, self.sql)
+    }
+}
+
+impl SqliteBackend {
+    /// Prepares, without running, every statement the backend uses and returns
+    /// the ones that fail. Run after `migrate_database` so a query naming a
+    /// table or column the schema doesn't have stops the bot at startup instead
+    /// of failing the first time it's used.
+    pub async fn verify_queries(&self) -> Result<Vec<QueryFailure>, DataError> {
+        let read_lock = self.rw_lock.read().await;
+        let connection = self.get_new_connection()?;
+        let mut failures = Vec::new();
+        for (name, sql) in queries::all() {
+            if let Err(error) = connection.prepare(&sql) {
+                failures.push(QueryFailure {
+                    name,
+                    sql,
+                    error: error.to_string(),
+                });
+            }
+        }
+        Ok(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{datamodel::RemainderRule, sqlite::testing::TempDatabase};
+
+    #[tokio::test]
+    async fn queries_prepare_against_migrated_schema() {
+        let database = TempDatabase::new("verify-migrated");
+        let mut backend = SqliteBackend::new(database.path(), 1, RemainderRule::default());
+        backend.migrate_database().await.unwrap();
+        let failures = backend.verify_queries().await.unwrap();
+
+        let report: Vec<String> = failures.iter().map(ToString::to_string).collect();
+        assert!(failures.is_empty(), "{}", report.join("\n"));
+    }
+
+    #[tokio::test]
+    async fn queries_fail_without_migrations() {
+        let database = TempDatabase::new("verify-empty");
+        let backend = SqliteBackend::new(database.path(), 1, RemainderRule::default());
+        let failures = backend.verify_queries().await.unwrap();
+
+        assert!(failures.iter().any(
+            |failure| failure.name == "SELECT_USER" && failure.error.contains("no such table")
+        ));
+        assert!(failures
+            .iter()
+            .any(|failure| failure.name == "select_expenses(EXPENSES_OF_GROUP)"));
+    }
+}
@@ -0,0 +1,208 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::{FixedOffset, NaiveDate};
+
+use super::{
+    balance::convert_expense,
+    datamodel::{CategoryId, CurrencyRate, Expense, Group, Settlement, UserExpenses, UserId},
+    DataError,
+};
+
+/// One line of a user's statement. Amounts are in minor units of the group's
+/// base currency.
+#[derive(Debug, Clone)]
+pub struct StatementEntry {
+    pub date: NaiveDate,
+    pub group: String,
+    pub currency: String,
+    pub kind: EntryKind,
+    /// How the entry changed the user's balance, positive when the user is owed
+    /// more afterwards.
+    pub amount: i64,
+}
+
+#[derive(Debug, Clone)]
+pub enum EntryKind {
+    Expense {
+        title: String,
+        category: Option<CategoryId>,
+        paid: i64,
+        share: i64,
+    },
+    SettlementPaid {
+        to: UserId,
+    },
+    SettlementReceived {
+        from: UserId,
+    },
+}
+
+/// Lists the expenses and settlements of a group that involve `user_id`, in the
+/// order they happened. Settlement dates are taken in the user's timezone.
+pub fn group_entries(
+    user_id: &UserId,
+    group: &Group,
+    expenses: &[Expense],
+    splits: &[UserExpenses],
+    settlements: &[Settlement],
+    rates: &[CurrencyRate],
+    utc_offset: i32,
+) -> Result<Vec<StatementEntry>, DataError> {
+    let mut entries: Vec<StatementEntry> = Vec::new();
+    for expense in expenses {
+        let converted = convert_expense(group, expense, splits, rates)?;
+        let paid = user_total(&converted.paid, user_id);
+        let share = user_total(&converted.owed, user_id);
+        if paid == 0 && share == 0 {
+            continue;
+        }
+        entries.push(StatementEntry {
+            date: expense.incurred_on,
+            group: group.name.clone(),
+            currency: group.base_currency.clone(),
+            kind: EntryKind::Expense {
+                title: expense.title.clone(),
+                category: expense.category,
+                paid,
+                share,
+            },
+            amount: paid - share,
+        });
+    }
+
+    let offset =
+        FixedOffset::east_opt(utc_offset * 60).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    for settlement in settlements {
+        let (kind, amount) = if &settlement.payer == user_id {
+            (
+                EntryKind::SettlementPaid {
+                    to: settlement.payee.clone(),
+                },
+                settlement.amount as i64,
+            )
+        } else if &settlement.payee == user_id {
+            (
+                EntryKind::SettlementReceived {
+                    from: settlement.payer.clone(),
+                },
+                -(settlement.amount as i64),
+            )
+        } else {
+            continue;
+        };
+        entries.push(StatementEntry {
+            date: settlement.settled_at.with_timezone(&offset).date_naive(),
+            group: group.name.clone(),
+            currency: group.base_currency.clone(),
+            kind,
+            amount,
+        });
+    }
+
+    entries.sort_by_key(|entry| entry.date);
+    Ok(entries)
+}
+
+fn user_total(amounts: &[(UserId, i64)], user_id: &UserId) -> i64 {
+    amounts
+        .iter()
+        .filter(|(id, _)| id == user_id)
+        .map(|(_, amount)| amount)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+    use crate::model::datamodel::Money;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    /// "1" paid 300 for pizza on 14 March shared by "1", "2" and "3", and "2"
+    /// paid "1" back late on 10 March UTC.
+    fn entries(user_id: &str, utc_offset: i32) -> Vec<StatementEntry> {
+        let group = Group::new(1, "flat".into(), "".into(), "1".into());
+        let mut expense = Expense::new(
+            "1".into(),
+            1,
+            Money::new(300, "INR"),
+            "Pizza".into(),
+            "".into(),
+        );
+        expense.id = Some(1);
+        expense.incurred_on = date("2026-03-14");
+        let splits: Vec<UserExpenses> = ["1", "2", "3"]
+            .into_iter()
+            .map(|user_id| UserExpenses {
+                user_id: user_id.into(),
+                expenses_id: 1,
+                split: 100,
+            })
+            .collect();
+        let mut settlement = Settlement::new(1, "2".into(), "1".into(), 100);
+        settlement.settled_at = "2026-03-10T20:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        group_entries(
+            &user_id.to_string(),
+            &group,
+            &[expense],
+            &splits,
+            &[settlement],
+            &[],
+            utc_offset,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn paying_raises_the_balance_and_a_share_lowers_it() {
+        let payer = entries("1", 0);
+        assert_eq!(payer.len(), 2);
+        assert!(matches!(payer[0].kind, EntryKind::SettlementReceived { ref from } if from == "2"));
+        assert_eq!(payer[0].amount, -100);
+        assert!(matches!(
+            payer[1].kind,
+            EntryKind::Expense {
+                paid: 300,
+                share: 100,
+                ..
+            }
+        ));
+        assert_eq!(payer[1].amount, 200);
+
+        let payee = entries("2", 0);
+        assert!(matches!(payee[0].kind, EntryKind::SettlementPaid { ref to } if to == "1"));
+        assert_eq!(payee[0].amount, 100);
+        assert_eq!(payee[1].amount, -100);
+
+        let other = entries("3", 0);
+        assert_eq!(other.len(), 1);
+        assert_eq!(other[0].amount, -100);
+        assert!(entries("4", 0).is_empty());
+    }
+
+    #[test]
+    fn entries_are_in_date_order_in_the_users_timezone() {
+        let dates: Vec<NaiveDate> = entries("1", 0).iter().map(|entry| entry.date).collect();
+        assert_eq!(dates, vec![date("2026-03-10"), date("2026-03-14")]);
+        assert_eq!(entries("1", 330)[0].date, date("2026-03-11"));
+    }
+}
@@ -0,0 +1,411 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::model::datamodel::{currency_exponent, Money};
+
+use super::ParseError;
+
+/// Precision used while evaluating an expression, amounts are rounded to the
+/// minor units of the currency only at the end.
+const SCALE_DIGITS: u32 = 6;
+const SCALE: i128 = 10i128.pow(SCALE_DIGITS);
+
+/// Why evaluating an expression failed, turned into a `ParseError` that quotes
+/// the whole text.
+enum EvalError {
+    Syntax,
+    DivisionByZero,
+    Overflow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i128),
+    Plus,
+    Minus,
+    Times,
+    Divide,
+    Open,
+    Close,
+}
+
+/// Parses an amount the way people type it. Supports
+/// - arithmetic with `+ - * /` and parentheses, `120+35*2`
+/// - thousands and decimal separators in either convention, `1,234.50` and `1.234,50`
+/// - currency symbols and codes, `€12.50` or `12.50 EUR`
+/// - a `k` suffix for thousands, `1.5k`
+///
+/// A lone separator after one to three digits and followed by exactly three
+/// digits is read as a thousands separator, so `1.500` is fifteen hundred while
+/// `0.500` is a half. `currency` is used when the text doesn't name one.
+pub fn parse_amount(text: &str, currency: &str) -> Result<Money, ParseError> {
+    let invalid = || ParseError::InvalidAmount(text.trim().to_string());
+    let (tokens, named_currency) = tokenize(text).ok_or_else(invalid)?;
+    let currency = named_currency.unwrap_or_else(|| currency.to_string());
+
+    let mut position = 0;
+    let value = expression(&tokens, &mut position).map_err(|e| match e {
+        EvalError::Syntax => invalid(),
+        EvalError::DivisionByZero => ParseError::DivisionByZero,
+        EvalError::Overflow => ParseError::AmountTooLarge,
+    })?;
+    if position != tokens.len() {
+        return Err(invalid());
+    }
+
+    let divisor = 10i128.pow(SCALE_DIGITS - currency_exponent(&currency).min(SCALE_DIGITS));
+    let minor_units = i64::try_from(divide_rounded(value, divisor).ok_or_else(invalid)?)
+        .map_err(|_| ParseError::AmountTooLarge)?;
+    Ok(Money {
+        minor_units,
+        currency,
+    })
+}
+
+/// Parses a positive amount, as stored in `Expense::amount`. The text may name
+/// a currency, otherwise `currency` is used.
+pub fn parse_positive_amount(text: &str, currency: &str) -> Result<Money, ParseError> {
+    let amount = parse_amount(text, currency)?;
+    positive_minor_units(&amount)?;
+    Ok(amount)
+}
+
+/// Parses a positive amount that must be in `currency`, as stored in payer
+/// amounts and `Settlement::amount`.
+pub fn parse_amount_in(text: &str, currency: &str) -> Result<u32, ParseError> {
+    let amount = parse_amount(text, currency)?;
+    if amount.currency != currency {
+        return Err(ParseError::UnexpectedCurrency(currency.to_string()));
+    }
+    positive_minor_units(&amount)
+}
+
+/// Parses a currency given as a three letter code, `eur`, or as a symbol, `€`.
+pub fn parse_currency(text: &str) -> Result<String, ParseError> {
+    let text = text.trim();
+    let mut chars = text.chars();
+    if let (Some(symbol), None) = (chars.next(), chars.next()) {
+        if let Some(code) = currency_for_symbol(symbol) {
+            return Ok(code.to_string());
+        }
+    }
+    if text.len() != 3 || !text.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(ParseError::InvalidCurrency(text.to_string()));
+    }
+    Ok(text.to_ascii_uppercase())
+}
+
+/// The amount as a positive number of minor units.
+fn positive_minor_units(amount: &Money) -> Result<u32, ParseError> {
+    if amount.minor_units <= 0 {
+        return Err(ParseError::AmountNotPositive);
+    }
+    u32::try_from(amount.minor_units).map_err(|_| ParseError::AmountTooLarge)
+}
+
+fn tokenize(text: &str) -> Option<(Vec<Token>, Option<String>)> {
+    let mut tokens = Vec::new();
+    let mut currency: Option<String> = None;
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '0'..='9' | '.' | ',' => {
+                let mut literal = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+                {
+                    literal.push(c);
+                }
+                let mut value = parse_number(&literal)?;
+                if chars.next_if(|c| *c == 'k' || *c == 'K').is_some() {
+                    value = value.checked_mul(1000)?;
+                }
+                Token::Number(value)
+            }
+            c if c.is_alphabetic() => {
+                let mut code = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_alphabetic()) {
+                    code.push(c);
+                }
+                if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+                    return None;
+                }
+                set_currency(&mut currency, &code.to_ascii_uppercase())?;
+                continue;
+            }
+            _ => {
+                chars.next();
+                match c {
+                    '+' => Token::Plus,
+                    '-' | '−' => Token::Minus,
+                    '*' | '×' => Token::Times,
+                    '/' | '÷' => Token::Divide,
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => {
+                        set_currency(&mut currency, currency_for_symbol(c)?)?;
+                        continue;
+                    }
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Some((tokens, currency))
+}
+
+/// Every currency mentioned in the text has to be the same.
+fn set_currency(currency: &mut Option<String>, code: &str) -> Option<()> {
+    match currency {
+        Some(existing) if existing != code => None,
+        _ => {
+            *currency = Some(code.to_string());
+            Some(())
+        }
+    }
+}
+
+fn currency_for_symbol(symbol: char) -> Option<&'static str> {
+    match symbol {
+        '₹' => Some("INR"),
+        '€' => Some("EUR"),
+        '$' => Some("USD"),
+        '£' => Some("GBP"),
+        '¥' => Some("JPY"),
+        _ => None,
+    }
+}
+
+/// Reads a number with optional separators into a value scaled by `SCALE`.
+fn parse_number(literal: &str) -> Option<i128> {
+    let last_dot = literal.rfind('.');
+    let last_comma = literal.rfind(',');
+    let decimal = match (last_dot, last_comma) {
+        (Some(dot), Some(comma)) => Some(dot.max(comma)),
+        (Some(index), None) | (None, Some(index)) => {
+            let separator = &literal[index..index + 1];
+            let whole = &literal[..index];
+            let is_thousands = literal.matches(separator).count() > 1
+                || (literal.len() - index - 1 == 3
+                    && (1..=3).contains(&whole.len())
+                    && !whole.starts_with('0'));
+            (!is_thousands).then_some(index)
+        }
+        (None, None) => None,
+    };
+
+    let (whole, fraction) = match decimal {
+        Some(index) => (&literal[..index], &literal[index + 1..]),
+        None => (literal, ""),
+    };
+    if fraction.contains(['.', ',']) || fraction.len() > SCALE_DIGITS as usize {
+        return None;
+    }
+    let whole: String = whole.chars().filter(|c| c.is_ascii_digit()).collect();
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let whole: i128 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let fraction: i128 = if fraction.is_empty() {
+        0
+    } else {
+        format!("{fraction:0<width$}", width = SCALE_DIGITS as usize)
+            .parse()
+            .ok()?
+    };
+    whole.checked_mul(SCALE)?.checked_add(fraction)
+}
+
+fn expression(tokens: &[Token], position: &mut usize) -> Result<i128, EvalError> {
+    let mut value = term(tokens, position)?;
+    while let Some(token @ (Token::Plus | Token::Minus)) = tokens.get(*position) {
+        *position += 1;
+        let rhs = term(tokens, position)?;
+        value = match token {
+            Token::Plus => value.checked_add(rhs),
+            _ => value.checked_sub(rhs),
+        }
+        .ok_or(EvalError::Overflow)?;
+    }
+    Ok(value)
+}
+
+fn term(tokens: &[Token], position: &mut usize) -> Result<i128, EvalError> {
+    let mut value = factor(tokens, position)?;
+    while let Some(token @ (Token::Times | Token::Divide)) = tokens.get(*position) {
+        *position += 1;
+        let rhs = factor(tokens, position)?;
+        value = match token {
+            Token::Times => value
+                .checked_mul(rhs)
+                .and_then(|value| divide_rounded(value, SCALE)),
+            _ if rhs == 0 => return Err(EvalError::DivisionByZero),
+            _ => value
+                .checked_mul(SCALE)
+                .and_then(|value| divide_rounded(value, rhs)),
+        }
+        .ok_or(EvalError::Overflow)?;
+    }
+    Ok(value)
+}
+
+fn factor(tokens: &[Token], position: &mut usize) -> Result<i128, EvalError> {
+    let token = tokens.get(*position).cloned();
+    *position += 1;
+    match token {
+        Some(Token::Number(value)) => Ok(value),
+        Some(Token::Minus) => Ok(-factor(tokens, position)?),
+        Some(Token::Open) => {
+            let value = expression(tokens, position)?;
+            if tokens.get(*position) != Some(&Token::Close) {
+                return Err(EvalError::Syntax);
+            }
+            *position += 1;
+            Ok(value)
+        }
+        _ => Err(EvalError::Syntax),
+    }
+}
+
+/// Divides rounding half away from zero.
+fn divide_rounded(value: i128, divisor: i128) -> Option<i128> {
+    let quotient = value.checked_div(divisor)?;
+    let remainder = value % divisor;
+    if remainder.abs() * 2 >= divisor.abs() {
+        Some(quotient + value.signum() * divisor.signum())
+    } else {
+        Some(quotient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minor_units(text: &str) -> i64 {
+        parse_amount(text, "INR").unwrap().minor_units
+    }
+
+    #[test]
+    fn arithmetic_and_parentheses() {
+        assert_eq!(minor_units("120+35*2"), 19000);
+        assert_eq!(minor_units("(120+35)*2"), 31000);
+        assert_eq!(minor_units("100 - 20.5"), 7950);
+        assert_eq!(minor_units("-(10)"), -1000);
+        assert!(parse_amount("(12", "INR").is_err());
+        assert!(parse_amount("12+", "INR").is_err());
+    }
+
+    #[test]
+    fn separators_in_either_convention() {
+        assert_eq!(minor_units("1,234.50"), 123450);
+        assert_eq!(minor_units("1.234,50"), 123450);
+        assert_eq!(minor_units("1,234,567"), 123456700);
+        assert_eq!(minor_units("12,5"), 1250);
+        assert!(parse_amount(",", "INR").is_err());
+    }
+
+    #[test]
+    fn three_digits_after_a_lone_separator_are_thousands() {
+        assert_eq!(minor_units("1.500"), 150000);
+        assert_eq!(minor_units("1,500"), 150000);
+        assert_eq!(minor_units("0.500"), 50);
+        assert_eq!(minor_units("1.50"), 150);
+        assert_eq!(minor_units("1234.500"), 123450);
+    }
+
+    #[test]
+    fn k_suffix_is_thousands() {
+        assert_eq!(minor_units("1.5k"), 150000);
+        assert_eq!(minor_units("2K"), 200000);
+        assert_eq!(minor_units("1k+250"), 125000);
+    }
+
+    #[test]
+    fn currency_symbols_and_codes() {
+        assert_eq!(parse_amount("€12.50", "INR"), Ok(Money::new(1250, "EUR")));
+        assert_eq!(
+            parse_amount("12.50 usd", "INR"),
+            Ok(Money::new(1250, "USD"))
+        );
+        assert_eq!(parse_amount("12.50", "INR"), Ok(Money::new(1250, "INR")));
+        assert_eq!(parse_amount("¥1500", "INR"), Ok(Money::new(1500, "JPY")));
+        assert_eq!(parse_amount("0.250 KWD", "INR"), Ok(Money::new(250, "KWD")));
+        assert!(parse_amount("12 EURO", "INR").is_err());
+    }
+
+    #[test]
+    fn mixed_currencies_are_rejected() {
+        assert!(parse_amount("€10 + 5 USD", "INR").is_err());
+        assert!(parse_amount("$10 + £5", "INR").is_err());
+        assert_eq!(
+            parse_amount("€10 + 5 EUR", "INR"),
+            Ok(Money::new(1500, "EUR"))
+        );
+    }
+
+    #[test]
+    fn division_rounds_half_away_from_zero() {
+        assert_eq!(minor_units("100/3"), 3333);
+        assert_eq!(minor_units("200/3"), 6667);
+        assert_eq!(minor_units("0.05/2"), 3);
+        assert_eq!(minor_units("-0.05/2"), -3);
+        assert_eq!(parse_amount("5/2 JPY", "INR"), Ok(Money::new(3, "JPY")));
+        assert_eq!(parse_amount("10/0", "INR"), Err(ParseError::DivisionByZero));
+    }
+
+    #[test]
+    fn positive_amounts() {
+        assert_eq!(
+            parse_positive_amount("0", "INR"),
+            Err(ParseError::AmountNotPositive)
+        );
+        assert_eq!(
+            parse_positive_amount("5-10", "INR"),
+            Err(ParseError::AmountNotPositive)
+        );
+        assert_eq!(
+            parse_positive_amount("50000000", "INR"),
+            Err(ParseError::AmountTooLarge)
+        );
+        assert_eq!(parse_amount_in("12.50", "EUR"), Ok(1250));
+        assert_eq!(
+            parse_amount_in("$12.50", "EUR"),
+            Err(ParseError::UnexpectedCurrency("EUR".to_string()))
+        );
+    }
+
+    #[test]
+    fn currencies_as_codes_or_symbols() {
+        assert_eq!(parse_currency(" eur "), Ok("EUR".to_string()));
+        assert_eq!(parse_currency("€"), Ok("EUR".to_string()));
+        assert_eq!(
+            parse_currency("euro"),
+            Err(ParseError::InvalidCurrency("euro".to_string()))
+        );
+        assert_eq!(
+            parse_currency("#"),
+            Err(ParseError::InvalidCurrency("#".to_string()))
+        );
+    }
+}
@@ -0,0 +1,79 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::model::datamodel::Category;
+
+use super::ParseError;
+
+/// What `/budget` was asked to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetAction {
+    /// Show the budgets and what has been spent against them.
+    Show,
+    /// Set the budget to the amount, as typed.
+    Set(String),
+    Remove,
+}
+
+/// A budget command, for example `/budget 20000`, `/budget 5000 food #flat` or
+/// `/budget off food`.
+///
+/// The first word is the monthly amount, or `off` to remove the budget. Without
+/// either the budgets are shown. `#name` picks the group and every other word
+/// is part of the category name, without one the budget covers the whole group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetCommand {
+    pub action: BudgetAction,
+    pub category: Option<String>,
+    pub group: Option<String>,
+}
+
+pub fn parse_budget_command(text: &str) -> BudgetCommand {
+    let mut words = text.split_whitespace().peekable();
+    let action = match words.peek() {
+        None => BudgetAction::Show,
+        Some(word) if word.starts_with('#') => BudgetAction::Show,
+        Some(word) if word.eq_ignore_ascii_case("off") => {
+            words.next();
+            BudgetAction::Remove
+        }
+        Some(_) => BudgetAction::Set(words.next().unwrap_or_default().to_string()),
+    };
+
+    let mut group = None;
+    let mut category: Vec<&str> = Vec::new();
+    for word in words {
+        match word.strip_prefix('#').filter(|name| !name.is_empty()) {
+            Some(name) => group = Some(name.to_string()),
+            None => category.push(word),
+        }
+    }
+    BudgetCommand {
+        action,
+        category: (!category.is_empty()).then(|| category.join(" ")),
+        group,
+    }
+}
+
+/// Finds the category called `name` among the categories of a group.
+pub fn find_category<'a>(
+    name: &str,
+    categories: &'a [Category],
+) -> Result<&'a Category, ParseError> {
+    categories
+        .iter()
+        .find(|category| category.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| ParseError::UnknownCategory(name.to_string()))
+}
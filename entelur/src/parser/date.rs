@@ -0,0 +1,228 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, Utc, Weekday};
+
+use super::ParseError;
+
+/// The current date for a user `utc_offset` minutes east of UTC.
+pub fn today(utc_offset: i32) -> NaiveDate {
+    let offset =
+        FixedOffset::east_opt(utc_offset * 60).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    Utc::now().with_timezone(&offset).date_naive()
+}
+
+/// Parses the day an expense happened, relative to `today`. Understands
+/// - `today`, `yesterday` and `N days ago`
+/// - weekdays, `friday` or `last friday` is the most recent friday before today
+/// - `2026-03-14`, `14/03/2026`, `14/03` and `14 mar`
+///
+/// Dates without a year are in the past year. Dates in the future are rejected.
+pub fn parse_date(text: &str, today: NaiveDate) -> Result<NaiveDate, ParseError> {
+    let text = text.trim().to_lowercase();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let date = match words[..] {
+        ["today"] | ["now"] => Some(today),
+        ["yesterday"] => today.pred_opt(),
+        [days, "days", "ago"] | [days, "day", "ago"] => days
+            .parse::<i64>()
+            .ok()
+            .and_then(|days| today.checked_sub_signed(Duration::try_days(days)?)),
+        ["last", weekday] => last_weekday(weekday, today),
+        [word] => last_weekday(word, today).or_else(|| parse_numeric_date(word, today)),
+        [day, month] => parse_day_month(day, month, today),
+        _ => None,
+    };
+
+    let date = date.ok_or_else(|| ParseError::InvalidDate(text.clone()))?;
+    if date > today {
+        return Err(ParseError::FutureDate(date));
+    }
+    Ok(date)
+}
+
+/// Parses an offset like `+05:30`, `-4` or `UTC+1`, returning minutes east of UTC.
+pub fn parse_utc_offset(text: &str) -> Result<i32, ParseError> {
+    let invalid = || ParseError::InvalidUtcOffset(text.trim().to_string());
+    let offset = text.trim().to_uppercase();
+    let offset = offset
+        .strip_prefix("UTC")
+        .or_else(|| offset.strip_prefix("GMT"))
+        .unwrap_or(&offset);
+    let (sign, offset) = match offset.chars().next() {
+        Some('+') => (1, &offset[1..]),
+        Some('-') => (-1, &offset[1..]),
+        _ => (1, offset),
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+    if !(0..=14).contains(&hours) || !(0..60).contains(&minutes) {
+        return Err(invalid());
+    }
+    Ok(sign * (hours * 60 + minutes))
+}
+
+/// The most recent day named `word` before `today`.
+fn last_weekday(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let weekday = parse_weekday(word)?;
+    let days_back =
+        (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday() - 1) % 7 + 1;
+    Some(today - Duration::days(days_back as i64))
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    if word.len() < 3 {
+        return None;
+    }
+    word.parse::<Weekday>().ok().or_else(|| {
+        [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ]
+        .into_iter()
+        .find(|weekday| weekday.to_string().to_lowercase().starts_with(word))
+    })
+}
+
+fn parse_numeric_date(date: &str, today: NaiveDate) -> Option<NaiveDate> {
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        return Some(date);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%d/%m/%Y") {
+        return Some(date);
+    }
+    let (day, month) = date.split_once('/')?;
+    in_past_year(day.parse().ok()?, month.parse().ok()?, today)
+}
+
+fn parse_day_month(day: &str, month: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let (day, month) = match day.parse::<u32>() {
+        Ok(day) => (day, month),
+        Err(_) => (month.parse::<u32>().ok()?, day),
+    };
+    if month.len() < 3 {
+        return None;
+    }
+    let month = (1..=12).find(|number| {
+        NaiveDate::from_ymd_opt(2000, *number, 1)
+            .map(|date| date.format("%B").to_string().to_lowercase())
+            .is_some_and(|name| name.starts_with(month))
+    })?;
+    in_past_year(day, month, today)
+}
+
+/// The date with the given day and month that is closest to `today` without
+/// being after it. The 29th of February falls back to the last leap year.
+fn in_past_year(day: u32, month: u32, today: NaiveDate) -> Option<NaiveDate> {
+    // Leap years are at most eight years apart, around the turn of a century
+    (0..=8)
+        .filter_map(|years| NaiveDate::from_ymd_opt(today.year() - years, month, day))
+        .find(|date| *date <= today)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// A Wednesday.
+    fn today() -> NaiveDate {
+        date(2025, 3, 12)
+    }
+
+    fn parse(text: &str) -> Result<NaiveDate, ParseError> {
+        parse_date(text, today())
+    }
+
+    #[test]
+    fn relative_days() {
+        assert_eq!(parse("today"), Ok(today()));
+        assert_eq!(parse("Now"), Ok(today()));
+        assert_eq!(parse("yesterday"), Ok(date(2025, 3, 11)));
+        assert_eq!(parse("3 days ago"), Ok(date(2025, 3, 9)));
+        assert_eq!(parse("1 day ago"), Ok(date(2025, 3, 11)));
+        assert!(parse("-3 days ago").is_err());
+    }
+
+    #[test]
+    fn weekdays_are_before_today() {
+        assert_eq!(parse("friday"), Ok(date(2025, 3, 7)));
+        assert_eq!(parse("last fri"), Ok(date(2025, 3, 7)));
+        assert_eq!(parse("tuesday"), Ok(date(2025, 3, 11)));
+        assert_eq!(parse("wed"), Ok(date(2025, 3, 5)));
+        assert!(parse("fr").is_err());
+    }
+
+    #[test]
+    fn numeric_dates() {
+        assert_eq!(parse("2025-01-31"), Ok(date(2025, 1, 31)));
+        assert_eq!(parse("31/01/2025"), Ok(date(2025, 1, 31)));
+        assert_eq!(parse("31/01"), Ok(date(2025, 1, 31)));
+        assert_eq!(parse("14 mar"), Ok(date(2024, 3, 14)));
+        assert_eq!(parse("March 12"), Ok(today()));
+        assert!(parse("31/02").is_err());
+        assert!(parse("14 ma").is_err());
+    }
+
+    #[test]
+    fn dates_without_a_year_are_in_the_past_year() {
+        assert_eq!(parse("12/03"), Ok(today()));
+        assert_eq!(parse("13/03"), Ok(date(2024, 3, 13)));
+        assert_eq!(parse("25 dec"), Ok(date(2024, 12, 25)));
+    }
+
+    #[test]
+    fn leap_days_fall_back_to_the_last_leap_year() {
+        assert_eq!(parse("29/02"), Ok(date(2024, 2, 29)));
+        assert_eq!(
+            parse_date("29 feb", date(2024, 3, 1)),
+            Ok(date(2024, 2, 29))
+        );
+        assert_eq!(
+            parse_date("29/02", date(2024, 2, 28)),
+            Ok(date(2020, 2, 29))
+        );
+        assert_eq!(parse_date("29/02", date(2101, 1, 1)), Ok(date(2096, 2, 29)));
+    }
+
+    #[test]
+    fn future_dates_are_rejected() {
+        assert_eq!(
+            parse("2025-03-13"),
+            Err(ParseError::FutureDate(date(2025, 3, 13)))
+        );
+        assert!(matches!(parse("tomorrow"), Err(ParseError::InvalidDate(_))));
+    }
+
+    #[test]
+    fn utc_offsets() {
+        assert_eq!(parse_utc_offset("+05:30"), Ok(330));
+        assert_eq!(parse_utc_offset("-4"), Ok(-240));
+        assert_eq!(parse_utc_offset("UTC+1"), Ok(60));
+        assert_eq!(parse_utc_offset("gmt+0"), Ok(0));
+        assert!(parse_utc_offset("+15").is_err());
+        assert!(parse_utc_offset("+05:60").is_err());
+    }
+}
@@ -0,0 +1,256 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::model::{
+    currency::currency_code,
+    datamodel::{Expense, Group, Money, Schedule, SplitType, SplitValue, User, UserId},
+};
+
+use super::{
+    amount::{parse_amount_in, parse_positive_amount},
+    find_group, ParseError,
+};
+
+/// An expense typed in one line, for example `/add 450 pizza @alice @bob` or
+/// `/add 1200 EUR rent split 60/40 #flat`.
+///
+/// The first word is the amount, optionally followed by a currency code in any
+/// case, see [`currency_code`]. Words
+/// starting with `@` name the people sharing the expense along with the sender,
+/// when nobody is named the whole group shares it. `split a/b/..` gives the
+/// part of every participant in order, sender first. Parts adding up to 100
+/// are percentages, anything else is read as shares. `#name` picks the group.
+/// Every other word is part of the title.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpenseCommand {
+    pub amount: String,
+    pub currency: Option<String>,
+    pub title: String,
+    pub mentions: Vec<String>,
+    pub split: Option<Vec<u32>>,
+    pub group: Option<String>,
+}
+
+pub fn parse_expense_command(text: &str) -> Result<ExpenseCommand, ParseError> {
+    let mut words = text.split_whitespace().peekable();
+    let Some(amount) = words.next() else {
+        return Err(ParseError::MissingAmount);
+    };
+    let mut command = ExpenseCommand {
+        amount: amount.to_string(),
+        ..Default::default()
+    };
+    if let Some(currency) = words.peek().and_then(|word| currency_code(word)) {
+        words.next();
+        command.currency = Some(currency);
+    }
+
+    let mut title: Vec<&str> = Vec::new();
+    while let Some(word) = words.next() {
+        if let Some(mention) = word.strip_prefix('@').filter(|name| !name.is_empty()) {
+            command.mentions.push(mention.to_string());
+        } else if let Some(group) = word.strip_prefix('#').filter(|name| !name.is_empty()) {
+            command.group = Some(group.to_string());
+        } else if word.eq_ignore_ascii_case("split") && words.peek().is_some() {
+            let parts = words.next().unwrap_or_default();
+            command.split = Some(parse_split(parts)?);
+        } else {
+            title.push(word);
+        }
+    }
+
+    if title.is_empty() {
+        return Err(ParseError::MissingTitle);
+    }
+    command.title = title.join(" ");
+    Ok(command)
+}
+
+/// A recurring expense typed in one line, for example
+/// `/recurring monthly 1200 rent #flat`. The schedule comes first, the rest is
+/// read like an [`ExpenseCommand`].
+pub fn parse_recurring_command(text: &str) -> Result<(Schedule, ExpenseCommand), ParseError> {
+    let text = text.trim_start();
+    let (schedule, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let schedule = match schedule.to_lowercase().as_str() {
+        "daily" => Schedule::Daily,
+        "weekly" => Schedule::Weekly,
+        "monthly" => Schedule::Monthly,
+        _ => return Err(ParseError::InvalidSchedule(schedule.to_string())),
+    };
+    Ok((schedule, parse_expense_command(rest)?))
+}
+
+/// Parts are whole numbers whose total fits the split values of an expense.
+fn parse_split(parts: &str) -> Result<Vec<u32>, ParseError> {
+    let split: Option<Vec<u32>> = parts.split('/').map(|part| part.parse().ok()).collect();
+    let total = split.as_ref().and_then(|split| {
+        split
+            .iter()
+            .try_fold(0u32, |total, part| total.checked_add(*part))
+    });
+    match (split, total) {
+        (Some(split), Some(total)) if split.len() > 1 && total > 0 => Ok(split),
+        _ => Err(ParseError::InvalidSplit(parts.to_string())),
+    }
+}
+
+/// Picks the group named in the command, or the only group of the user when
+/// none is named.
+pub fn select_group(command: &ExpenseCommand, groups: Vec<Group>) -> Result<Group, ParseError> {
+    find_group(command.group.as_deref(), groups)
+}
+
+impl ExpenseCommand {
+    /// Builds the expense for `group`. The sender paid the whole amount, they
+    /// can change that from the confirmation.
+    pub fn into_expense(
+        self,
+        added_by: UserId,
+        group: &Group,
+        members: &[User],
+    ) -> Result<Expense, ParseError> {
+        let amount = match &self.currency {
+            Some(currency) => Money::new(parse_amount_in(&self.amount, currency)? as i64, currency),
+            None => parse_positive_amount(&self.amount, &group.base_currency)?,
+        };
+
+        let participants: Vec<UserId> = if self.mentions.is_empty() {
+            members
+                .iter()
+                .map(|member| member.user_id.clone())
+                .collect()
+        } else {
+            let mut participants = vec![added_by.clone()];
+            for mention in &self.mentions {
+                let Some(member) = members.iter().find(|member| {
+                    member.username.eq_ignore_ascii_case(mention)
+                        || member.name.eq_ignore_ascii_case(mention)
+                }) else {
+                    return Err(ParseError::UnknownMember(mention.clone()));
+                };
+                if !participants.contains(&member.user_id) {
+                    participants.push(member.user_id.clone());
+                }
+            }
+            participants
+        };
+
+        let mut expense =
+            Expense::new(added_by, group.group_id, amount, self.title, "".to_string());
+
+        if let Some(split) = self.split {
+            // Without mentions the order of the group is used, with the sender first
+            let mut ordered = participants.clone();
+            if let Some(index) = ordered
+                .iter()
+                .position(|user_id| user_id == &expense.added_by)
+            {
+                let sender = ordered.remove(index);
+                ordered.insert(0, sender);
+            }
+            if split.len() != ordered.len() {
+                return Err(ParseError::SplitCountMismatch {
+                    parts: split.len(),
+                    participants: ordered.len(),
+                });
+            }
+            let split_type = if split.iter().map(|part| *part as u64).sum::<u64>() == 100 {
+                SplitType::Percent
+            } else {
+                SplitType::Shares
+            };
+            expense.split_type = split_type.try_into().unwrap();
+            expense.split_values = ordered
+                .into_iter()
+                .zip(split)
+                .map(|(user_id, value)| SplitValue::new(user_id, value))
+                .collect();
+        }
+        expense.participants = participants;
+        Ok(expense)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_and_members() -> (Group, Vec<User>) {
+        let group = Group::new(1, "flat".into(), "".into(), "1".into());
+        let members = vec![
+            User::new("1".into(), "Alice".into(), "alice".into()),
+            User::new("2".into(), "Bob".into(), "bob".into()),
+        ];
+        (group, members)
+    }
+
+    fn split_of(text: &str) -> Expense {
+        let (group, members) = group_and_members();
+        parse_expense_command(text)
+            .unwrap()
+            .into_expense("1".into(), &group, &members)
+            .unwrap()
+    }
+
+    #[test]
+    fn parts_adding_up_to_100_are_percentages() {
+        let expense = split_of("100 rent split 60/40");
+        assert!(matches!(
+            SplitType::try_from(expense.split_type),
+            Ok(SplitType::Percent)
+        ));
+    }
+
+    #[test]
+    fn other_parts_are_shares() {
+        let expense = split_of("100 rent split 2/1");
+        assert!(matches!(
+            SplitType::try_from(expense.split_type),
+            Ok(SplitType::Shares)
+        ));
+    }
+
+    #[test]
+    fn parts_overflowing_u32_are_rejected() {
+        for split in ["4294967295/1", "4294967296/1", "0/0"] {
+            assert_eq!(
+                parse_expense_command(&format!("100 x split {split}")),
+                Err(ParseError::InvalidSplit(split.to_string()))
+            );
+        }
+        assert_eq!(
+            split_of("100 x split 4294967294/1").split_values[0].value,
+            u32::MAX - 1
+        );
+    }
+
+    #[test]
+    fn currency_codes_in_any_case() {
+        let currency = |text: &str| parse_expense_command(text).unwrap().currency;
+        assert_eq!(currency("12 eur pizza"), Some("EUR".to_string()));
+        assert_eq!(currency("12 Usd pizza"), Some("USD".to_string()));
+        assert_eq!(currency("12 EUR pizza"), Some("EUR".to_string()));
+        assert_eq!(currency("12 tea"), None);
+        assert_eq!(currency("12 TEA"), None);
+        // Codes that are also words need upper case
+        assert_eq!(currency("12 cup coffee"), None);
+        assert_eq!(currency("12 CUP coffee"), Some("CUP".to_string()));
+
+        let command = parse_expense_command("12 eur pizza").unwrap();
+        assert_eq!(command.title, "pizza");
+    }
+}
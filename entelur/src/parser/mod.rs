@@ -0,0 +1,116 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+pub mod amount;
+pub mod budget;
+pub mod date;
+pub mod expense;
+
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use crate::model::datamodel::Group;
+
+/// Reasons a command typed by the user couldn't be understood. The messages are
+/// shown to the user as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    MissingAmount,
+    InvalidAmount(String),
+    AmountNotPositive,
+    AmountTooLarge,
+    DivisionByZero,
+    UnexpectedCurrency(String),
+    InvalidCurrency(String),
+    InvalidDate(String),
+    FutureDate(NaiveDate),
+    InvalidUtcOffset(String),
+    InvalidSchedule(String),
+    MissingTitle,
+    InvalidSplit(String),
+    SplitCountMismatch { parts: usize, participants: usize },
+    UnknownMember(String),
+    UnknownGroup(String),
+    UnknownCategory(String),
+    AmbiguousGroup,
+    NoGroup,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingAmount => write!(f, "Start with the amount, for example /add 450 pizza."),
+            ParseError::InvalidAmount(amount) => write!(f, "\"{amount}\" is not a valid amount."),
+            ParseError::AmountNotPositive => write!(f, "The amount must be more than zero."),
+            ParseError::AmountTooLarge => write!(f, "The amount is too large."),
+            ParseError::DivisionByZero => write!(f, "The amount divides by zero."),
+            ParseError::UnexpectedCurrency(currency) => {
+                write!(f, "Please enter the amount in {currency}.")
+            }
+            ParseError::InvalidCurrency(currency) => write!(
+                f,
+                "\"{currency}\" is not a currency. Use a three letter code like INR or EUR."
+            ),
+            ParseError::InvalidDate(date) => write!(
+                f,
+                "Couldn't understand the date \"{date}\". Try yesterday, last friday or 2026-03-14."
+            ),
+            ParseError::FutureDate(date) => write!(f, "{date} is in the future."),
+            ParseError::InvalidUtcOffset(offset) => write!(
+                f,
+                "\"{offset}\" is not a valid timezone. Use an offset from UTC like +05:30 or -4."
+            ),
+            ParseError::InvalidSchedule(schedule) => write!(
+                f,
+                "\"{schedule}\" is not a schedule. Start with daily, weekly or monthly, for example /recurring monthly 1200 rent."
+            ),
+            ParseError::MissingTitle => write!(f, "Add what the expense was for, for example /add 450 pizza."),
+            ParseError::InvalidSplit(split) => write!(
+                f,
+                "\"{split}\" is not a valid split. Use numbers separated by /, for example split 60/40."
+            ),
+            ParseError::SplitCountMismatch { parts, participants } => write!(
+                f,
+                "The split has {parts} parts but there are {participants} people sharing the expense."
+            ),
+            ParseError::UnknownMember(name) => write!(f, "{name} is not a member of this group."),
+            ParseError::UnknownGroup(name) => write!(f, "You are not part of a group called {name}."),
+            ParseError::UnknownCategory(name) => write!(f, "There is no category called {name}."),
+            ParseError::AmbiguousGroup => write!(
+                f,
+                "You are part of several groups, add the group name like #trip."
+            ),
+            ParseError::NoGroup => write!(
+                f,
+                "You are not part of any group yet. Use /creategroup to create one."
+            ),
+        }
+    }
+}
+
+/// Picks the group called `name`, or the only group of the user when no name
+/// is given.
+pub fn find_group(name: Option<&str>, groups: Vec<Group>) -> Result<Group, ParseError> {
+    match name {
+        Some(name) => groups
+            .into_iter()
+            .find(|group| group.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| ParseError::UnknownGroup(name.to_string())),
+        None if groups.len() > 1 => Err(ParseError::AmbiguousGroup),
+        None => groups.into_iter().next().ok_or(ParseError::NoGroup),
+    }
+}
@@ -0,0 +1,312 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+
+use teloxide::prelude::*;
+
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{Datamodel, Expense, GroupId, RecurringExpense, DEFAULT_UTC_OFFSET},
+        DataError,
+    },
+    parser::date::today,
+    state_machine::budget::notify_budget_alerts,
+};
+
+/// How often the scheduler looks for recurring expenses that are due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Adds the due occurrences of recurring expenses and purges expenses deleted
+/// more than `purge_after` ago, forever. Spawned next to the dispatcher in
+/// `main`.
+pub async fn run(bot: Bot, backend: Arc<Backend>, purge_after: chrono::Duration) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        match add_due_expenses(&backend).await {
+            Ok(occurrences) => notify_occurrences(&bot, &backend, occurrences).await,
+            Err(e) => log::error!("Failed to add recurring expenses: {:?}", e),
+        }
+        match backend
+            .purge_deleted_expenses(Utc::now() - purge_after)
+            .await
+        {
+            Ok(0) => {}
+            Ok(purged) => log::info!("Purged {} deleted expenses.", purged),
+            Err(e) => log::error!("Failed to purge deleted expenses: {:?}", e),
+        }
+    }
+}
+
+/// What happened to a recurring expense during a check.
+pub enum Occurrence {
+    Added {
+        recurring: RecurringExpense,
+        expense: Expense,
+    },
+    /// The occurrence can never be added, so the recurring expense was cancelled.
+    Stopped {
+        recurring: RecurringExpense,
+        expense: Expense,
+        error: DataError,
+    },
+}
+
+/// Adds every occurrence that is due by today, in the timezone of the user who
+/// set up the recurring expense. Occurrences missed while the bot was down are
+/// added too. An occurrence that was added but not yet recorded as done is
+/// rejected by the backend as a duplicate, so it is never added twice.
+///
+/// A transient failure leaves the occurrence due for the next check. Any other
+/// failure would repeat forever, so the recurring expense is cancelled instead.
+pub async fn add_due_expenses(backend: &Backend) -> Result<Vec<Occurrence>, DataError> {
+    let mut occurrences = Vec::new();
+    for mut recurring in backend.get_recurring_expenses().await? {
+        let Some(recurring_id) = recurring.id else {
+            continue;
+        };
+        let utc_offset = backend
+            .get_user(recurring.template.added_by.clone())
+            .await
+            .map(|user| user.utc_offset)
+            .unwrap_or(DEFAULT_UTC_OFFSET);
+        let today = today(utc_offset);
+
+        while let Some(expense) = recurring
+            .next_expense()
+            .filter(|expense| expense.incurred_on <= today)
+        {
+            match backend.add_expense(expense.clone()).await {
+                Ok(()) => occurrences.push(Occurrence::Added {
+                    recurring: recurring.clone(),
+                    expense: expense.clone(),
+                }),
+                Err(DataError::DuplicateExpense) => {}
+                Err(e) if e.is_transient() => {
+                    log::warn!(
+                        "Failed to add occurrence {} of recurring expense {}, retrying later: {:?}",
+                        expense.incurred_on,
+                        recurring_id,
+                        e
+                    );
+                    break;
+                }
+                Err(error) => {
+                    log::warn!(
+                        "Stopping recurring expense {}, occurrence {} can't be added: {:?}",
+                        recurring_id,
+                        expense.incurred_on,
+                        error
+                    );
+                    match backend
+                        .cancel_recurring_expense(recurring_id, recurring.template.added_by.clone())
+                        .await
+                    {
+                        Ok(()) => occurrences.push(Occurrence::Stopped {
+                            recurring,
+                            expense,
+                            error,
+                        }),
+                        Err(e) => log::error!(
+                            "Failed to cancel recurring expense {}: {:?}",
+                            recurring_id,
+                            e
+                        ),
+                    }
+                    break;
+                }
+            }
+            recurring.occurrences += 1;
+            if let Err(e) = backend
+                .set_recurring_occurrences(recurring_id, recurring.occurrences)
+                .await
+            {
+                // The occurrence is skipped as a duplicate on the next check
+                log::error!(
+                    "Failed to record occurrence {} of recurring expense {}: {:?}",
+                    expense.incurred_on,
+                    recurring_id,
+                    e
+                );
+                break;
+            }
+        }
+    }
+    Ok(occurrences)
+}
+
+/// Tells the members of the group about the added occurrences and the recurring
+/// expenses that were stopped.
+pub async fn notify_occurrences(bot: &Bot, backend: &Backend, occurrences: Vec<Occurrence>) {
+    for occurrence in occurrences {
+        match occurrence {
+            Occurrence::Added { recurring, expense } => {
+                notify_members(bot, backend, &recurring, &expense).await;
+                notify_budget_alerts(bot, backend, &expense, None).await;
+            }
+            Occurrence::Stopped {
+                recurring,
+                expense,
+                error,
+            } => notify_stopped(bot, backend, &recurring, &expense, error).await,
+        }
+    }
+}
+
+async fn notify_members(
+    bot: &Bot,
+    backend: &Backend,
+    recurring: &RecurringExpense,
+    expense: &Expense,
+) {
+    let group_name = backend
+        .get_group(expense.group)
+        .await
+        .map(|group| group.name)
+        .unwrap_or_default();
+    let message = format!(
+        "Added the {} expense {}: {} {} for {} in {}.",
+        recurring.schedule,
+        expense.title,
+        expense.amount,
+        expense.amount.currency,
+        expense.incurred_on,
+        group_name
+    );
+    send_to_members(bot, backend, expense.group, message).await;
+}
+
+async fn notify_stopped(
+    bot: &Bot,
+    backend: &Backend,
+    recurring: &RecurringExpense,
+    expense: &Expense,
+    error: DataError,
+) {
+    let group_name = backend
+        .get_group(expense.group)
+        .await
+        .map(|group| group.name)
+        .unwrap_or_default();
+    let reason = match error {
+        DataError::LogicalError => "someone who pays or shares it is no longer a member",
+        DataError::QueryReturnedNoRows => "the group no longer exists",
+        _ => "it couldn't be added",
+    };
+    let message = format!(
+        "Stopped the {} expense {} in {} because {}. Use /recurring to set it up again.",
+        recurring.schedule, expense.title, group_name, reason
+    );
+    send_to_members(bot, backend, expense.group, message).await;
+}
+
+async fn send_to_members(bot: &Bot, backend: &Backend, group_id: GroupId, message: String) {
+    for member in backend
+        .get_group_members(group_id)
+        .await
+        .unwrap_or_default()
+    {
+        if let Ok(chat) = member.user_id.parse::<i64>() {
+            // A member may have blocked the bot, that shouldn't stop the others
+            let _ = bot.send_message(ChatId(chat), message.clone()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        datamodel::{Group, Money, RemainderRule, Schedule, User},
+        inmemory::backend::InMemoryBackend,
+    };
+
+    /// An in-memory backend with users "1" and "2" in group 1 and a daily
+    /// expense paid by "2" that started three days ago.
+    async fn backend_with_recurring() -> Backend {
+        let backend = Backend::InMemory(InMemoryBackend::new(RemainderRule::default()));
+        for user_id in ["1", "2"] {
+            backend
+                .add_user(User::new(user_id.into(), user_id.into(), user_id.into()))
+                .await
+                .unwrap();
+        }
+        backend
+            .add_group(Group::new(0, "flat".into(), "".into(), "1".into()))
+            .await
+            .unwrap();
+        backend
+            .add_user_to_group(1, "2".into(), "1".into())
+            .await
+            .unwrap();
+        let template = Expense::new(
+            "2".into(),
+            1,
+            Money::new(500, "INR"),
+            "Milk".into(),
+            "".into(),
+        );
+        let start_date = today(DEFAULT_UTC_OFFSET) - chrono::Duration::days(3);
+        backend
+            .add_recurring_expense(RecurringExpense::new(template, Schedule::Daily, start_date))
+            .await
+            .unwrap();
+        backend
+    }
+
+    #[tokio::test]
+    async fn missed_occurrences_are_added_once() {
+        let backend = backend_with_recurring().await;
+        let occurrences = add_due_expenses(&backend).await.unwrap();
+        assert_eq!(occurrences.len(), 4);
+        assert!(occurrences
+            .iter()
+            .all(|occurrence| matches!(occurrence, Occurrence::Added { .. })));
+        assert!(add_due_expenses(&backend).await.unwrap().is_empty());
+        assert_eq!(backend.get_expenses(1).await.unwrap().len(), 4);
+
+        // Occurrences added before the count was recorded are skipped, not added again
+        backend.set_recurring_occurrences(1, 1).await.unwrap();
+        assert!(add_due_expenses(&backend).await.unwrap().is_empty());
+        assert_eq!(backend.get_expenses(1).await.unwrap().len(), 4);
+        assert_eq!(
+            backend.get_recurring_expenses().await.unwrap()[0].occurrences,
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn recurring_expenses_that_cant_be_added_are_stopped() {
+        let backend = backend_with_recurring().await;
+        backend
+            .remove_user_from_group(1, "2".into(), "1".into())
+            .await
+            .unwrap();
+        let occurrences = add_due_expenses(&backend).await.unwrap();
+        assert!(matches!(
+            occurrences[..],
+            [Occurrence::Stopped {
+                error: DataError::LogicalError,
+                ..
+            }]
+        ));
+        assert!(backend.get_recurring_expenses().await.unwrap().is_empty());
+        assert!(backend.get_expenses(1).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+
+use teloxide::{prelude::*, types::ParseMode, utils::html};
+
+use crate::model::{
+    backend::Backend,
+    datamodel::{Balance, Datamodel, Group, Money, Transfer, User, UserId},
+    DataError,
+};
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+pub async fn show_pending(bot: Bot, msg: Message, backend: Arc<Backend>) -> HandlerResult {
+    let Ok(memberships) = backend.get_membership(msg.chat.id.to_string()).await else {
+        bot.send_message(
+            msg.chat.id,
+            "Failed to fetch your groups. Please try again later.",
+        )
+        .await?;
+        return Ok(());
+    };
+    if memberships.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "You are not part of any group yet. Use /creategroup to create one.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut sections: Vec<String> = Vec::new();
+    for membership in memberships {
+        let Ok(group) = backend.get_group(membership.group_id).await else {
+            continue;
+        };
+        let members = backend
+            .get_group_members(group.group_id)
+            .await
+            .unwrap_or_default();
+        let section = match backend.get_group_balances(group.group_id).await {
+            Ok(balances) => {
+                let debts = backend
+                    .get_group_debts(group.group_id)
+                    .await
+                    .unwrap_or_default();
+                format!(
+                    "{}\n{}",
+                    format_balances(&group, &members, &balances),
+                    format_transfers(&group, &members, &debts)
+                )
+            }
+            Err(DataError::MissingCurrencyRate) => format!(
+                "<b>{}</b>\nAn exchange rate is missing for one of the expenses.",
+                html::escape(&group.name)
+            ),
+            Err(_) => format!(
+                "<b>{}</b>\nFailed to compute balances.",
+                html::escape(&group.name)
+            ),
+        };
+        sections.push(section);
+    }
+
+    bot.send_message(msg.chat.id, sections.join("\n\n"))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Renders the balances of a group as a table with one row per member.
+pub fn format_balances(group: &Group, members: &[User], balances: &[Balance]) -> String {
+    let rows: Vec<(String, String, String)> = balances
+        .iter()
+        .map(|balance| {
+            let amount = Money::new(balance.amount.abs(), &group.base_currency).to_string();
+            let name = member_name(members, &balance.user_id);
+            match balance.amount.signum() {
+                -1 => (name, amount, String::new()),
+                1 => (name, String::new(), amount),
+                _ => (name, String::new(), String::new()),
+            }
+        })
+        .collect();
+
+    let header = (
+        "Member".to_string(),
+        "Owes".to_string(),
+        "Is owed".to_string(),
+    );
+    let name_width = rows
+        .iter()
+        .chain([&header])
+        .map(|row| row.0.chars().count())
+        .max();
+    let owes_width = rows.iter().chain([&header]).map(|row| row.1.len()).max();
+    let name_width = name_width.unwrap_or_default();
+    let owes_width = owes_width.unwrap_or_default();
+
+    let mut table = String::new();
+    for (name, owes, owed) in [&header].into_iter().chain(rows.iter()) {
+        table.push_str(&format!(
+            "{name:<name_width$}  {owes:>owes_width$}  {owed}\n"
+        ));
+    }
+    format!(
+        "<b>{}</b> ({})\n<pre>{}</pre>",
+        html::escape(&group.name),
+        group.base_currency,
+        html::escape(table.trim_end())
+    )
+}
+
+/// Renders the payments needed to settle a group, one per line.
+pub fn format_transfers(group: &Group, members: &[User], transfers: &[Transfer]) -> String {
+    if transfers.is_empty() {
+        return "Everyone is settled up.".to_string();
+    }
+    let heading = if group.simplify_debts {
+        "Payments to settle up:"
+    } else {
+        "Debts between members:"
+    };
+    let lines: Vec<String> = transfers
+        .iter()
+        .map(|transfer| {
+            format!(
+                "{} → {}: {}",
+                html::escape(&member_name(members, &transfer.from)),
+                html::escape(&member_name(members, &transfer.to)),
+                Money::new(transfer.amount, &group.base_currency)
+            )
+        })
+        .collect();
+    format!("{heading}\n{}", lines.join("\n"))
+}
+
+pub fn member_name(members: &[User], user_id: &UserId) -> String {
+    members
+        .iter()
+        .find(|member| &member.user_id == user_id)
+        .map(|member| member.name.clone())
+        .unwrap_or_else(|| user_id.clone())
+}
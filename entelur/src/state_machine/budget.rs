@@ -0,0 +1,290 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+
+use crate::{
+    model::{
+        backend::Backend,
+        budget::BudgetAlert,
+        datamodel::{Budget, Category, CategoryId, Datamodel, Expense, Group, Money},
+        DataError,
+    },
+    parser::{
+        amount::parse_amount_in,
+        budget::{find_category, parse_budget_command, BudgetAction},
+        find_group,
+    },
+};
+
+use super::{expense::user_today, user_groups};
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+pub async fn budget(bot: Bot, msg: Message, text: String, backend: Arc<Backend>) -> HandlerResult {
+    let user_id = msg.chat.id.to_string();
+    let command = parse_budget_command(&text);
+    let groups = user_groups(&backend, user_id.clone()).await;
+    if command.action == BudgetAction::Show && command.group.is_none() {
+        return show_budgets(&bot, msg.chat.id, &backend, groups).await;
+    }
+    let group = match find_group(command.group.as_deref(), groups) {
+        Ok(group) => group,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let categories = backend
+        .get_categories(group.group_id)
+        .await
+        .unwrap_or_default();
+    let category = match command
+        .category
+        .as_deref()
+        .map(|name| find_category(name, &categories))
+    {
+        Some(Ok(category)) => category.id,
+        Some(Err(e)) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+        None => None,
+    };
+    let label = budget_label(&categories, category);
+
+    match command.action {
+        BudgetAction::Show => show_budgets(&bot, msg.chat.id, &backend, vec![group]).await?,
+        BudgetAction::Set(amount) => {
+            let amount = match parse_amount_in(&amount, &group.base_currency) {
+                Ok(amount) => amount,
+                Err(e) => {
+                    bot.send_message(msg.chat.id, e.to_string()).await?;
+                    return Ok(());
+                }
+            };
+            match backend
+                .set_budget(Budget::new(group.group_id, category, amount), user_id)
+                .await
+            {
+                Ok(_) => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!(
+                            "The {} budget of {} is now {} {} a month. Members are warned at 80% and 100%.",
+                            label,
+                            group.name,
+                            Money::new(amount as i64, &group.base_currency),
+                            group.base_currency
+                        ),
+                    )
+                    .await?;
+                }
+                Err(e) => {
+                    log::error!("Failed to set budget: {:?}", e);
+                    bot.send_message(msg.chat.id, "Error setting budget. Please try again later.")
+                        .await?;
+                }
+            }
+        }
+        BudgetAction::Remove => match backend
+            .remove_budget(group.group_id, category, user_id)
+            .await
+        {
+            Ok(_) => {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("Removed the {} budget of {}.", label, group.name),
+                )
+                .await?;
+            }
+            Err(DataError::QueryReturnedNoRows) => {
+                bot.send_message(
+                    msg.chat.id,
+                    format!("{} has no {} budget.", group.name, label),
+                )
+                .await?;
+            }
+            Err(e) => {
+                log::error!("Failed to remove budget: {:?}", e);
+                bot.send_message(
+                    msg.chat.id,
+                    "Error removing budget. Please try again later.",
+                )
+                .await?;
+            }
+        },
+    }
+    Ok(())
+}
+
+async fn show_budgets(
+    bot: &Bot,
+    chat_id: ChatId,
+    backend: &Backend,
+    groups: Vec<Group>,
+) -> HandlerResult {
+    let month = user_today(backend, chat_id.to_string()).await;
+    let mut lines: Vec<String> = Vec::new();
+    for group in &groups {
+        let budgets = backend
+            .get_budgets(group.group_id)
+            .await
+            .unwrap_or_default();
+        if budgets.is_empty() {
+            continue;
+        }
+        let categories = backend
+            .get_categories(group.group_id)
+            .await
+            .unwrap_or_default();
+        for budget in budgets {
+            let spent = match backend
+                .get_month_spend(group.group_id, month, budget.category)
+                .await
+            {
+                Ok(spent) => format!(
+                    "{} of {} {} ({}%)",
+                    Money::new(spent, &group.base_currency),
+                    Money::new(budget.amount as i64, &group.base_currency),
+                    group.base_currency,
+                    percent(spent, budget.amount)
+                ),
+                Err(DataError::MissingCurrencyRate) => "an exchange rate is missing".to_string(),
+                Err(_) => "failed to compute spending".to_string(),
+            };
+            lines.push(format!(
+                "{}, {}: {}",
+                group.name,
+                budget_label(&categories, budget.category),
+                spent
+            ));
+        }
+    }
+
+    if lines.is_empty() {
+        bot.send_message(
+            chat_id,
+            "No budgets are set. Set one with /budget 20000, or /budget 5000 food for a category.",
+        )
+        .await?;
+        return Ok(());
+    }
+    bot.send_message(
+        chat_id,
+        format!(
+            "Budgets for {}:\n{}",
+            month.format("%B %Y"),
+            lines.join("\n")
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Warns the members of the group about the budget thresholds crossed by
+/// `expense`. Called right after an expense is added, or edited from
+/// `previous`.
+pub async fn notify_budget_alerts(
+    bot: &Bot,
+    backend: &Backend,
+    expense: &Expense,
+    previous: Option<&Expense>,
+) {
+    let alerts = match backend.get_budget_alerts(expense, previous).await {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            log::warn!(
+                "Failed to check budgets of group {}: {:?}",
+                expense.group,
+                e
+            );
+            return;
+        }
+    };
+    if alerts.is_empty() {
+        return;
+    }
+    let Ok(group) = backend.get_group(expense.group).await else {
+        return;
+    };
+    let categories = backend
+        .get_categories(expense.group)
+        .await
+        .unwrap_or_default();
+    let messages: Vec<String> = alerts
+        .iter()
+        .map(|alert| alert_message(alert, &group, &categories, expense))
+        .collect();
+    for member in backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default()
+    {
+        if let Ok(chat) = member.user_id.parse::<i64>() {
+            for message in &messages {
+                // A member may have blocked the bot, that shouldn't stop the others
+                let _ = bot.send_message(ChatId(chat), message.clone()).await;
+            }
+        }
+    }
+}
+
+fn alert_message(
+    alert: &BudgetAlert,
+    group: &Group,
+    categories: &[Category],
+    expense: &Expense,
+) -> String {
+    let label = budget_label(categories, alert.budget.category);
+    let spent = format!(
+        "{} of {} {} spent in {}",
+        Money::new(alert.spent, &group.base_currency),
+        Money::new(alert.budget.amount as i64, &group.base_currency),
+        group.base_currency,
+        expense.incurred_on.format("%B")
+    );
+    if alert.threshold >= 100 {
+        format!(
+            "The {} budget of {} is used up, {}.",
+            label, group.name, spent
+        )
+    } else {
+        format!(
+            "Heads up, {}% of the {} budget of {} is used, {}.",
+            alert.threshold, label, group.name, spent
+        )
+    }
+}
+
+fn budget_label(categories: &[Category], category: Option<CategoryId>) -> String {
+    match category {
+        None => "monthly".to_string(),
+        Some(id) => categories
+            .iter()
+            .find(|candidate| candidate.id == Some(id))
+            .map(|candidate| candidate.name.clone())
+            .unwrap_or_else(|| "category".to_string()),
+    }
+}
+
+fn percent(spent: i64, amount: u32) -> i64 {
+    if amount == 0 {
+        return 0;
+    }
+    spent * 100 / amount as i64
+}
@@ -0,0 +1,76 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+
+use crate::model::{backend::Backend, currency::parse_rates, datamodel::Datamodel};
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Chats allowed to run admin commands like /importrates, set on the command
+/// line.
+#[derive(Debug, Clone, Default)]
+pub struct Admins(pub Vec<ChatId>);
+
+pub async fn import_rates(
+    bot: Bot,
+    msg: Message,
+    text: String,
+    backend: Arc<Backend>,
+    admins: Admins,
+) -> HandlerResult {
+    if !admins.0.contains(&msg.chat.id) {
+        bot.send_message(msg.chat.id, "Only the bot admin can import exchange rates.")
+            .await?;
+        return Ok(());
+    }
+    let rates = match parse_rates(&text) {
+        Ok(rates) if rates.is_empty() => {
+            bot.send_message(
+                msg.chat.id,
+                "Add the rates after the command, one per line, e.g. /importrates 2026-03-14,EUR,INR,90.25",
+            )
+            .await?;
+            return Ok(());
+        }
+        Ok(rates) => rates,
+        Err(e) => {
+            bot.send_message(msg.chat.id, format!("Invalid exchange rates, {e}."))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let rate_count = rates.len();
+    for rate in rates {
+        if let Err(e) = backend.add_currency_rate(rate).await {
+            log::error!("Failed to import exchange rate: {:?}", e);
+            bot.send_message(
+                msg.chat.id,
+                "Error importing the exchange rates. Please try again later.",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+    bot.send_message(
+        msg.chat.id,
+        format!("Imported {rate_count} exchange rates."),
+    )
+    .await?;
+    Ok(())
+}
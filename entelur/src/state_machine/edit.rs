@@ -0,0 +1,627 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::sync::Arc;
+
+use teloxide::{
+    dispatching::{dialogue::InMemStorage, UpdateHandler},
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{CategoryId, Datamodel, Expense, ExpensePayer, Money, SplitType, User, UserId},
+        split::calculate_split,
+    },
+    parser::date::parse_date,
+};
+
+use super::{
+    budget::notify_budget_alerts,
+    expense::{
+        expense_details, parse_expense_amount, parse_payers, parse_split_values,
+        participants_keyboard, split_error_message, split_type_keyboard, split_values_prompt,
+        user_today, PARTICIPANTS_DONE,
+    },
+    state::{ExpenseField, State},
+};
+
+type BotDialogue = Dialogue<State, InMemStorage<State>>;
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Number of recent expenses offered for editing.
+const RECENT_EXPENSES: usize = 10;
+
+pub fn edit_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    use dptree::case;
+
+    dptree::entry().branch(
+        case![State::EditExpenseValue {
+            original,
+            expense,
+            field
+        }]
+        .endpoint(recieve_edit_value),
+    )
+}
+
+pub fn edit_callback_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    use dptree::case;
+
+    dptree::entry()
+        .branch(case![State::EditExpense].endpoint(recieve_expense_to_edit))
+        .branch(case![State::EditExpenseField { original, expense }].endpoint(recieve_edit_field))
+        .branch(
+            case![State::EditExpenseCategory { original, expense }].endpoint(recieve_edit_category),
+        )
+        .branch(
+            case![State::EditExpenseParticipants { original, expense }]
+                .endpoint(recieve_edit_participant_toggle),
+        )
+        .branch(
+            case![State::EditExpenseSplitType { original, expense }]
+                .endpoint(recieve_edit_split_type),
+        )
+        .branch(
+            case![State::ConfirmExpenseEdit { original, expense }].endpoint(confirm_expense_edit),
+        )
+}
+
+pub async fn edit_expense(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let mut expenses = backend
+        .get_user_expenses(msg.chat.id.to_string())
+        .await
+        .unwrap_or_default();
+    if expenses.is_empty() {
+        bot.send_message(msg.chat.id, "You have no expenses to edit.")
+            .await?;
+        return Ok(());
+    }
+    expenses.sort_by_key(|expense| std::cmp::Reverse((expense.incurred_on, expense.id)));
+
+    let keyboard_buttons = expenses.iter().take(RECENT_EXPENSES).map(|expense| {
+        [InlineKeyboardButton::callback(
+            format!(
+                "{} {}: {} {}",
+                expense.incurred_on, expense.title, expense.amount, expense.amount.currency
+            ),
+            expense.id.unwrap_or_default().to_string(),
+        )]
+    });
+    bot.send_message(msg.chat.id, "Which expense do you want to edit?")
+        .reply_markup(InlineKeyboardMarkup::new(keyboard_buttons))
+        .await?;
+    dialogue.update(State::EditExpense).await?;
+    Ok(())
+}
+
+async fn recieve_expense_to_edit(
+    bot: Bot,
+    dialogue: BotDialogue,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let expense_id = q.data.and_then(|data| data.parse::<u32>().ok());
+    let expense = backend
+        .get_user_expenses(dialogue.chat_id().to_string())
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .find(|expense| expense_id.is_some() && expense.id == expense_id);
+    let Some(expense) = expense else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find the expense. Please try again",
+        )
+        .await?;
+        return Ok(());
+    };
+    ask_for_field(&bot, &dialogue, expense.clone(), expense).await
+}
+
+async fn ask_for_field(
+    bot: &Bot,
+    dialogue: &BotDialogue,
+    original: Expense,
+    expense: Expense,
+) -> HandlerResult {
+    let rows = [
+        vec![("Title", "title"), ("Amount", "amount"), ("Date", "date")],
+        vec![
+            ("Category", "category"),
+            ("Participants", "participants"),
+            ("Split", "split"),
+        ],
+        vec![("Paid by", "payers"), ("Delete", "delete")],
+        vec![("Preview and save", "save"), ("Cancel", "cancel")],
+    ]
+    .map(|row| {
+        row.into_iter()
+            .map(|(label, option)| InlineKeyboardButton::callback(label, option))
+            .collect::<Vec<_>>()
+    });
+    bot.send_message(
+        dialogue.chat_id(),
+        format!("Editing {}. What do you want to change?", expense.title),
+    )
+    .reply_markup(InlineKeyboardMarkup::new(rows))
+    .await?;
+    dialogue
+        .update(State::EditExpenseField { original, expense })
+        .await?;
+    Ok(())
+}
+
+async fn recieve_edit_field(
+    bot: Bot,
+    dialogue: BotDialogue,
+    (original, mut expense): (Expense, Expense),
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+
+    let field = match option.as_str() {
+        "title" => {
+            bot.send_message(dialogue.chat_id(), "What was the expense for?")
+                .await?;
+            ExpenseField::Title
+        }
+        "amount" => {
+            bot.send_message(
+                dialogue.chat_id(),
+                format!(
+                    "How much was it? (in {}, or name another currency like 20 USD or €20)",
+                    expense.amount.currency
+                ),
+            )
+            .await?;
+            ExpenseField::Amount
+        }
+        "date" => {
+            bot.send_message(
+                dialogue.chat_id(),
+                "When was it? Type a date like yesterday, last friday or 2026-03-14.",
+            )
+            .await?;
+            ExpenseField::Date
+        }
+        "payers" => {
+            bot.send_message(
+                dialogue.chat_id(),
+                format!(
+                    "Send one line per person with their name and how much they paid, for example:\nAlice 300\nBob 150\nThe amounts must add up to {} {}.",
+                    expense.amount,
+                    expense.amount.currency
+                ),
+            )
+            .await?;
+            ExpenseField::Payers
+        }
+        "category" => {
+            let categories = backend
+                .get_categories(expense.group)
+                .await
+                .unwrap_or_default();
+            let keyboard: Vec<Vec<InlineKeyboardButton>> = categories
+                .iter()
+                .filter_map(|category| {
+                    category.id.map(|id| {
+                        InlineKeyboardButton::callback(category.name.clone(), id.to_string())
+                    })
+                })
+                .collect::<Vec<_>>()
+                .chunks(3)
+                .map(|row| row.to_vec())
+                .collect();
+            bot.send_message(dialogue.chat_id(), "Pick a category:")
+                .reply_markup(InlineKeyboardMarkup::new(keyboard))
+                .await?;
+            dialogue
+                .update(State::EditExpenseCategory { original, expense })
+                .await?;
+            return Ok(());
+        }
+        "participants" => {
+            if expense.participants.is_empty() {
+                expense.participants = members
+                    .iter()
+                    .map(|member| member.user_id.clone())
+                    .collect();
+            }
+            bot.send_message(dialogue.chat_id(), "Who shared this expense?")
+                .reply_markup(participants_keyboard(&members, &expense.participants))
+                .await?;
+            dialogue
+                .update(State::EditExpenseParticipants { original, expense })
+                .await?;
+            return Ok(());
+        }
+        "split" => {
+            bot.send_message(dialogue.chat_id(), "How should it be split?")
+                .reply_markup(split_type_keyboard())
+                .await?;
+            dialogue
+                .update(State::EditExpenseSplitType { original, expense })
+                .await?;
+            return Ok(());
+        }
+        "save" => {
+            return ask_for_edit_confirmation(&bot, &dialogue, &backend, original, expense).await
+        }
+        "delete" => {
+            match backend
+                .delete_expense(
+                    original.id.unwrap_or_default(),
+                    dialogue.chat_id().to_string(),
+                )
+                .await
+            {
+                Ok(_) => {
+                    dialogue.update(State::Start).await?;
+                    bot.send_message(
+                        dialogue.chat_id(),
+                        format!("Deleted {}. Use /undo to bring it back.", original.title),
+                    )
+                    .await?;
+                }
+                Err(e) => {
+                    log::error!("Failed to delete expense: {:?}", e);
+                    bot.send_message(
+                        dialogue.chat_id(),
+                        "Error deleting expense. Please try again later.",
+                    )
+                    .await?;
+                }
+            }
+            return Ok(());
+        }
+        "cancel" => {
+            dialogue.update(State::Start).await?;
+            bot.send_message(dialogue.chat_id(), "Left the expense unchanged.")
+                .await?;
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+    dialogue
+        .update(State::EditExpenseValue {
+            original,
+            expense,
+            field,
+        })
+        .await?;
+    Ok(())
+}
+
+async fn recieve_edit_value(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    (original, mut expense, field): (Expense, Expense, ExpenseField),
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let text = msg.text().unwrap_or_default().trim();
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+
+    let result = match field {
+        ExpenseField::Title if text.is_empty() => {
+            Err("Please enter a title for the expense.".to_string())
+        }
+        ExpenseField::Title => {
+            expense.title = text.to_string();
+            Ok(())
+        }
+        ExpenseField::Amount => parse_expense_amount(text, &mut expense)
+            .map(|_| {
+                // A single payer paid whatever the new amount is
+                if let [payer] = expense.paid_by.as_mut_slice() {
+                    payer.amount = expense.amount.minor_units as u32;
+                }
+            })
+            .map_err(|e| e.to_string()),
+        ExpenseField::Date => {
+            let today = user_today(&backend, msg.chat.id.to_string()).await;
+            parse_date(text, today)
+                .map(|date| expense.incurred_on = date)
+                .map_err(|e| e.to_string())
+        }
+        ExpenseField::SplitValues => {
+            parse_split_values(text, &expense, &members).map(|values| expense.split_values = values)
+        }
+        ExpenseField::Payers => parse_payers(text, &members, &expense.amount.currency)
+            .map(|payers| expense.paid_by = payers),
+    };
+    if let Err(message) = result {
+        bot.send_message(msg.chat.id, message).await?;
+        return Ok(());
+    }
+    ask_for_field(&bot, &dialogue, original, expense).await
+}
+
+async fn recieve_edit_category(
+    bot: Bot,
+    dialogue: BotDialogue,
+    (original, mut expense): (Expense, Expense),
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let categories = backend
+        .get_categories(expense.group)
+        .await
+        .unwrap_or_default();
+    let Some(category_id) = q
+        .data
+        .and_then(|data| data.parse::<CategoryId>().ok())
+        .filter(|id| categories.iter().any(|category| category.id == Some(*id)))
+    else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find the category. Please try again",
+        )
+        .await?;
+        return Ok(());
+    };
+    expense.category = Some(category_id);
+    ask_for_field(&bot, &dialogue, original, expense).await
+}
+
+async fn recieve_edit_participant_toggle(
+    bot: Bot,
+    dialogue: BotDialogue,
+    (original, mut expense): (Expense, Expense),
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if option == PARTICIPANTS_DONE {
+        if expense.participants.is_empty() {
+            bot.send_message(dialogue.chat_id(), "Please select at least one person.")
+                .await?;
+            return Ok(());
+        }
+        let values_match = expense.split_values.len() == expense.participants.len()
+            && expense
+                .split_values
+                .iter()
+                .all(|value| expense.participants.contains(&value.user_id));
+        if !matches!(
+            SplitType::try_from(expense.split_type),
+            Ok(SplitType::Equal)
+        ) && !values_match
+        {
+            expense.split_type = SplitType::Equal.try_into().unwrap();
+            expense.split_values.clear();
+            bot.send_message(
+                dialogue.chat_id(),
+                "The expense is split equally again, pick Split to change that.",
+            )
+            .await?;
+        }
+        return ask_for_field(&bot, &dialogue, original, expense).await;
+    }
+
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    if !members.iter().any(|member| member.user_id == option) {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find the member in this group. Please try again",
+        )
+        .await?;
+        return Ok(());
+    }
+    if expense.participants.contains(&option) {
+        expense.participants.retain(|user_id| user_id != &option);
+    } else {
+        // Keep the participants in the order of the group so remainders are stable
+        expense.participants.push(option);
+        expense
+            .participants
+            .sort_by_key(|user_id| members.iter().position(|member| &member.user_id == user_id));
+    }
+
+    if let Some(message) = q.message {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .reply_markup(participants_keyboard(&members, &expense.participants))
+            .await?;
+    }
+    dialogue
+        .update(State::EditExpenseParticipants { original, expense })
+        .await?;
+    Ok(())
+}
+
+async fn recieve_edit_split_type(
+    bot: Bot,
+    dialogue: BotDialogue,
+    (original, mut expense): (Expense, Expense),
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(split_type) = q.data.and_then(|data| data.parse::<u32>().ok()) else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+    let Ok(kind) = SplitType::try_from(split_type) else {
+        bot.send_message(dialogue.chat_id(), "Unknown split type. Please try again.")
+            .await?;
+        return Ok(());
+    };
+    expense.split_type = split_type;
+    expense.split_values.clear();
+    if let SplitType::Equal = kind {
+        return ask_for_field(&bot, &dialogue, original, expense).await;
+    }
+
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    if expense.participants.is_empty() {
+        expense.participants = members
+            .iter()
+            .map(|member| member.user_id.clone())
+            .collect();
+    }
+    bot.send_message(dialogue.chat_id(), split_values_prompt(&expense, &members))
+        .await?;
+    dialogue
+        .update(State::EditExpenseValue {
+            original,
+            expense,
+            field: ExpenseField::SplitValues,
+        })
+        .await?;
+    Ok(())
+}
+
+/// Shows the expense as it is stored and as it will be saved.
+async fn ask_for_edit_confirmation(
+    bot: &Bot,
+    dialogue: &BotDialogue,
+    backend: &Backend,
+    original: Expense,
+    expense: Expense,
+) -> HandlerResult {
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    let split = match calculate_split(&expense, &members, backend.remainder_rule()) {
+        Ok(split) => split,
+        Err(e) => {
+            bot.send_message(dialogue.chat_id(), split_error_message(&e, &expense))
+                .await?;
+            return ask_for_field(bot, dialogue, original, expense).await;
+        }
+    };
+    let stored_split: Vec<(UserId, Money)> = backend
+        .get_expense_splits(original.group)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|split| Some(split.expenses_id) == original.id)
+        .map(|split| {
+            let share = Money::new(split.split as i64, &original.amount.currency);
+            (split.user_id, share)
+        })
+        .collect();
+    let categories = backend
+        .get_categories(expense.group)
+        .await
+        .unwrap_or_default();
+
+    let message = format!(
+        "Before:\n{}\nAfter:\n{}",
+        expense_details(&original, &members, &categories, &stored_split),
+        expense_details(&expense, &members, &categories, &split)
+    );
+    let options = [
+        ("Save", "save"),
+        ("Keep editing", "edit"),
+        ("Cancel", "cancel"),
+    ]
+    .map(|(label, option)| InlineKeyboardButton::callback(label, option));
+    bot.send_message(dialogue.chat_id(), message)
+        .reply_markup(InlineKeyboardMarkup::new([options]))
+        .await?;
+    dialogue
+        .update(State::ConfirmExpenseEdit { original, expense })
+        .await?;
+    Ok(())
+}
+
+async fn confirm_expense_edit(
+    bot: Bot,
+    dialogue: BotDialogue,
+    (original, expense): (Expense, Expense),
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    match option.as_str() {
+        "save" => match backend
+            .update_expense(expense.clone(), dialogue.chat_id().to_string())
+            .await
+        {
+            Ok(_) => {
+                dialogue.update(State::Start).await?;
+                bot.send_message(dialogue.chat_id(), "Expense updated successfully.")
+                    .await?;
+                notify_budget_alerts(&bot, &backend, &expense, Some(&original)).await;
+            }
+            Err(e) => {
+                log::error!("Failed to update expense: {:?}", e);
+                bot.send_message(
+                    dialogue.chat_id(),
+                    "Error updating expense. Please try again later.",
+                )
+                .await?;
+            }
+        },
+        "edit" => ask_for_field(&bot, &dialogue, original, expense).await?,
+        "cancel" => {
+            dialogue.update(State::Start).await?;
+            bot.send_message(dialogue.chat_id(), "Left the expense unchanged.")
+                .await?;
+        }
+        _ => {}
+    };
+    Ok(())
+}
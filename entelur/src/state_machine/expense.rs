@@ -0,0 +1,1054 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use teloxide::{
+    dispatching::{dialogue::InMemStorage, UpdateHandler},
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{
+            Category, CategoryId, CategoryKeyword, Datamodel, Expense, ExpensePayer, GroupId,
+            Money, SplitType, SplitValue, User, UserId, DEFAULT_UTC_OFFSET,
+        },
+        split::calculate_split,
+        DataError,
+    },
+    parser::{
+        amount::{parse_amount_in, parse_positive_amount},
+        date::{parse_date, today},
+        expense::{parse_expense_command, select_group},
+        ParseError,
+    },
+};
+
+use super::{balance::member_name, budget::notify_budget_alerts, state::State, user_groups};
+
+type BotDialogue = Dialogue<State, InMemStorage<State>>;
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+const SEVERAL_PAYERS: &str = "several";
+pub(super) const PARTICIPANTS_DONE: &str = "done";
+const TODAY: &str = "today";
+const YESTERDAY: &str = "yesterday";
+const NEW_CATEGORY: &str = "new";
+
+pub fn expense_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    use dptree::case;
+
+    dptree::entry()
+        .branch(case![State::AddExpense { expense }].endpoint(recieve_expense_title))
+        .branch(
+            case![State::RecieveAddExpenseAmountEqualSplit { expense }]
+                .endpoint(recieve_amount_equal_split),
+        )
+        .branch(
+            case![State::RecieveAddExpenseAmountCustomSplit { expense }]
+                .endpoint(recieve_amount_custom_split),
+        )
+        .branch(case![State::RecieveAddExpenseUser { expense }].endpoint(recieve_split_values))
+        .branch(case![State::RecieveAddExpenseDate { expense }].endpoint(recieve_date))
+        .branch(
+            case![State::RecieveAddExpensePayerAmounts { expense }].endpoint(recieve_payer_amounts),
+        )
+        .branch(
+            case![State::RecieveExpenseCategoryName { expense }].endpoint(recieve_category_name),
+        )
+}
+
+pub fn expense_callback_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
+{
+    use dptree::case;
+
+    dptree::entry()
+        .branch(case![State::RecieveAddExpenseGroup].endpoint(recieve_expense_group))
+        .branch(
+            case![State::RecieveAddExpenseParticipants { expense }]
+                .endpoint(recieve_participant_toggle),
+        )
+        .branch(case![State::RecieveAddExpenseType { expense }].endpoint(recieve_split_type))
+        .branch(case![State::RecieveAddExpenseDate { expense }].endpoint(recieve_date_option))
+        .branch(case![State::RecieveAddExpensePayer { expense }].endpoint(recieve_payer))
+        .branch(case![State::ConfirmExpense { expense }].endpoint(confirm_expense))
+        .branch(case![State::RecieveExpenseCategory { expense }].endpoint(recieve_category))
+}
+
+async fn recieve_expense_group(
+    bot: Bot,
+    dialogue: BotDialogue,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(group_id) = q.data.and_then(|data| data.parse::<GroupId>().ok()) else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find group in response. Please try again",
+        )
+        .await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+    let Ok(group) = backend.get_group(group_id).await else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find group in database. Please try again",
+        )
+        .await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+
+    let expense = Expense::new(
+        dialogue.chat_id().to_string(),
+        group.group_id,
+        Money::new(0, &group.base_currency),
+        "".to_string(),
+        "".to_string(),
+    );
+    ask_for_title(&bot, &dialogue, expense).await
+}
+
+async fn ask_for_title(bot: &Bot, dialogue: &BotDialogue, expense: Expense) -> HandlerResult {
+    bot.send_message(dialogue.chat_id(), "What was the expense for?")
+        .await?;
+    dialogue.update(State::AddExpense { expense }).await?;
+    Ok(())
+}
+
+async fn recieve_expense_title(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(title) = msg.text().map(str::trim).filter(|title| !title.is_empty()) else {
+        bot.send_message(msg.chat.id, "Please enter a title for the expense.")
+            .await?;
+        return Ok(());
+    };
+    expense.title = title.to_string();
+
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    expense.participants = members
+        .iter()
+        .map(|member| member.user_id.clone())
+        .collect();
+    bot.send_message(msg.chat.id, "Who shared this expense?")
+        .reply_markup(participants_keyboard(&members, &expense.participants))
+        .await?;
+    dialogue
+        .update(State::RecieveAddExpenseParticipants { expense })
+        .await?;
+    Ok(())
+}
+
+/// One toggle button per member, the selected members are marked with a check.
+pub(super) fn participants_keyboard(
+    members: &[User],
+    participants: &[UserId],
+) -> InlineKeyboardMarkup {
+    let mut keyboard_buttons: Vec<[InlineKeyboardButton; 1]> = members
+        .iter()
+        .map(|member| {
+            let label = if participants.contains(&member.user_id) {
+                format!("✅ {}", member.name)
+            } else {
+                member.name.to_string()
+            };
+            [InlineKeyboardButton::callback(
+                label,
+                member.user_id.to_string(),
+            )]
+        })
+        .collect();
+    keyboard_buttons.push([InlineKeyboardButton::callback("Done", PARTICIPANTS_DONE)]);
+    InlineKeyboardMarkup::new(keyboard_buttons)
+}
+
+async fn recieve_participant_toggle(
+    bot: Bot,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if option == PARTICIPANTS_DONE {
+        if expense.participants.is_empty() {
+            bot.send_message(dialogue.chat_id(), "Please select at least one person.")
+                .await?;
+            return Ok(());
+        }
+        return ask_for_split_type(&bot, &dialogue, expense).await;
+    }
+
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    if !members.iter().any(|member| member.user_id == option) {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find the member in this group. Please try again",
+        )
+        .await?;
+        return Ok(());
+    }
+    if expense.participants.contains(&option) {
+        expense.participants.retain(|user_id| user_id != &option);
+    } else {
+        // Keep the participants in the order of the group so remainders are stable
+        expense.participants.push(option);
+        expense
+            .participants
+            .sort_by_key(|user_id| members.iter().position(|member| &member.user_id == user_id));
+    }
+
+    if let Some(message) = q.message {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .reply_markup(participants_keyboard(&members, &expense.participants))
+            .await?;
+    }
+    dialogue
+        .update(State::RecieveAddExpenseParticipants { expense })
+        .await?;
+    Ok(())
+}
+
+async fn ask_for_split_type(bot: &Bot, dialogue: &BotDialogue, expense: Expense) -> HandlerResult {
+    bot.send_message(dialogue.chat_id(), "How should it be split?")
+        .reply_markup(split_type_keyboard())
+        .await?;
+    dialogue
+        .update(State::RecieveAddExpenseType { expense })
+        .await?;
+    Ok(())
+}
+
+pub(super) fn split_type_keyboard() -> InlineKeyboardMarkup {
+    let options = [
+        ("Equally", SplitType::Equal),
+        ("By percentage", SplitType::Percent),
+        ("By exact amounts", SplitType::Amount),
+        ("By shares", SplitType::Shares),
+    ]
+    .map(|(label, split_type)| {
+        let split_type: u32 = split_type.try_into().unwrap();
+        [InlineKeyboardButton::callback(
+            label,
+            split_type.to_string(),
+        )]
+    });
+    InlineKeyboardMarkup::new(options)
+}
+
+async fn recieve_split_type(
+    bot: Bot,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    q: CallbackQuery,
+) -> HandlerResult {
+    let Some(split_type) = q.data.and_then(|data| data.parse::<u32>().ok()) else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+    if SplitType::try_from(split_type).is_err() {
+        bot.send_message(dialogue.chat_id(), "Unknown split type. Please try again.")
+            .await?;
+        return Ok(());
+    }
+    expense.split_type = split_type;
+    ask_for_amount(&bot, &dialogue, expense).await
+}
+
+async fn ask_for_amount(bot: &Bot, dialogue: &BotDialogue, expense: Expense) -> HandlerResult {
+    bot.send_message(
+        dialogue.chat_id(),
+        format!(
+            "How much was it? (in {}, or name another currency like 20 USD or €20)",
+            expense.amount.currency
+        ),
+    )
+    .await?;
+    match SplitType::try_from(expense.split_type) {
+        Ok(SplitType::Equal) => {
+            dialogue
+                .update(State::RecieveAddExpenseAmountEqualSplit { expense })
+                .await?
+        }
+        _ => {
+            dialogue
+                .update(State::RecieveAddExpenseAmountCustomSplit { expense })
+                .await?
+        }
+    }
+    Ok(())
+}
+
+/// Reads the amount of the expense. The currency defaults to the one already
+/// set on the expense.
+pub(super) fn parse_expense_amount(text: &str, expense: &mut Expense) -> Result<(), ParseError> {
+    expense.amount = parse_positive_amount(text, &expense.amount.currency)?;
+    Ok(())
+}
+
+async fn recieve_amount_equal_split(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    if let Err(e) = parse_expense_amount(msg.text().unwrap_or_default(), &mut expense) {
+        bot.send_message(msg.chat.id, e.to_string()).await?;
+        return Ok(());
+    }
+    ask_for_date(&bot, &dialogue, expense).await
+}
+
+async fn recieve_amount_custom_split(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    if let Err(e) = parse_expense_amount(msg.text().unwrap_or_default(), &mut expense) {
+        bot.send_message(msg.chat.id, e.to_string()).await?;
+        return Ok(());
+    }
+
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    bot.send_message(msg.chat.id, split_values_prompt(&expense, &members))
+        .await?;
+    dialogue
+        .update(State::RecieveAddExpenseUser { expense })
+        .await?;
+    Ok(())
+}
+
+/// Asks for the split value of every participant, worded for the split type.
+pub(super) fn split_values_prompt(expense: &Expense, members: &[User]) -> String {
+    let unit = match SplitType::try_from(expense.split_type) {
+        Ok(SplitType::Percent) => "the percentage they owe".to_string(),
+        Ok(SplitType::Amount) => format!("the amount they owe in {}", expense.amount.currency),
+        _ => "their number of shares".to_string(),
+    };
+    let names: Vec<&str> = members
+        .iter()
+        .filter(|member| expense.participants.contains(&member.user_id))
+        .map(|member| member.name.as_str())
+        .collect();
+    format!(
+        "Send one line per person with their name and {}. Members: {}",
+        unit,
+        names.join(", ")
+    )
+}
+
+/// Reads one `<name> <value>` line per participant, the values are amounts for
+/// exact splits and whole numbers otherwise.
+pub(super) fn parse_split_values(
+    text: &str,
+    expense: &Expense,
+    members: &[User],
+) -> Result<Vec<SplitValue>, String> {
+    let is_amount = matches!(
+        SplitType::try_from(expense.split_type),
+        Ok(SplitType::Amount)
+    );
+    let participants: Vec<User> = members
+        .iter()
+        .filter(|member| expense.participants.contains(&member.user_id))
+        .cloned()
+        .collect();
+    parse_member_lines(text, &participants)?
+        .into_iter()
+        .map(|(member, value)| {
+            let value = if is_amount {
+                parse_amount_in(value, &expense.amount.currency).ok()
+            } else {
+                value.parse::<u32>().ok()
+            };
+            value
+                .map(|value| SplitValue::new(member.user_id.clone(), value))
+                .ok_or_else(|| format!("The value for {} is not valid.", member.name))
+        })
+        .collect()
+}
+
+async fn recieve_split_values(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    let values = parse_split_values(msg.text().unwrap_or_default(), &expense, &members);
+    let values = match values {
+        Ok(values) => values,
+        Err(message) => {
+            bot.send_message(msg.chat.id, message).await?;
+            return Ok(());
+        }
+    };
+
+    expense.split_values = values;
+    if let Err(e) = calculate_split(&expense, &members, backend.remainder_rule()) {
+        bot.send_message(msg.chat.id, split_error_message(&e, &expense))
+            .await?;
+        return Ok(());
+    }
+    ask_for_date(&bot, &dialogue, expense).await
+}
+
+pub(super) fn split_error_message(error: &DataError, expense: &Expense) -> String {
+    match error {
+        DataError::InvalidSplitPercentage => {
+            "The percentages must add up to 100. Please try again.".to_string()
+        }
+        DataError::SplitAmountMismatch => format!(
+            "The amounts must add up to {} {}. Please try again.",
+            expense.amount, expense.amount.currency
+        ),
+        DataError::InvalidSplitShares => "At least one person needs a share.".to_string(),
+        DataError::DuplicateSplitUser => {
+            "Everyone can only be listed once in the split. Please try again.".to_string()
+        }
+        DataError::PayerAmountMismatch => format!(
+            "The amounts paid must add up to {} {}.",
+            expense.amount, expense.amount.currency
+        ),
+        _ => "Couldn't split the expense. Please try again.".to_string(),
+    }
+}
+
+/// Handles `/add`, which describes the whole expense in one message. Goes
+/// straight to the confirmation so the expense can still be reviewed.
+pub async fn add_in_one_line(
+    bot: Bot,
+    msg: Message,
+    text: String,
+    dialogue: BotDialogue,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let user_id = msg.chat.id.to_string();
+    let command = match parse_expense_command(&text) {
+        Ok(command) => command,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let group = match select_group(&command, user_groups(&backend, user_id.clone()).await) {
+        Ok(group) => group,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let members = backend
+        .get_group_members(group.group_id)
+        .await
+        .unwrap_or_default();
+    let today = user_today(&backend, user_id.clone()).await;
+    let mut expense = match command.into_expense(user_id, &group, &members) {
+        Ok(expense) => expense,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    expense.incurred_on = today;
+    if let Err(e) = calculate_split(&expense, &members, backend.remainder_rule()) {
+        bot.send_message(msg.chat.id, split_error_message(&e, &expense))
+            .await?;
+        return Ok(());
+    }
+    ask_for_confirmation(&bot, &dialogue, &backend, &members, expense).await
+}
+
+/// The current date in the timezone of the user.
+pub(super) async fn user_today(backend: &Backend, user_id: UserId) -> NaiveDate {
+    let utc_offset = backend
+        .get_user(user_id)
+        .await
+        .map(|user| user.utc_offset)
+        .unwrap_or(DEFAULT_UTC_OFFSET);
+    today(utc_offset)
+}
+
+async fn ask_for_date(bot: &Bot, dialogue: &BotDialogue, expense: Expense) -> HandlerResult {
+    let options = [("Today", TODAY), ("Yesterday", YESTERDAY)]
+        .map(|(label, option)| InlineKeyboardButton::callback(label, option));
+    bot.send_message(
+        dialogue.chat_id(),
+        "When was it? You can also type a date like last friday or 2026-03-14.",
+    )
+    .reply_markup(InlineKeyboardMarkup::new([options]))
+    .await?;
+    dialogue
+        .update(State::RecieveAddExpenseDate { expense })
+        .await?;
+    Ok(())
+}
+
+async fn recieve_date(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    expense: Expense,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    set_date(
+        &bot,
+        &dialogue,
+        &backend,
+        expense,
+        msg.text().unwrap_or_default(),
+    )
+    .await
+}
+
+async fn recieve_date_option(
+    bot: Bot,
+    dialogue: BotDialogue,
+    expense: Expense,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+    set_date(&bot, &dialogue, &backend, expense, &option).await
+}
+
+async fn set_date(
+    bot: &Bot,
+    dialogue: &BotDialogue,
+    backend: &Backend,
+    mut expense: Expense,
+    text: &str,
+) -> HandlerResult {
+    let today = user_today(backend, dialogue.chat_id().to_string()).await;
+    match parse_date(text, today) {
+        Ok(date) => {
+            expense.incurred_on = date;
+            ask_for_payer(bot, dialogue, backend, expense).await
+        }
+        Err(e) => {
+            bot.send_message(dialogue.chat_id(), e.to_string()).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Asks who paid for the expense. The members of the group are offered along
+/// with an option to split the payment between several people.
+pub async fn ask_for_payer(
+    bot: &Bot,
+    dialogue: &BotDialogue,
+    backend: &Backend,
+    expense: Expense,
+) -> HandlerResult {
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    let mut keyboard_buttons: Vec<[InlineKeyboardButton; 1]> = members
+        .iter()
+        .map(|member| {
+            [InlineKeyboardButton::callback(
+                member.name.to_string(),
+                member.user_id.to_string(),
+            )]
+        })
+        .collect();
+    keyboard_buttons.push([InlineKeyboardButton::callback(
+        "Several people paid",
+        SEVERAL_PAYERS,
+    )]);
+
+    bot.send_message(dialogue.chat_id(), "Who paid for this?")
+        .reply_markup(InlineKeyboardMarkup::new(keyboard_buttons))
+        .await?;
+    dialogue
+        .update(State::RecieveAddExpensePayer { expense })
+        .await?;
+    Ok(())
+}
+
+async fn recieve_payer(
+    bot: Bot,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if option == SEVERAL_PAYERS {
+        bot.send_message(
+            dialogue.chat_id(),
+            format!(
+                "Send one line per person with their name and how much they paid, for example:\nAlice 300\nBob 150\nThe amounts must add up to {} {}.",
+                expense.amount,
+                expense.amount.currency
+            ),
+        )
+        .await?;
+        dialogue
+            .update(State::RecieveAddExpensePayerAmounts { expense })
+            .await?;
+        return Ok(());
+    }
+
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    if !members.iter().any(|member| member.user_id == option) {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find the member in this group. Please try again",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let Ok(amount) = u32::try_from(expense.amount.minor_units) else {
+        bot.send_message(dialogue.chat_id(), ParseError::AmountTooLarge.to_string())
+            .await?;
+        return ask_for_amount(&bot, &dialogue, expense).await;
+    };
+    expense.paid_by = vec![ExpensePayer::new(option, amount)];
+    ask_for_confirmation(&bot, &dialogue, &backend, &members, expense).await
+}
+
+async fn recieve_payer_amounts(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    let payers = parse_payers(
+        msg.text().unwrap_or_default(),
+        &members,
+        &expense.amount.currency,
+    );
+
+    let payers = payers.and_then(|payers| {
+        let total: u64 = payers.iter().map(|payer| payer.amount as u64).sum();
+        if total as i64 == expense.amount.minor_units {
+            Ok(payers)
+        } else {
+            Err(format!(
+                "The amounts add up to {} but the expense is {} {}. Please try again.",
+                Money::new(total as i64, &expense.amount.currency),
+                expense.amount,
+                expense.amount.currency
+            ))
+        }
+    });
+
+    match payers {
+        Ok(payers) => {
+            expense.paid_by = payers;
+            ask_for_confirmation(&bot, &dialogue, &backend, &members, expense).await
+        }
+        Err(message) => {
+            bot.send_message(msg.chat.id, message).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Reads lines of `<name> <value>` and matches the names against the members.
+/// Members can be referred to by their name or telegram username.
+fn parse_member_lines<'a, 'b>(
+    text: &'b str,
+    members: &'a [User],
+) -> Result<Vec<(&'a User, &'b str)>, String> {
+    let mut lines: Vec<(&User, &str)> = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let Some((name, value)) = line.rsplit_once(char::is_whitespace) else {
+            return Err(format!("Couldn't read \"{}\". Use <name> <value>.", line));
+        };
+        let name = name.trim().trim_start_matches('@');
+        let Some(member) = members.iter().find(|member| {
+            member.name.eq_ignore_ascii_case(name) || member.username.eq_ignore_ascii_case(name)
+        }) else {
+            return Err(format!("{} is not a member of this group.", name));
+        };
+        if lines
+            .iter()
+            .any(|(other, _)| other.user_id == member.user_id)
+        {
+            return Err(format!("{} is listed more than once.", member.name));
+        }
+        lines.push((member, value));
+    }
+
+    if lines.is_empty() {
+        return Err("Please enter at least one person.".to_string());
+    }
+    Ok(lines)
+}
+
+pub(super) fn parse_payers(
+    text: &str,
+    members: &[User],
+    currency: &str,
+) -> Result<Vec<ExpensePayer>, String> {
+    parse_member_lines(text, members)?
+        .into_iter()
+        .map(|(member, amount)| {
+            parse_amount_in(amount, currency)
+                .map(|amount| ExpensePayer::new(member.user_id.clone(), amount))
+                .map_err(|e| format!("{}: {}", member.name, e))
+        })
+        .collect()
+}
+
+/// Shows the expense with the share of every member and asks for confirmation.
+async fn ask_for_confirmation(
+    bot: &Bot,
+    dialogue: &BotDialogue,
+    backend: &Backend,
+    members: &[User],
+    mut expense: Expense,
+) -> HandlerResult {
+    let split = match calculate_split(&expense, members, backend.remainder_rule()) {
+        Ok(split) => split,
+        Err(e) => {
+            bot.send_message(dialogue.chat_id(), split_error_message(&e, &expense))
+                .await?;
+            return ask_for_title(bot, dialogue, expense).await;
+        }
+    };
+
+    if expense.category.is_none() {
+        expense.category = backend
+            .suggest_category(expense.group, &expense.title)
+            .await
+            .unwrap_or_default();
+    }
+    let categories = backend
+        .get_categories(expense.group)
+        .await
+        .unwrap_or_default();
+
+    let message = format!(
+        "Confirm the expense:\n{}",
+        expense_details(&expense, members, &categories, &split)
+    );
+
+    let options =
+        ["Confirm", "Edit", "Cancel"].map(|option| InlineKeyboardButton::callback(option, option));
+    let category = [InlineKeyboardButton::callback(
+        "Change category",
+        "Category",
+    )];
+    bot.send_message(dialogue.chat_id(), message)
+        .reply_markup(InlineKeyboardMarkup::new([
+            options.to_vec(),
+            category.to_vec(),
+        ]))
+        .await?;
+    dialogue.update(State::ConfirmExpense { expense }).await?;
+    Ok(())
+}
+
+async fn confirm_expense(
+    bot: Bot,
+    dialogue: BotDialogue,
+    expense: Expense,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    match option.as_str() {
+        "Confirm" => match backend.add_expense(expense.clone()).await {
+            Ok(_) => {
+                dialogue.update(State::Start).await?;
+                bot.send_message(dialogue.chat_id(), "Expense added successfully.")
+                    .await?;
+                notify_budget_alerts(&bot, &backend, &expense, None).await;
+            }
+            Err(e) => {
+                log::error!("Failed to add expense: {:?}", e);
+                bot.send_message(
+                    dialogue.chat_id(),
+                    "Error adding expense. Please try again later.",
+                )
+                .await?;
+            }
+        },
+        "Edit" => {
+            let mut expense = expense;
+            expense.split_values.clear();
+            expense.paid_by.clear();
+            expense.participants.clear();
+            expense.category = None;
+            ask_for_title(&bot, &dialogue, expense).await?;
+        }
+        "Category" => {
+            let categories = backend
+                .get_categories(expense.group)
+                .await
+                .unwrap_or_default();
+            let mut keyboard: Vec<Vec<InlineKeyboardButton>> = categories
+                .iter()
+                .filter_map(|category| {
+                    category.id.map(|id| {
+                        InlineKeyboardButton::callback(category.name.clone(), id.to_string())
+                    })
+                })
+                .collect::<Vec<_>>()
+                .chunks(3)
+                .map(|row| row.to_vec())
+                .collect();
+            keyboard.push(vec![InlineKeyboardButton::callback(
+                "New category",
+                NEW_CATEGORY,
+            )]);
+            bot.send_message(dialogue.chat_id(), "Pick a category:")
+                .reply_markup(InlineKeyboardMarkup::new(keyboard))
+                .await?;
+            dialogue
+                .update(State::RecieveExpenseCategory { expense })
+                .await?;
+        }
+        "Cancel" => {
+            dialogue.update(State::Start).await?;
+            bot.send_message(dialogue.chat_id(), "Canceled expense.")
+                .await?;
+        }
+        _ => {}
+    };
+
+    Ok(())
+}
+
+async fn recieve_category(
+    bot: Bot,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    if option == NEW_CATEGORY {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Send the name of the new category, optionally followed by keywords that should map to it, for example:\nPets: vet, pet food, grooming",
+        )
+        .await?;
+        dialogue
+            .update(State::RecieveExpenseCategoryName { expense })
+            .await?;
+        return Ok(());
+    }
+
+    let categories = backend
+        .get_categories(expense.group)
+        .await
+        .unwrap_or_default();
+    let Some(category_id) = option
+        .parse::<CategoryId>()
+        .ok()
+        .filter(|id| categories.iter().any(|category| category.id == Some(*id)))
+    else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find the category. Please try again",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    expense.category = Some(category_id);
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    ask_for_confirmation(&bot, &dialogue, &backend, &members, expense).await
+}
+
+async fn recieve_category_name(
+    bot: Bot,
+    dialogue: BotDialogue,
+    mut expense: Expense,
+    msg: Message,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let text = msg.text().unwrap_or_default();
+    let (name, keywords) = text.split_once(':').unwrap_or((text, ""));
+    let name = name.trim();
+    if name.is_empty() {
+        bot.send_message(dialogue.chat_id(), "The category needs a name.")
+            .await?;
+        return Ok(());
+    }
+
+    let category_id = match backend
+        .add_category(
+            Category::new(Some(expense.group), name.to_string()),
+            msg.chat.id.to_string(),
+        )
+        .await
+    {
+        Ok(category_id) => category_id,
+        Err(DataError::LogicalError) => {
+            bot.send_message(
+                dialogue.chat_id(),
+                format!("A category named {} already exists.", name),
+            )
+            .await?;
+            return Ok(());
+        }
+        Err(e) => {
+            log::error!("Failed to add category: {:?}", e);
+            bot.send_message(
+                dialogue.chat_id(),
+                "Error adding category. Please try again later.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    for keyword in keywords
+        .split(',')
+        .map(str::trim)
+        .filter(|keyword| !keyword.is_empty())
+    {
+        let keyword = CategoryKeyword::new(keyword.to_string(), Some(expense.group), category_id);
+        if let Err(e) = backend
+            .add_category_keyword(keyword, msg.chat.id.to_string())
+            .await
+        {
+            log::error!("Failed to add category keyword: {:?}", e);
+        }
+    }
+
+    expense.category = Some(category_id);
+    let members = backend
+        .get_group_members(expense.group)
+        .await
+        .unwrap_or_default();
+    ask_for_confirmation(&bot, &dialogue, &backend, &members, expense).await
+}
+
+/// The fields of an expense along with who paid and the share of everyone in
+/// `split`, one per line.
+pub(super) fn expense_details(
+    expense: &Expense,
+    members: &[User],
+    categories: &[Category],
+    split: &[(UserId, Money)],
+) -> String {
+    let mut details = format!(
+        "Title: {}\nCategory: {}\nDate: {}\nAmount: {} {}\nPaid by:\n",
+        expense.title,
+        category_name(categories, expense.category),
+        expense.incurred_on,
+        expense.amount,
+        expense.amount.currency
+    );
+    for payer in expense.payers() {
+        details.push_str(&format!(
+            "  {}: {}\n",
+            member_name(members, &payer.user_id),
+            Money::new(payer.amount as i64, &expense.amount.currency)
+        ));
+    }
+    details.push_str("Shares:\n");
+    for (user_id, share) in split.iter().filter(|(_, share)| share.minor_units > 0) {
+        details.push_str(&format!("  {}: {}\n", member_name(members, user_id), share));
+    }
+    details
+}
+
+pub(super) fn category_name(categories: &[Category], category: Option<CategoryId>) -> String {
+    categories
+        .iter()
+        .find(|candidate| candidate.id.is_some() && candidate.id == category)
+        .map(|candidate| candidate.name.clone())
+        .unwrap_or_else(|| "Uncategorized".to_string())
+}
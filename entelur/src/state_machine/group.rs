@@ -28,9 +28,11 @@ use teloxide::{
 use tokio::runtime::Handle;
 
 use crate::model::{
-    datamodel::{Datamodel, Group, GroupId},
-    sqlite::backend::SqliteBackend,
+    backend::Backend,
+    datamodel::{Datamodel, Group, GroupId, DEFAULT_CURRENCY},
+    DataError,
 };
+use crate::parser::amount::parse_currency;
 
 use super::state::State;
 
@@ -49,6 +51,7 @@ pub fn group_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync +
     dptree::entry()
         .branch(case![State::CreateGroup].endpoint(create_group))
         .branch(case![State::RecieveGroupDescription { group }].endpoint(recieve_group_description))
+        .branch(case![State::RecieveGroupCurrency { group }].endpoint(recieve_group_currency))
 }
 
 pub fn group_callback_schema(
@@ -58,6 +61,7 @@ pub fn group_callback_schema(
     dptree::entry()
         .branch(case![State::ConfirmGroup { group }].endpoint(confirm_group))
         .branch(case![State::RecieveGroupToAddUser].endpoint(recieve_group_user_add))
+        .branch(case![State::RecieveGroupToToggleSimplify].endpoint(toggle_simplify_debts))
 }
 
 async fn create_group(bot: Bot, msg: Message, dialogue: BotDialogue) -> HandlerResult {
@@ -67,6 +71,8 @@ async fn create_group(bot: Bot, msg: Message, dialogue: BotDialogue) -> HandlerR
             description: "".to_string(),
             created_by: msg.chat.id.to_string(),
             group_id: 0,
+            base_currency: "".to_string(),
+            simplify_debts: true,
         };
         bot.send_message(msg.chat.id, "Please enter the description of the group.")
             .await?;
@@ -90,19 +96,15 @@ async fn recieve_group_description(
         let mut group = group;
         group.description = description.to_string();
 
-        let options = ["Confirm", "Edit", "Cancel"]
-            .map(|option| InlineKeyboardButton::callback(option, option));
-
         bot.send_message(
             msg.chat.id,
             format!(
-                "Confirm your group details:\nName: {}\nDescription: {}",
-                group.name, group.description
+                "Please enter the currency of the group, for example {}. Balances and budgets are kept in it.",
+                DEFAULT_CURRENCY
             ),
         )
-        .reply_markup(InlineKeyboardMarkup::new([options]))
         .await?;
-        dialogue.update(State::ConfirmGroup { group }).await?;
+        dialogue.update(State::RecieveGroupCurrency { group }).await?;
     } else {
         bot.send_message(msg.chat.id, "Please enter a description for the group.")
             .await?;
@@ -110,12 +112,49 @@ async fn recieve_group_description(
     Ok(())
 }
 
+async fn recieve_group_currency(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    group: Group,
+) -> HandlerResult {
+    let Some(text) = msg.text() else {
+        bot.send_message(msg.chat.id, "Please enter a currency for the group.")
+            .await?;
+        return Ok(());
+    };
+    match parse_currency(text) {
+        Ok(currency) => {
+            let mut group = group;
+            group.base_currency = currency;
+
+            let options = ["Confirm", "Edit", "Cancel"]
+                .map(|option| InlineKeyboardButton::callback(option, option));
+
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Confirm your group details:\nName: {}\nDescription: {}\nCurrency: {}",
+                    group.name, group.description, group.base_currency
+                ),
+            )
+            .reply_markup(InlineKeyboardMarkup::new([options]))
+            .await?;
+            dialogue.update(State::ConfirmGroup { group }).await?;
+        }
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+        }
+    }
+    Ok(())
+}
+
 async fn confirm_group(
     bot: Bot,
     dialogue: BotDialogue,
     group: Group,
     q: CallbackQuery,
-    backend: Arc<SqliteBackend>,
+    backend: Arc<Backend>,
 ) -> HandlerResult {
     let Some(option) = q.data else {
         bot.send_message(
@@ -134,11 +173,19 @@ async fn confirm_group(
                     bot.send_message(dialogue.chat_id(), "Group created successfully.")
                         .await?;
                 }
+                Err(DataError::UnknownUser) => {
+                    dialogue.update(State::Start).await?;
+                    bot.send_message(
+                        dialogue.chat_id(),
+                        "Please /register before creating a group.",
+                    )
+                    .await?;
+                }
                 Err(e) => {
-                    dbg!(e);
+                    log::error!("Failed to create group: {:?}", e);
                     bot.send_message(
                         dialogue.chat_id(),
-                        format!("Error creating group. Please try again later."),
+                        "Error creating group. Please try again later.",
                     )
                     .await?;
                 }
@@ -165,7 +212,7 @@ async fn recieve_group_user_add(
     dialogue: BotDialogue,
     group: Group,
     q: CallbackQuery,
-    backend: Arc<SqliteBackend>,
+    backend: Arc<Backend>,
 ) -> HandlerResult {
     let Some(group_id) = q.data else {
         bot.send_message(
@@ -197,3 +244,50 @@ async fn recieve_group_user_add(
 
     Ok(())
 }
+
+async fn toggle_simplify_debts(
+    bot: Bot,
+    dialogue: BotDialogue,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    dialogue.update(State::Start).await?;
+    let Some(group_id) = q.data.and_then(|data| data.parse::<GroupId>().ok()) else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find group in response. Please try again",
+        )
+        .await?;
+        return Ok(());
+    };
+    let Ok(group) = backend.get_group(group_id).await else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find group in database. Please try again",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let simplify = !group.simplify_debts;
+    if backend
+        .set_group_simplify_debts(group_id, simplify, dialogue.chat_id().to_string())
+        .await
+        .is_err()
+    {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Failed to update the group. Please try again later.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let message = if simplify {
+        format!("{} will now show the simplified payments.", group.name)
+    } else {
+        format!("{} will now show the debts between members.", group.name)
+    };
+    bot.send_message(dialogue.chat_id(), message).await?;
+    Ok(())
+}
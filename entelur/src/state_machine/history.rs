@@ -0,0 +1,185 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::sync::Arc;
+
+use chrono::FixedOffset;
+use teloxide::prelude::*;
+
+use crate::{
+    model::{
+        audit::{changed_fields, snapshot_field},
+        backend::Backend,
+        datamodel::{
+            AuditAction, AuditEntity, AuditEntry, Datamodel, Money, User, DEFAULT_UTC_OFFSET,
+        },
+    },
+    parser::find_group,
+};
+
+use super::user_groups;
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Number of changes shown by /history.
+const HISTORY_LENGTH: u32 = 20;
+
+pub async fn show_history(
+    bot: Bot,
+    msg: Message,
+    text: String,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let user_id = msg.chat.id.to_string();
+    let name = text.trim().trim_start_matches('#');
+    let groups = user_groups(&backend, user_id.clone()).await;
+    let group = match find_group((!name.is_empty()).then_some(name), groups) {
+        Ok(group) => group,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let entries = match backend.get_audit_log(group.group_id, HISTORY_LENGTH).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!(
+                "Failed to load audit log of group {}: {:?}",
+                group.group_id,
+                e
+            );
+            bot.send_message(
+                msg.chat.id,
+                "Error loading the history. Please try again later.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if entries.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            format!("Nothing has changed in {} yet.", group.name),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let utc_offset = backend
+        .get_user(user_id)
+        .await
+        .map(|user| user.utc_offset)
+        .unwrap_or(DEFAULT_UTC_OFFSET);
+    let timezone =
+        FixedOffset::east_opt(utc_offset * 60).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    let mut users = backend
+        .get_group_members(group.group_id)
+        .await
+        .unwrap_or_default();
+    for entry in &entries {
+        for user_id in [&entry.actor, &entry.entity_id] {
+            if users.iter().any(|user| &user.user_id == user_id) {
+                continue;
+            }
+            // Members who left the group are still named in its history
+            if let Ok(user) = backend.get_user(user_id.clone()).await {
+                users.push(user);
+            }
+        }
+    }
+
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{} {}",
+                entry
+                    .created_at
+                    .with_timezone(&timezone)
+                    .format("%d %b %H:%M"),
+                describe_entry(entry, &users)
+            )
+        })
+        .collect();
+    bot.send_message(
+        msg.chat.id,
+        format!("Latest changes in {}:\n{}", group.name, lines.join("\n")),
+    )
+    .await?;
+    Ok(())
+}
+
+/// One line about the change, like "Alice changed expense Pizza (amount)".
+pub(super) fn describe_entry(entry: &AuditEntry, users: &[User]) -> String {
+    let name = |user_id: &str| {
+        users
+            .iter()
+            .find(|user| user.user_id == user_id)
+            .map_or(user_id.to_string(), |user| user.name.clone())
+    };
+    let Some(snapshot) = entry.after.as_ref().or(entry.before.as_ref()) else {
+        return format!("{} {} {}", name(&entry.actor), entry.action, entry.entity);
+    };
+
+    let label = match entry.entity {
+        AuditEntity::Membership => name(&entry.entity_id),
+        AuditEntity::Expense => format!(
+            "{} ({} {})",
+            snapshot_field(snapshot, "title").unwrap_or_default(),
+            snapshot_money(snapshot),
+            snapshot_field(snapshot, "currency").unwrap_or_default()
+        ),
+        AuditEntity::Settlement => format!(
+            "from {} to {}",
+            name(&snapshot_field(snapshot, "payer").unwrap_or_default()),
+            name(&snapshot_field(snapshot, "payee").unwrap_or_default())
+        ),
+        AuditEntity::RecurringExpense => format!(
+            "#{} {}",
+            entry.entity_id,
+            snapshot_field(snapshot, "title").unwrap_or_default()
+        ),
+        _ => snapshot_field(snapshot, "name")
+            .or_else(|| snapshot_field(snapshot, "keyword"))
+            .unwrap_or_else(|| format!("#{}", entry.entity_id)),
+    };
+    let mut line = format!(
+        "{} {} {} {}",
+        name(&entry.actor),
+        entry.action,
+        entry.entity,
+        label
+    );
+    if let (AuditAction::Update, Some(before), Some(after)) =
+        (entry.action, &entry.before, &entry.after)
+    {
+        let changed = changed_fields(before, after);
+        if !changed.is_empty() {
+            line.push_str(&format!(" - {}", changed.join(", ")));
+        }
+    }
+    if entry.reverts.is_some() {
+        line.push_str(" (undo)");
+    }
+    line
+}
+
+fn snapshot_money(snapshot: &str) -> Money {
+    let amount = snapshot_field(snapshot, "amount")
+        .and_then(|amount| amount.parse::<i64>().ok())
+        .unwrap_or_default();
+    let currency = snapshot_field(snapshot, "currency").unwrap_or_default();
+    Money::new(amount, &currency)
+}
@@ -14,8 +14,19 @@ You should have received a copy of the GNU General Public License along with Foo
 If not, see <https://www.gnu.org/licenses/>.
 */
 
+pub mod balance;
+pub mod budget;
+pub mod currency;
+pub mod edit;
+pub mod expense;
 pub mod group;
+pub mod history;
+pub mod recurring;
+pub mod settle;
 pub mod state;
+pub mod statement;
+pub mod summary;
+pub mod undo;
 pub mod user;
 
 use std::{clone, sync::Arc};
@@ -35,12 +46,15 @@ use state::State;
 
 use crate::{
     model::{
-        datamodel::{Datamodel, Group},
-        sqlite::backend::{self, SqliteBackend},
+        backend::Backend,
+        datamodel::{Datamodel, Group, UserId},
         DataError,
     },
     state_machine::{
+        edit::{edit_callback_schema, edit_schema},
+        expense::{expense_callback_schema, expense_schema},
         group::{group_callback_schema, group_schema},
+        settle::{settle_callback_schema, settle_schema},
         user::{user_callback_schema, user_schemas},
     },
 };
@@ -68,6 +82,24 @@ enum Command {
     AddUser,
     #[command(description = "Add Expense")]
     AddExpense,
+    #[command(description = "Edit one of your recent expenses")]
+    EditExpense,
+    #[command(description = "Add an expense in one line, e.g. /add 450 pizza @alice")]
+    Add { text: String },
+    #[command(description = "Add a recurring expense, e.g. /recurring monthly 1200 rent")]
+    Recurring { text: String },
+    #[command(description = "Show recurring expenses")]
+    ListRecurring,
+    #[command(description = "Stop a recurring expense, e.g. /cancelrecurring 3")]
+    CancelRecurring { id: String },
+    #[command(description = "Set a monthly budget, e.g. /budget 20000 or /budget 5000 food")]
+    Budget { text: String },
+    #[command(description = "Undo your last change to an expense")]
+    Undo,
+    #[command(description = "Show the latest changes to a group, e.g. /history #flat")]
+    History { text: String },
+    #[command(description = "Set your timezone as an offset from UTC, e.g. /timezone +05:30")]
+    Timezone { offset: String },
     #[command(description = "Show pending settlements")]
     ShowPending,
     #[command(description = "Settle the group")]
@@ -76,6 +108,10 @@ enum Command {
     ShowSummary,
     #[command(description = "Show statement for past n months")]
     ShowStatement { months: u32 },
+    #[command(description = "Switch a group between simplified and raw debts")]
+    SimplifyDebts,
+    #[command(description = "Import exchange rates, one date,currency,base_currency,rate per line (admin only)")]
+    ImportRates { text: String },
 }
 
 pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
@@ -90,10 +126,21 @@ pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'stat
                 .branch(case![Command::ModifyGroup].endpoint(modify_group))
                 .branch(case![Command::AddUser].endpoint(add_user))
                 .branch(case![Command::AddExpense].endpoint(add_expense))
-                .branch(case![Command::ShowPending].endpoint(show_pending))
+                .branch(case![Command::EditExpense].endpoint(edit::edit_expense))
+                .branch(case![Command::Add { text }].endpoint(expense::add_in_one_line))
+                .branch(case![Command::Recurring { text }].endpoint(recurring::add_recurring))
+                .branch(case![Command::ListRecurring].endpoint(recurring::list_recurring))
+                .branch(case![Command::CancelRecurring { id }].endpoint(recurring::cancel_recurring))
+                .branch(case![Command::Budget { text }].endpoint(budget::budget))
+                .branch(case![Command::Undo].endpoint(undo::undo))
+                .branch(case![Command::History { text }].endpoint(history::show_history))
+                .branch(case![Command::Timezone { offset }].endpoint(user::set_timezone))
+                .branch(case![Command::ShowPending].endpoint(balance::show_pending))
                 .branch(case![Command::Settle].endpoint(settle))
-                .branch(case![Command::ShowSummary].endpoint(show_summary))
-                .branch(case![Command::ShowStatement { months }].endpoint(show_statement)),
+                .branch(case![Command::ShowSummary].endpoint(summary::show_summary))
+                .branch(case![Command::ShowStatement { months }].endpoint(statement::show_statement))
+                .branch(case![Command::SimplifyDebts].endpoint(simplify_debts))
+                .branch(case![Command::ImportRates { text }].endpoint(currency::import_rates)),
         )
         .branch(case![Command::Help].endpoint(help));
 
@@ -101,11 +148,23 @@ pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'stat
         .branch(
             Update::filter_message()
                 .branch(command_handler)
-                .branch(user_schemas().chain(group_schema())),
+                .branch(
+                    user_schemas()
+                        .chain(group_schema())
+                        .chain(settle_schema())
+                        .chain(expense_schema())
+                        .chain(edit_schema()),
+                ),
         )
         .branch(
             Update::filter_callback_query()
-                .branch(user_callback_schema().chain(group_callback_schema())),
+                .branch(
+                    user_callback_schema()
+                        .chain(group_callback_schema())
+                        .chain(settle_callback_schema())
+                        .chain(expense_callback_schema())
+                        .chain(edit_callback_schema()),
+                ),
         )
         .branch(endpoint(invalid_state))
 }
@@ -138,7 +197,17 @@ async fn register(bot: Bot, msg: Message, dialogue: BotDialogue) -> HandlerResul
     Ok(())
 }
 
-async fn create_group(bot: Bot, msg: Message, dialogue: BotDialogue) -> HandlerResult {
+async fn create_group(
+    bot: Bot,
+    msg: Message,
+    backend: Arc<Backend>,
+    dialogue: BotDialogue,
+) -> HandlerResult {
+    if backend.get_user(msg.chat.id.to_string()).await.is_err() {
+        bot.send_message(msg.chat.id, "Please /register before creating a group.")
+            .await?;
+        return Ok(());
+    }
     bot.send_message(msg.chat.id, "Please enter a name for the group.")
         .await?;
     dialogue.update(State::CreateGroup).await?;
@@ -153,7 +222,7 @@ async fn modify_group(bot: Bot, msg: Message) -> HandlerResult {
 async fn add_user(
     bot: Bot,
     msg: Message,
-    backend: Arc<SqliteBackend>,
+    backend: Arc<Backend>,
     dialogue: BotDialogue,
 ) -> HandlerResult {
     bot.send_message(msg.chat.id, "Please select a group.")
@@ -163,7 +232,7 @@ async fn add_user(
         .get_membership(msg.chat.id.to_string())
         .await
     else {
-        bot.send_message(msg.chat.id, format!("Please select a group."))
+        bot.send_message(msg.chat.id, "Please select a group.")
             .await?;
         return Ok(());
     };
@@ -187,29 +256,82 @@ async fn add_user(
     Ok(())
 }
 
-async fn add_expense(bot: Bot, msg: Message) -> HandlerResult {
-    to_do_message(&bot, msg.chat.id).await?;
+async fn add_expense(
+    bot: Bot,
+    msg: Message,
+    backend: Arc<Backend>,
+    dialogue: BotDialogue,
+) -> HandlerResult {
+    if select_group(&bot, msg.chat.id, &backend).await? {
+        dialogue.update(State::RecieveAddExpenseGroup).await?;
+    }
     Ok(())
 }
 
-async fn show_pending(bot: Bot, msg: Message) -> HandlerResult {
-    to_do_message(&bot, msg.chat.id).await?;
+async fn simplify_debts(
+    bot: Bot,
+    msg: Message,
+    backend: Arc<Backend>,
+    dialogue: BotDialogue,
+) -> HandlerResult {
+    if select_group(&bot, msg.chat.id, &backend).await? {
+        dialogue
+            .update(State::RecieveGroupToToggleSimplify)
+            .await?;
+    }
     Ok(())
 }
 
-async fn settle(bot: Bot, msg: Message) -> HandlerResult {
-    to_do_message(&bot, msg.chat.id).await?;
+async fn settle(
+    bot: Bot,
+    msg: Message,
+    backend: Arc<Backend>,
+    dialogue: BotDialogue,
+) -> HandlerResult {
+    if select_group(&bot, msg.chat.id, &backend).await? {
+        dialogue.update(State::RecieveGroupToSettle).await?;
+    }
     Ok(())
 }
 
-async fn show_summary(bot: Bot, msg: Message) -> HandlerResult {
-    to_do_message(&bot, msg.chat.id).await?;
-    Ok(())
+/// Sends an inline keyboard with the groups of the user. Returns false when the
+/// user is not part of any group.
+async fn select_group(
+    bot: &Bot,
+    chat_id: ChatId,
+    backend: &Backend,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let groups = user_groups(backend, chat_id.to_string()).await;
+    if groups.is_empty() {
+        bot.send_message(
+            chat_id,
+            "You are not part of any group yet. Use /creategroup to create one.",
+        )
+        .await?;
+        return Ok(false);
+    }
+
+    let keyboard_buttons = groups
+        .iter()
+        .map(|g| [InlineKeyboardButton::callback(g.name.to_string(), g.group_id.to_string())]);
+    bot.send_message(chat_id, "Please select a group.")
+        .reply_markup(InlineKeyboardMarkup::new(keyboard_buttons))
+        .await?;
+    Ok(true)
 }
 
-async fn show_statement(bot: Bot, msg: Message) -> HandlerResult {
-    to_do_message(&bot, msg.chat.id).await?;
-    Ok(())
+/// Groups the user is a member of.
+async fn user_groups(backend: &Backend, user_id: UserId) -> Vec<Group> {
+    let memberships = backend.get_membership(user_id).await.unwrap_or_default();
+
+    let mut groups: Vec<Group> = Vec::new();
+    for membership in memberships {
+        let Ok(group) = backend.get_group(membership.group_id).await else {
+            continue;
+        };
+        groups.push(group);
+    }
+    groups
 }
 
 async fn to_do_message(bot: &Bot, id: ChatId) -> HandlerResult {
@@ -219,9 +341,7 @@ async fn to_do_message(bot: &Bot, id: ChatId) -> HandlerResult {
 }
 
 impl From<DataError> for () {
-    fn from(value: DataError) -> Self {
-        ()
-    }
+    fn from(value: DataError) -> Self {}
 }
 /*
 For reference -
@@ -0,0 +1,227 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::sync::Arc;
+
+use teloxide::prelude::*;
+
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{Datamodel, RecurringExpense, RecurringExpenseId},
+        split::calculate_split,
+    },
+    parser::expense::{parse_recurring_command, select_group},
+    scheduler::{add_due_expenses, notify_occurrences},
+};
+
+use super::{
+    expense::{split_error_message, user_today},
+    user_groups,
+};
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+pub async fn add_recurring(
+    bot: Bot,
+    msg: Message,
+    text: String,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let user_id = msg.chat.id.to_string();
+    let (schedule, command) = match parse_recurring_command(&text) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let group = match select_group(&command, user_groups(&backend, user_id.clone()).await) {
+        Ok(group) => group,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let members = backend
+        .get_group_members(group.group_id)
+        .await
+        .unwrap_or_default();
+    let start_date = user_today(&backend, user_id.clone()).await;
+    let mut template = match command.into_expense(user_id, &group, &members) {
+        Ok(expense) => expense,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    if let Err(e) = calculate_split(&template, &members, backend.remainder_rule()) {
+        bot.send_message(msg.chat.id, split_error_message(&e, &template))
+            .await?;
+        return Ok(());
+    }
+    template.category = backend
+        .suggest_category(group.group_id, &template.title)
+        .await
+        .unwrap_or_default();
+
+    let recurring = RecurringExpense::new(template, schedule, start_date);
+    match backend.add_recurring_expense(recurring.clone()).await {
+        Ok(recurring_id) => {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Recurring expense #{} created: {}, {} {} {} starting {}. Use /cancelrecurring {} to stop it.",
+                    recurring_id,
+                    recurring.template.title,
+                    recurring.template.amount,
+                    recurring.template.amount.currency,
+                    recurring.schedule,
+                    start_date,
+                    recurring_id
+                ),
+            )
+            .await?;
+            // Today's occurrence is added right away instead of on the next check
+            match add_due_expenses(&backend).await {
+                Ok(occurrences) => notify_occurrences(&bot, &backend, occurrences).await,
+                Err(e) => log::error!("Failed to add due recurring expenses: {:?}", e),
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to add recurring expense: {:?}", e);
+            bot.send_message(
+                msg.chat.id,
+                "Error adding recurring expense. Please try again later.",
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn list_recurring(bot: Bot, msg: Message, backend: Arc<Backend>) -> HandlerResult {
+    let groups = user_groups(&backend, msg.chat.id.to_string()).await;
+    let recurring_expenses = backend.get_recurring_expenses().await.unwrap_or_default();
+
+    let mut lines: Vec<String> = Vec::new();
+    for group in &groups {
+        for recurring in recurring_expenses
+            .iter()
+            .filter(|recurring| recurring.template.group == group.group_id)
+        {
+            lines.push(format!(
+                "#{} {}: {} {} {}, next on {} ({})",
+                recurring.id.unwrap_or_default(),
+                recurring.template.title,
+                recurring.template.amount,
+                recurring.template.amount.currency,
+                recurring.schedule,
+                recurring
+                    .next_due()
+                    .map(|date| date.to_string())
+                    .unwrap_or_default(),
+                group.name
+            ));
+        }
+    }
+
+    if lines.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "There are no recurring expenses. Add one with /recurring monthly 1200 rent.",
+        )
+        .await?;
+        return Ok(());
+    }
+    bot.send_message(
+        msg.chat.id,
+        format!("Recurring expenses:\n{}", lines.join("\n")),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn cancel_recurring(
+    bot: Bot,
+    msg: Message,
+    id: String,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Ok(recurring_id) = id
+        .trim()
+        .trim_start_matches('#')
+        .parse::<RecurringExpenseId>()
+    else {
+        bot.send_message(
+            msg.chat.id,
+            "Give the number of the recurring expense, for example /cancelrecurring 3. /listrecurring shows the numbers.",
+        )
+        .await?;
+        return Ok(());
+    };
+    let groups = user_groups(&backend, msg.chat.id.to_string()).await;
+    let recurring = backend
+        .get_recurring_expenses()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .find(|recurring| {
+            recurring.id == Some(recurring_id)
+                && groups
+                    .iter()
+                    .any(|group| group.group_id == recurring.template.group)
+        });
+    let Some(recurring) = recurring else {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "There is no recurring expense #{} in your groups.",
+                recurring_id
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    match backend
+        .cancel_recurring_expense(recurring_id, msg.chat.id.to_string())
+        .await
+    {
+        Ok(_) => {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Stopped the recurring expense {}. Expenses already added are kept.",
+                    recurring.template.title
+                ),
+            )
+            .await?;
+        }
+        Err(e) => {
+            log::error!(
+                "Failed to cancel recurring expense {}: {:?}",
+                recurring_id,
+                e
+            );
+            bot.send_message(
+                msg.chat.id,
+                "Error cancelling recurring expense. Please try again later.",
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+
+use teloxide::{
+    dispatching::{dialogue::InMemStorage, UpdateHandler},
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{Datamodel, Group, GroupId, Money, Settlement, User},
+        DataError,
+    },
+    parser::amount::parse_amount_in,
+};
+
+use super::{balance::member_name, state::State};
+
+type BotDialogue = Dialogue<State, InMemStorage<State>>;
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+pub fn settle_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    use dptree::case;
+
+    dptree::entry()
+        .branch(case![State::RecieveSettleAmount { group, payee }].endpoint(recieve_settle_amount))
+}
+
+pub fn settle_callback_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
+{
+    use dptree::case;
+
+    dptree::entry()
+        .branch(case![State::RecieveGroupToSettle].endpoint(recieve_settle_group))
+        .branch(
+            case![State::RecieveSettleCounterparty { group }].endpoint(recieve_settle_counterparty),
+        )
+        .branch(
+            case![State::ConfirmSettlement {
+                group,
+                payee,
+                settlement
+            }]
+            .endpoint(confirm_settlement),
+        )
+}
+
+async fn recieve_settle_group(
+    bot: Bot,
+    dialogue: BotDialogue,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(group_id) = q.data.and_then(|data| data.parse::<GroupId>().ok()) else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find group in response. Please try again",
+        )
+        .await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+    let Ok(group) = backend.get_group(group_id).await else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find group in database. Please try again",
+        )
+        .await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    };
+
+    let user_id = dialogue.chat_id().to_string();
+    let members = backend
+        .get_group_members(group_id)
+        .await
+        .unwrap_or_default();
+    let others: Vec<&User> = members
+        .iter()
+        .filter(|member| member.user_id != user_id)
+        .collect();
+    if others.is_empty() {
+        bot.send_message(
+            dialogue.chat_id(),
+            "There is nobody else in this group to settle with.",
+        )
+        .await?;
+        dialogue.update(State::Start).await?;
+        return Ok(());
+    }
+
+    let debts = backend.get_group_debts(group_id).await.unwrap_or_default();
+    let mut message = String::new();
+    for debt in debts.iter().filter(|debt| debt.from == user_id) {
+        message.push_str(&format!(
+            "You owe {} {}\n",
+            member_name(&members, &debt.to),
+            Money::new(debt.amount, &group.base_currency)
+        ));
+    }
+    message.push_str("Who did you pay?");
+
+    let keyboard_buttons = others.iter().map(|member| {
+        [InlineKeyboardButton::callback(
+            member.name.to_string(),
+            member.user_id.to_string(),
+        )]
+    });
+    bot.send_message(dialogue.chat_id(), message)
+        .reply_markup(InlineKeyboardMarkup::new(keyboard_buttons))
+        .await?;
+    dialogue
+        .update(State::RecieveSettleCounterparty { group })
+        .await?;
+    Ok(())
+}
+
+async fn recieve_settle_counterparty(
+    bot: Bot,
+    dialogue: BotDialogue,
+    group: Group,
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let members = backend
+        .get_group_members(group.group_id)
+        .await
+        .unwrap_or_default();
+    let Some(payee) = q
+        .data
+        .and_then(|user_id| members.into_iter().find(|member| member.user_id == user_id))
+    else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Didn't find the member in this group. Please try again",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    bot.send_message(
+        dialogue.chat_id(),
+        format!(
+            "How much did you pay {}? (in {})",
+            payee.name, group.base_currency
+        ),
+    )
+    .await?;
+    dialogue
+        .update(State::RecieveSettleAmount { group, payee })
+        .await?;
+    Ok(())
+}
+
+async fn recieve_settle_amount(
+    bot: Bot,
+    msg: Message,
+    dialogue: BotDialogue,
+    (group, payee): (Group, User),
+) -> HandlerResult {
+    let amount = match parse_amount_in(msg.text().unwrap_or_default(), &group.base_currency) {
+        Ok(amount) => amount,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    let settlement = Settlement::new(
+        group.group_id,
+        msg.chat.id.to_string(),
+        payee.user_id.clone(),
+        amount,
+    );
+    let options =
+        ["Confirm", "Edit", "Cancel"].map(|option| InlineKeyboardButton::callback(option, option));
+    bot.send_message(
+        msg.chat.id,
+        format!(
+            "Confirm your payment:\nGroup: {}\nPaid to: {}\nAmount: {} {}",
+            group.name,
+            payee.name,
+            Money::new(amount as i64, &group.base_currency),
+            group.base_currency
+        ),
+    )
+    .reply_markup(InlineKeyboardMarkup::new([options]))
+    .await?;
+    dialogue
+        .update(State::ConfirmSettlement {
+            group,
+            payee,
+            settlement,
+        })
+        .await?;
+    Ok(())
+}
+
+async fn confirm_settlement(
+    bot: Bot,
+    dialogue: BotDialogue,
+    (group, payee, settlement): (Group, User, Settlement),
+    q: CallbackQuery,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let Some(option) = q.data else {
+        bot.send_message(
+            dialogue.chat_id(),
+            "Callback query not found. Please try again. Or /cancel to go back to main menu.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    match option.as_str() {
+        "Confirm" => {
+            let amount = Money::new(settlement.amount as i64, &group.base_currency);
+            let payer = settlement.payer.clone();
+            match backend
+                .add_settlement(settlement, dialogue.chat_id().to_string())
+                .await
+            {
+                Ok(_) => {
+                    dialogue.update(State::Start).await?;
+                    bot.send_message(dialogue.chat_id(), "Payment recorded successfully.")
+                        .await?;
+                    if let Ok(payee_chat) = payee.user_id.parse::<i64>() {
+                        let payer_name = backend
+                            .get_user(payer)
+                            .await
+                            .map(|user| user.name)
+                            .unwrap_or_default();
+                        // The payee may have blocked the bot, that shouldn't fail the settlement
+                        let _ = bot
+                            .send_message(
+                                ChatId(payee_chat),
+                                format!(
+                                    "{} recorded a payment of {} {} to you in {}.",
+                                    payer_name, amount, group.base_currency, group.name
+                                ),
+                            )
+                            .await;
+                    }
+                }
+                Err(DataError::LogicalError) => {
+                    dialogue.update(State::Start).await?;
+                    bot.send_message(
+                        dialogue.chat_id(),
+                        format!(
+                            "You or {} are no longer a member of {}.",
+                            payee.name, group.name
+                        ),
+                    )
+                    .await?;
+                }
+                Err(e) => {
+                    log::error!("Failed to record settlement: {:?}", e);
+                    bot.send_message(
+                        dialogue.chat_id(),
+                        "Error recording payment. Please try again later.",
+                    )
+                    .await?;
+                }
+            }
+        }
+        "Edit" => {
+            bot.send_message(
+                dialogue.chat_id(),
+                format!(
+                    "How much did you pay {}? (in {})",
+                    payee.name, group.base_currency
+                ),
+            )
+            .await?;
+            dialogue
+                .update(State::RecieveSettleAmount { group, payee })
+                .await?;
+        }
+        "Cancel" => {
+            dialogue.update(State::Start).await?;
+            bot.send_message(dialogue.chat_id(), "Canceled settlement.")
+                .await?;
+        }
+        _ => {}
+    };
+
+    Ok(())
+}
@@ -23,6 +23,16 @@ pub struct UserData {
 }
 
 
+/// A field of an expense that is edited by typing its new value.
+#[derive(Clone)]
+pub enum ExpenseField {
+    Title,
+    Amount,
+    Date,
+    SplitValues,
+    Payers,
+}
+
 #[derive(Clone, Default)]
 pub enum State {
     #[default]
@@ -36,6 +46,9 @@ pub enum State {
     RecieveGroupDescription {
         group: datamodel::Group
     },
+    RecieveGroupCurrency {
+        group: datamodel::Group
+    },
     ConfirmGroup {
         group: datamodel::Group
     },
@@ -43,17 +56,88 @@ pub enum State {
     RecieveUserToAdd {
         group: datamodel::Group
     },
+    RecieveGroupToToggleSimplify,
+    RecieveGroupToSettle,
+    RecieveSettleCounterparty {
+        group: datamodel::Group
+    },
+    RecieveSettleAmount {
+        group: datamodel::Group,
+        payee: datamodel::User
+    },
+    ConfirmSettlement {
+        group: datamodel::Group,
+        payee: datamodel::User,
+        settlement: datamodel::Settlement
+    },
+    RecieveAddExpensePayer {
+        expense: datamodel::Expense
+    },
+    RecieveAddExpensePayerAmounts {
+        expense: datamodel::Expense
+    },
+    ConfirmExpense {
+        expense: datamodel::Expense
+    },
+    RecieveExpenseCategory {
+        expense: datamodel::Expense
+    },
+    RecieveExpenseCategoryName {
+        expense: datamodel::Expense
+    },
+    EditExpense,
+    EditExpenseField {
+        original: datamodel::Expense,
+        expense: datamodel::Expense
+    },
+    EditExpenseValue {
+        original: datamodel::Expense,
+        expense: datamodel::Expense,
+        field: ExpenseField
+    },
+    EditExpenseCategory {
+        original: datamodel::Expense,
+        expense: datamodel::Expense
+    },
+    EditExpenseParticipants {
+        original: datamodel::Expense,
+        expense: datamodel::Expense
+    },
+    EditExpenseSplitType {
+        original: datamodel::Expense,
+        expense: datamodel::Expense
+    },
+    ConfirmExpenseEdit {
+        original: datamodel::Expense,
+        expense: datamodel::Expense
+    },
     ModifyGroup,
     RecieveGroupNameToModify,
     RecieveModifyGroupAction,
     RecieveModifyGroupUserToAdd,
     RecieveUserToRemove,
     RecieveNewDescription,
-    AddExpense,
-    RecieveAddExpenseType,
-    RecieveAddExpenseUser,
+    AddExpense {
+        expense: datamodel::Expense
+    },
+    RecieveAddExpenseType {
+        expense: datamodel::Expense
+    },
+    RecieveAddExpenseUser {
+        expense: datamodel::Expense
+    },
     RecieveAddExpenseGroup,
-    RecieveAddExpenseAmountEqualSplit,
-    RecieveAddExpenseAmountCustomSplit,
+    RecieveAddExpenseParticipants {
+        expense: datamodel::Expense
+    },
+    RecieveAddExpenseAmountEqualSplit {
+        expense: datamodel::Expense
+    },
+    RecieveAddExpenseAmountCustomSplit {
+        expense: datamodel::Expense
+    },
+    RecieveAddExpenseDate {
+        expense: datamodel::Expense
+    },
 }
 
@@ -0,0 +1,299 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use chrono::{Datelike, Months, NaiveDate};
+use teloxide::{
+    prelude::*,
+    types::{InputFile, ParseMode},
+    utils::html,
+};
+
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{Datamodel, Money, User, DEFAULT_UTC_OFFSET},
+        statement::{group_entries, EntryKind, StatementEntry},
+        DataError,
+    },
+    parser::date::today,
+};
+
+use super::{balance::member_name, user_groups};
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Longest text Telegram accepts in a single message.
+const MESSAGE_LIMIT: usize = 4096;
+
+pub async fn show_statement(
+    bot: Bot,
+    msg: Message,
+    months: u32,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let user_id = msg.chat.id.to_string();
+    let groups = user_groups(&backend, user_id.clone()).await;
+    if groups.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "You are not part of any group yet. Use /creategroup to create one.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let utc_offset = backend
+        .get_user(user_id.clone())
+        .await
+        .map(|user| user.utc_offset)
+        .unwrap_or(DEFAULT_UTC_OFFSET);
+    let months = months.max(1);
+    let today = today(utc_offset);
+    let from = today
+        .checked_sub_months(Months::new(months))
+        .unwrap_or(NaiveDate::MIN);
+
+    let mut entries: Vec<StatementEntry> = Vec::new();
+    let mut members: Vec<User> = Vec::new();
+    let mut problems: Vec<String> = Vec::new();
+    for group in groups {
+        let rates = backend
+            .get_currency_rates(group.base_currency.clone())
+            .await
+            .unwrap_or_default();
+        let group_entries = match (
+            backend.get_expenses(group.group_id).await,
+            backend.get_expense_splits(group.group_id).await,
+            backend.get_settlements(group.group_id).await,
+        ) {
+            (Ok(expenses), Ok(splits), Ok(settlements)) => group_entries(
+                &user_id,
+                &group,
+                &expenses,
+                &splits,
+                &settlements,
+                &rates,
+                utc_offset,
+            ),
+            _ => Err(DataError::DatabaseError),
+        };
+        match group_entries {
+            Ok(group_entries) => entries.extend(group_entries),
+            Err(DataError::MissingCurrencyRate) => problems.push(format!(
+                "{} is left out, an exchange rate is missing for one of its expenses.",
+                group.name
+            )),
+            Err(_) => problems.push(format!("{} couldn't be loaded.", group.name)),
+        }
+        members.extend(
+            backend
+                .get_group_members(group.group_id)
+                .await
+                .unwrap_or_default(),
+        );
+    }
+
+    let mut statement = format_statement(&entries, &members, from, today, months);
+    for problem in problems {
+        statement.push_str(&format!("\n{problem}"));
+    }
+
+    let message = format!("<pre>{}</pre>", html::escape(&statement));
+    if message.chars().count() <= MESSAGE_LIMIT {
+        bot.send_message(msg.chat.id, message)
+            .parse_mode(ParseMode::Html)
+            .await?;
+    } else {
+        bot.send_document(
+            msg.chat.id,
+            InputFile::memory(statement.into_bytes()).file_name(format!("statement-{today}.txt")),
+        )
+        .caption(format!("Your statement for the past {months} months"))
+        .await?;
+    }
+    Ok(())
+}
+
+/// Renders the statement as plain text. Every currency gets its own section
+/// starting with the balance carried over from before `from`, followed by the
+/// entries of each month with a running balance and the month's subtotal.
+fn format_statement(
+    entries: &[StatementEntry],
+    members: &[User],
+    from: NaiveDate,
+    to: NaiveDate,
+    months: u32,
+) -> String {
+    let mut by_currency: BTreeMap<&str, Vec<&StatementEntry>> = BTreeMap::new();
+    for entry in entries {
+        by_currency.entry(&entry.currency).or_default().push(entry);
+    }
+
+    let mut text = format!("Statement from {from} to {to}\n");
+    if by_currency
+        .values()
+        .flatten()
+        .all(|entry| entry.date < from)
+    {
+        text.push_str(&format!(
+            "\nNo expenses or payments in the past {months} months.\n"
+        ));
+    }
+    for (currency, entries) in by_currency {
+        let mut balance: i64 = entries
+            .iter()
+            .filter(|entry| entry.date < from)
+            .map(|entry| entry.amount)
+            .sum();
+        text.push_str(&format!(
+            "\n{currency}\nOpening balance {}\n",
+            signed(balance, currency)
+        ));
+
+        let mut month: Option<(i32, u32)> = None;
+        let mut subtotal: i64 = 0;
+        for entry in entries.iter().filter(|entry| entry.date >= from) {
+            let entry_month = (entry.date.year(), entry.date.month());
+            if month != Some(entry_month) {
+                if month.is_some() {
+                    text.push_str(&format!("  Month total {}\n", signed(subtotal, currency)));
+                }
+                text.push_str(&format!("\n{}\n", entry.date.format("%B %Y")));
+                month = Some(entry_month);
+                subtotal = 0;
+            }
+            balance += entry.amount;
+            subtotal += entry.amount;
+            text.push_str(&format!(
+                "  {} {} [{}]\n    {}  balance {}\n",
+                entry.date.format("%d %b"),
+                describe(entry, members, currency),
+                entry.group,
+                signed(entry.amount, currency),
+                signed(balance, currency)
+            ));
+        }
+        if month.is_some() {
+            text.push_str(&format!("  Month total {}\n", signed(subtotal, currency)));
+        }
+        text.push_str(&format!("Closing balance {}\n", signed(balance, currency)));
+    }
+    text
+}
+
+fn describe(entry: &StatementEntry, members: &[User], currency: &str) -> String {
+    match &entry.kind {
+        EntryKind::Expense {
+            title, paid, share, ..
+        } => format!(
+            "{title} (paid {}, share {})",
+            Money::new(*paid, currency),
+            Money::new(*share, currency)
+        ),
+        EntryKind::SettlementPaid { to } => format!("You paid {}", member_name(members, to)),
+        EntryKind::SettlementReceived { from } => {
+            format!("{} paid you", member_name(members, from))
+        }
+    }
+}
+
+fn signed(amount: i64, currency: &str) -> String {
+    let sign = if amount > 0 { "+" } else { "" };
+    format!("{sign}{}", Money::new(amount, currency))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(date: &str, amount: i64) -> StatementEntry {
+        StatementEntry {
+            date: date.parse().unwrap(),
+            group: "flat".into(),
+            currency: "INR".into(),
+            kind: EntryKind::Expense {
+                title: "Pizza".into(),
+                category: None,
+                paid: amount.max(0),
+                share: (-amount).max(0),
+            },
+            amount,
+        }
+    }
+
+    fn format(entries: &[StatementEntry]) -> String {
+        format_statement(
+            entries,
+            &[],
+            "2026-02-01".parse().unwrap(),
+            "2026-03-31".parse().unwrap(),
+            2,
+        )
+    }
+
+    #[test]
+    fn entries_before_the_period_make_the_opening_balance() {
+        let text = format(&[
+            entry("2026-01-31", 500),
+            entry("2026-02-01", -200),
+            entry("2026-02-28", 100),
+            entry("2026-03-01", -50),
+        ]);
+        assert_eq!(
+            text,
+            "Statement from 2026-02-01 to 2026-03-31
+
+INR
+Opening balance +5.00
+
+February 2026
+  01 Feb Pizza (paid 0.00, share 2.00) [flat]
+    -2.00  balance +3.00
+  28 Feb Pizza (paid 1.00, share 0.00) [flat]
+    +1.00  balance +4.00
+  Month total -1.00
+
+March 2026
+  01 Mar Pizza (paid 0.00, share 0.50) [flat]
+    -0.50  balance +3.50
+  Month total -0.50
+Closing balance +3.50
+"
+        );
+    }
+
+    #[test]
+    fn quiet_periods_only_carry_the_balance() {
+        let text = format(&[entry("2026-01-10", -300), entry("2026-01-31", 100)]);
+        assert!(text.contains("No expenses or payments in the past 2 months."));
+        assert!(text.contains("Opening balance -2.00\nClosing balance -2.00\n"));
+        assert!(!text.contains("Month total"));
+    }
+
+    #[test]
+    fn every_currency_has_its_own_balance() {
+        let mut euros = entry("2026-02-10", 700);
+        euros.currency = "EUR".into();
+        let text = format(&[entry("2026-02-10", -300), euros]);
+        let eur = text.find("EUR\nOpening balance 0.00").unwrap();
+        let inr = text.find("INR\nOpening balance 0.00").unwrap();
+        assert!(eur < inr);
+        assert!(text.contains("Closing balance +7.00"));
+        assert!(text.contains("Closing balance -3.00"));
+    }
+}
@@ -0,0 +1,204 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use chrono::Datelike;
+use teloxide::{prelude::*, types::ParseMode, utils::html};
+
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{CategoryId, Datamodel, Expense, Money, DEFAULT_UTC_OFFSET},
+        statement::{group_entries, EntryKind, StatementEntry},
+        DataError,
+    },
+    parser::date::today,
+};
+
+use super::user_groups;
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Number of spending lines shown for the current month.
+const TOP_SPENDING: usize = 5;
+
+pub async fn show_summary(bot: Bot, msg: Message, backend: Arc<Backend>) -> HandlerResult {
+    let user_id = msg.chat.id.to_string();
+    let groups = user_groups(&backend, user_id.clone()).await;
+    if groups.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "You are not part of any group yet. Use /creategroup to create one.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let utc_offset = backend
+        .get_user(user_id.clone())
+        .await
+        .map(|user| user.utc_offset)
+        .unwrap_or(DEFAULT_UTC_OFFSET);
+    let today = today(utc_offset);
+    let expenses = backend
+        .get_user_expenses(user_id.clone())
+        .await
+        .unwrap_or_default();
+
+    let mut group_lines: Vec<String> = Vec::new();
+    let mut overall: BTreeMap<String, i64> = BTreeMap::new();
+    let mut month_entries: Vec<StatementEntry> = Vec::new();
+    let mut category_names: HashMap<CategoryId, String> = HashMap::new();
+    for group in &groups {
+        let balance = match backend.get_group_balances(group.group_id).await {
+            Ok(balances) => balances
+                .into_iter()
+                .find(|balance| balance.user_id == user_id)
+                .map(|balance| balance.amount)
+                .unwrap_or_default(),
+            Err(DataError::MissingCurrencyRate) => {
+                group_lines.push(format!(
+                    "{}: an exchange rate is missing",
+                    html::escape(&group.name)
+                ));
+                continue;
+            }
+            Err(_) => {
+                group_lines.push(format!(
+                    "{}: failed to compute balance",
+                    html::escape(&group.name)
+                ));
+                continue;
+            }
+        };
+        group_lines.push(format!(
+            "{}: {}",
+            html::escape(&group.name),
+            describe_balance(balance, &group.base_currency)
+        ));
+        *overall.entry(group.base_currency.clone()).or_insert(0) += balance;
+
+        let group_expenses: Vec<Expense> = expenses
+            .iter()
+            .filter(|expense| expense.group == group.group_id)
+            .filter(|expense| {
+                expense.incurred_on.year() == today.year()
+                    && expense.incurred_on.month() == today.month()
+            })
+            .cloned()
+            .collect();
+        if group_expenses.is_empty() {
+            continue;
+        }
+        for category in backend
+            .get_categories(group.group_id)
+            .await
+            .unwrap_or_default()
+        {
+            if let Some(id) = category.id {
+                category_names.insert(id, category.name);
+            }
+        }
+        let splits = backend
+            .get_expense_splits(group.group_id)
+            .await
+            .unwrap_or_default();
+        let rates = backend
+            .get_currency_rates(group.base_currency.clone())
+            .await
+            .unwrap_or_default();
+        if let Ok(entries) = group_entries(
+            &user_id,
+            group,
+            &group_expenses,
+            &splits,
+            &[],
+            &rates,
+            utc_offset,
+        ) {
+            month_entries.extend(entries);
+        }
+    }
+
+    let mut message = format!("<b>Your summary</b>\n\n{}\n", group_lines.join("\n"));
+    let overall: Vec<String> = overall
+        .iter()
+        .map(|(currency, balance)| describe_balance(*balance, currency))
+        .collect();
+    message.push_str(&format!("\n<b>Overall</b>\n{}\n", overall.join("\n")));
+
+    message.push_str(&format!(
+        "\n<b>Top spending in {}</b>\n",
+        today.format("%B %Y")
+    ));
+    let spending = top_spending(&month_entries, &category_names);
+    if spending.is_empty() {
+        message.push_str("Nothing spent yet this month.");
+    }
+    for ((currency, category), share) in spending {
+        message.push_str(&format!(
+            "{}: {} {}\n",
+            html::escape(&category),
+            Money::new(share, &currency),
+            currency
+        ));
+    }
+
+    bot.send_message(msg.chat.id, message)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+fn describe_balance(balance: i64, currency: &str) -> String {
+    let amount = Money::new(balance.abs(), currency);
+    match balance.signum() {
+        1 => format!("you are owed {amount} {currency}"),
+        -1 => format!("you owe {amount} {currency}"),
+        _ => format!("settled up in {currency}"),
+    }
+}
+
+/// Adds up the user's share of the expenses by category, largest first.
+fn top_spending(
+    entries: &[StatementEntry],
+    category_names: &HashMap<CategoryId, String>,
+) -> Vec<((String, String), i64)> {
+    let mut spending: BTreeMap<(String, String), i64> = BTreeMap::new();
+    for entry in entries {
+        if let EntryKind::Expense {
+            category, share, ..
+        } = &entry.kind
+        {
+            let category = category
+                .and_then(|id| category_names.get(&id).cloned())
+                .unwrap_or_else(|| "Uncategorized".to_string());
+            *spending
+                .entry((entry.currency.clone(), category))
+                .or_insert(0) += share;
+        }
+    }
+    let mut spending: Vec<((String, String), i64)> = spending
+        .into_iter()
+        .filter(|(_, share)| *share > 0)
+        .collect();
+    spending.sort_by_key(|(_, share)| std::cmp::Reverse(*share));
+    spending.truncate(TOP_SPENDING);
+    spending
+}
@@ -0,0 +1,145 @@
+/*
+This file is part of Entelur (https://github.com/ParadoxZero/entelur/).
+Copyright (c) 2024 Sidhin S Thomas.
+
+Entelur is free software: you can redistribute it and/or modify it under the terms of the
+GNU General Public License as published by the Free Software Foundation, either version 3
+of the License, or (at your option) any later version.
+
+Entelur is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY;
+without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.
+See the GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License along with Foobar.
+If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use teloxide::prelude::*;
+
+use crate::model::{
+    backend::Backend,
+    datamodel::{AuditEntry, Datamodel, User},
+    DataError,
+};
+
+use super::history::describe_entry;
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// How long after a change /undo can still revert it, set on the command line.
+#[derive(Debug, Clone, Copy)]
+pub struct UndoWindow(pub Duration);
+
+impl UndoWindow {
+    /// Whether the change recorded in `entry` is recent enough to be undone at `now`.
+    pub fn allows(&self, entry: &AuditEntry, now: DateTime<Utc>) -> bool {
+        now - entry.created_at <= self.0
+    }
+}
+
+pub async fn undo(
+    bot: Bot,
+    msg: Message,
+    backend: Arc<Backend>,
+    window: UndoWindow,
+) -> HandlerResult {
+    let user_id = msg.chat.id.to_string();
+    let entry = match backend.get_last_undoable_change(user_id.clone()).await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => {
+            bot.send_message(msg.chat.id, "You have no changes to undo.")
+                .await?;
+            return Ok(());
+        }
+        Err(e) => {
+            log::error!("Failed to find the last change to undo: {:?}", e);
+            bot.send_message(
+                msg.chat.id,
+                "Error loading your last change. Please try again later.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if !window.allows(&entry, Utc::now()) {
+        bot.send_message(
+            msg.chat.id,
+            format!(
+                "Your last change was made more than {} minutes ago and can't be undone anymore.",
+                window.0.num_minutes()
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let users: Vec<User> = match entry.group {
+        Some(group_id) => backend
+            .get_group_members(group_id)
+            .await
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let description = describe_entry(&entry, &users);
+    match backend.undo_change(entry, user_id).await {
+        Ok(_) => {
+            bot.send_message(msg.chat.id, format!("Undone: {}", description))
+                .await?;
+        }
+        Err(DataError::ConflictingChange) => {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Can't undo \"{}\", the expense was changed after that.",
+                    description
+                ),
+            )
+            .await?;
+        }
+        Err(DataError::QueryReturnedNoRows) => {
+            bot.send_message(
+                msg.chat.id,
+                "The expense doesn't exist anymore, the change can't be undone.",
+            )
+            .await?;
+        }
+        Err(e) => {
+            log::error!("Failed to undo change: {:?}", e);
+            bot.send_message(
+                msg.chat.id,
+                "Error undoing the change. Please try again later.",
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        audit,
+        datamodel::{Expense, Money},
+    };
+
+    #[test]
+    fn changes_older_than_the_window_cant_be_undone() {
+        let window = UndoWindow(Duration::minutes(10));
+        let expense = Expense::new(
+            "1".into(),
+            1,
+            Money::new(300, "INR"),
+            "Pizza".into(),
+            "".into(),
+        );
+        let entry = audit::added("1".into(), &expense);
+        let changed_at = entry.created_at;
+
+        assert!(window.allows(&entry, changed_at));
+        assert!(window.allows(&entry, changed_at + Duration::minutes(10)));
+        assert!(!window.allows(&entry, changed_at + Duration::minutes(11)));
+    }
+}
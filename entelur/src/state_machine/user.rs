@@ -26,9 +26,12 @@ use teloxide::{
     utils::command::{self, BotCommands},
 };
 
-use crate::model::{
-    datamodel::{Datamodel, User},
-    sqlite::backend::{self, SqliteBackend},
+use crate::{
+    model::{
+        backend::Backend,
+        datamodel::{Datamodel, User, DEFAULT_UTC_OFFSET},
+    },
+    parser::date::parse_utc_offset,
 };
 
 use super::state::{State, UserData};
@@ -49,6 +52,43 @@ pub fn user_callback_schema() -> UpdateHandler<Box<dyn std::error::Error + Send
         .branch(case![State::ConfirmUser { data }].endpoint(confirm_user))
 }
 
+pub async fn set_timezone(
+    bot: Bot,
+    msg: Message,
+    offset: String,
+    backend: Arc<Backend>,
+) -> HandlerResult {
+    let utc_offset = match parse_utc_offset(&offset) {
+        Ok(utc_offset) => utc_offset,
+        Err(e) => {
+            bot.send_message(msg.chat.id, e.to_string()).await?;
+            return Ok(());
+        }
+    };
+    match backend
+        .set_user_utc_offset(msg.chat.id.to_string(), utc_offset)
+        .await
+    {
+        Ok(_) => {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Timezone set to UTC{}{:02}:{:02}",
+                    if utc_offset < 0 { '-' } else { '+' },
+                    utc_offset.abs() / 60,
+                    utc_offset.abs() % 60
+                ),
+            )
+            .await?;
+        }
+        Err(_) => {
+            bot.send_message(msg.chat.id, "Please /register before setting your timezone.")
+                .await?;
+        }
+    }
+    Ok(())
+}
+
 async fn register_name(bot: Bot, msg: Message, dialogue: BotDialogue) -> HandlerResult {
     let Some(name) = msg.text() else {
         bot.send_message(msg.chat.id, "Please enter a name.")
@@ -87,7 +127,7 @@ async fn confirm_user(
     dialogue: BotDialogue,
     data: UserData,
     query: CallbackQuery,
-    backend: Arc<SqliteBackend>,
+    backend: Arc<Backend>,
 ) -> HandlerResult {
     if let Some(option) = query.data {
         match option.as_str() {
@@ -103,6 +143,7 @@ async fn confirm_user(
                     name: data.username,
                     user_id: dialogue.chat_id().to_string(),
                     username: data.name,
+                    utc_offset: DEFAULT_UTC_OFFSET,
                 };
                 match backend.as_ref().add_user(user).await {
                     Ok(_) => {
@@ -113,7 +154,7 @@ async fn confirm_user(
                     Err(e) => {
                         bot.send_message(
                             dialogue.chat_id(),
-                            format!("Failed to register. Please Try again."),
+                            "Failed to register. Please Try again.",
                         )
                         .await?;
                     }